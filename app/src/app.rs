@@ -1,14 +1,23 @@
 use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
+use std::time::Instant;
+use tracing::{debug, trace_span, warn};
+
+use crate::gamepad::{self, Gamepads};
+use crate::scaling::Letterbox;
+use crate::timing::{FixedTimestep, FrameStats};
 
 use sdl2::{
     EventPump,
 
+    controller::{Axis, Button as PadButton},
     event::{
         Event,
         WindowEvent
     },
     keyboard::Keycode,
+    mouse::{MouseButton, MouseWheelDirection},
     pixels::Color,
+    rect::Rect,
     render::{
         BlendMode,
         Canvas,
@@ -16,40 +25,119 @@ use sdl2::{
         TextureAccess,
         TextureCreator,
     },
+    ttf::Sdl2TtfContext,
     video::{
+        FullscreenType,
         Window,
         WindowContext
     },
 };
 
-static CANVAS_SCALE: f64 = 6.0;
+const DEFAULT_SCALE: f64 = 6.0;
+const DEFAULT_UPDATE_RATE: u32 = 60;
+/// Updates run in a single frame before the game gives up on catching up.
+const DEFAULT_MAX_UPDATES_PER_FRAME: u32 = 5;
+const BACKGROUND_COLOR: Color = Color::RGB(85, 117, 139);
+/// Color of the bars around the picture when it does not fill the window.
+const LETTERBOX_COLOR: Color = Color::RGB(0, 0, 0);
+
+pub struct WindowSettings {
+    pub title: String,
+    pub width: u32,
+    pub height: u32,
+    /// Window pixels per game pixel.
+    pub scale: f64,
+    /// Only scale by whole numbers, leaving bars around the picture.
+    pub pixel_perfect: bool,
+    pub fullscreen: bool,
+    /// Calls of `App::update` per second, independent of the frame rate.
+    pub update_rate: u32,
+    pub max_updates_per_frame: u32,
+}
 
 struct RenderTarget {
     texture: Texture,
+    letterbox: Letterbox,
     scale: f64,
+    pixel_perfect: bool,
     needs_update: bool,
 }
 
 pub struct Context<'a> {
     pub events: EventPump,
     pub texture_creator: &'a TextureCreator<WindowContext>,
+    /// Loads `font::TtfFont`s.
+    pub ttf: &'static Sdl2TtfContext,
+    letterbox: Letterbox,
+    window_size: (u32, u32),
+    fullscreen: bool,
+    /// Changes asked for by the app, applied before the next frame.
+    requested_scale: Option<f64>,
+    requested_pixel_perfect: Option<bool>,
+    requested_fullscreen: Option<bool>,
+    requested_quit: bool,
+    dt: f64,
+    stats: FrameStats,
+    gamepads: Gamepads,
 }
 
 pub trait App {
     fn init(&mut self, _ctx: &mut Context) {}
     fn update(&mut self, _ctx: &mut Context) {}
-    fn draw(&mut self, _ctx: &mut Context, _canvas: &mut Canvas<Window>) {}
-    fn key_pressed(&mut self, _ctx: &mut Context, _keycode: Keycode) {}
+    /// Called once per frame, `alpha` is how far the frame is between the last update and
+    /// the next one, from 0 to 1. Use it to interpolate positions between updates.
+    fn draw(&mut self, _ctx: &mut Context, _canvas: &mut Canvas<Window>, _alpha: f64) {}
+    /// `repeat` is set for the presses the OS generates while a key is held, useful for text
+    /// editing but not for actions.
+    fn key_pressed(&mut self, _ctx: &mut Context, _keycode: Keycode, _repeat: bool) {}
     fn key_released(&mut self, _ctx: &mut Context, _keycode: Keycode) {}
+    /// Mouse positions are in render target coordinates, like `Context::get_mouse_pos`.
+    fn mouse_pressed(&mut self, _ctx: &mut Context, _button: MouseButton, _x: f64, _y: f64) {}
+    fn mouse_released(&mut self, _ctx: &mut Context, _button: MouseButton, _x: f64, _y: f64) {}
+    fn mouse_moved(&mut self, _ctx: &mut Context, _x: f64, _y: f64) {}
+    /// Positive `y` scrolls away from the user, positive `x` to the right.
+    fn mouse_wheel(&mut self, _ctx: &mut Context, _x: i32, _y: i32) {}
+    fn text_input(&mut self, _ctx: &mut Context, _text: &str) {}
+    /// Keys released while the window is not focused are never reported, forget held keys here.
+    fn focus_lost(&mut self, _ctx: &mut Context) {}
+    fn focus_gained(&mut self, _ctx: &mut Context) {}
+    /// Gamepads are told apart by `id`, which stays the same until the gamepad is unplugged.
+    fn gamepad_added(&mut self, _ctx: &mut Context, _id: u32) {}
+    fn gamepad_removed(&mut self, _ctx: &mut Context, _id: u32) {}
+    fn gamepad_pressed(&mut self, _ctx: &mut Context, _id: u32, _button: PadButton) {}
+    fn gamepad_released(&mut self, _ctx: &mut Context, _id: u32, _button: PadButton) {}
+    /// Sticks go from -1 to 1, down and right being positive, triggers from 0 to 1.
+    fn gamepad_axis(&mut self, _ctx: &mut Context, _id: u32, _axis: Axis, _value: f64) {}
+    fn quit(&mut self, _ctx: &mut Context) {}
+}
+
+impl Default for WindowSettings {
+    fn default() -> Self {
+        Self {
+            title: "ZED".into(),
+            width: 1000,
+            height: 1000,
+            scale: DEFAULT_SCALE,
+            pixel_perfect: true,
+            fullscreen: false,
+            update_rate: DEFAULT_UPDATE_RATE,
+            max_updates_per_frame: DEFAULT_MAX_UPDATES_PER_FRAME,
+        }
+    }
 }
 
 impl RenderTarget {
-    pub fn new(texture_creator: &TextureCreator<WindowContext>, window: &Canvas<Window>, scale: f64)
+    pub fn new(texture_creator: &TextureCreator<WindowContext>, canvas: &Canvas<Window>, scale: f64, pixel_perfect: bool)
         -> Self
     {
+        let (width, height) = canvas.output_size().unwrap();
+        let letterbox = Letterbox::fit(width, height, scale, pixel_perfect);
+
         Self {
-            texture: Self::create(texture_creator, window, scale),
+            texture: Self::create(texture_creator, &letterbox),
+            letterbox,
             scale,
+            pixel_perfect,
             needs_update: false,
         }
     }
@@ -59,35 +147,116 @@ impl RenderTarget {
         self.needs_update = true;
     }
 
-    fn update(&mut self, texture_creator: &TextureCreator<WindowContext>, window: &Canvas<Window>) {
-        if self.needs_update {
-            self.texture = Self::create(texture_creator, window, self.scale);
-        }
+    fn set_pixel_perfect(&mut self, pixel_perfect: bool) {
+        self.pixel_perfect = pixel_perfect;
+        self.needs_update = true;
     }
 
-    fn create(texture_creator: &TextureCreator<WindowContext>, canvas: &Canvas<Window>, scale: f64)
-        -> Texture
-    {
+    /// Fits the target to the window again after the window or the scale changed,
+    /// the texture is only recreated when its size changes.
+    fn update(&mut self, texture_creator: &TextureCreator<WindowContext>, canvas: &Canvas<Window>) {
+        if !self.needs_update {
+            return;
+        }
+        self.needs_update = false;
+
         let (width, height) = canvas.output_size().unwrap();
+        let letterbox = Letterbox::fit(width, height, self.scale, self.pixel_perfect);
+        if (letterbox.target_width, letterbox.target_height) != (self.letterbox.target_width, self.letterbox.target_height) {
+            self.texture = Self::create(texture_creator, &letterbox);
+            self.texture.set_blend_mode(BlendMode::None);
+            debug!(width = letterbox.target_width, height = letterbox.target_height, "Render target resized");
+        }
+        self.letterbox = letterbox;
+    }
 
+    fn create(texture_creator: &TextureCreator<WindowContext>, letterbox: &Letterbox) -> Texture {
         texture_creator.create_texture(
             texture_creator.default_pixel_format(),
             TextureAccess::Target,
-            (width as f64 / scale) as u32,
-             (height as f64 / scale) as u32
+            letterbox.target_width,
+            letterbox.target_height,
         ).unwrap()
     }
+
+    fn destination(&self) -> Rect {
+        Rect::new(self.letterbox.x, self.letterbox.y, self.letterbox.width, self.letterbox.height)
+    }
 }
 
 impl<'a> Context<'a> {
+    /// Size of the render target the app draws on, in game pixels.
+    pub fn screen_size(&self) -> (u32, u32) {
+        (self.letterbox.target_width, self.letterbox.target_height)
+    }
+
+    pub fn window_size(&self) -> (u32, u32) {
+        self.window_size
+    }
+
+    pub fn is_fullscreen(&self) -> bool {
+        self.fullscreen
+    }
+
+    pub fn set_fullscreen(&mut self, fullscreen: bool) {
+        self.requested_fullscreen = Some(fullscreen);
+    }
+
+    /// Changes the number of window pixels per game pixel.
+    pub fn set_scale(&mut self, scale: f64) {
+        self.requested_scale = Some(scale);
+    }
+
+    /// Scales the game by whole numbers only, leaving black bars around it.
+    pub fn set_pixel_perfect(&mut self, pixel_perfect: bool) {
+        self.requested_pixel_perfect = Some(pixel_perfect);
+    }
+
+    /// Closes the window after this frame, `App::quit` is still called.
+    pub fn quit(&mut self) {
+        self.requested_quit = true;
+    }
+
+    /// Seconds simulated by a call of `App::update`.
+    pub fn dt(&self) -> f64 {
+        self.dt
+    }
+
+    /// Seconds since the previous frame.
+    pub fn frame_time(&self) -> f64 {
+        self.stats.frame_time()
+    }
+
+    /// Frames drawn so far.
+    pub fn frame_count(&self) -> u64 {
+        self.stats.frame_count()
+    }
+
+    pub fn fps(&self) -> f64 {
+        self.stats.fps()
+    }
+
+    /// Instance ids and names of the connected gamepads.
+    pub fn gamepads(&self) -> Vec<(u32, String)> {
+        self.gamepads.connected()
+    }
+
+    /// Mouse position on the render target, convert it with a `Camera` to get world coordinates.
     pub fn get_mouse_pos(&mut self) -> (f64, f64) {
         let mouse = self.events.mouse_state();
 
-        (mouse.x() as f64 / CANVAS_SCALE, mouse.y() as f64 / CANVAS_SCALE)
+        self.letterbox.window_to_target(mouse.x(), mouse.y())
+    }
+}
+
+fn set_fullscreen(canvas: &mut Canvas<Window>, fullscreen: bool) {
+    let mode = if fullscreen { FullscreenType::Desktop } else { FullscreenType::Off };
+    if let Err(e) = canvas.window_mut().set_fullscreen(mode) {
+        warn!(error = %e, "Cannot change fullscreen mode");
     }
 }
 
-pub fn run<A, F>(f: F, should_run: Arc<AtomicBool>)
+pub fn run<A, F>(f: F, settings: WindowSettings, should_run: Arc<AtomicBool>)
     where
         A: App,
         F: FnOnce(&mut Context) -> A
@@ -96,8 +265,9 @@ pub fn run<A, F>(f: F, should_run: Arc<AtomicBool>)
 
 
     let video = sdl2_ctx.video().unwrap();
-    let window = video.window("ZED", 1000, 1000)
+    let window = video.window(&settings.title, settings.width, settings.height)
         .position_centered()
+        .resizable()
         .build()
         .unwrap();
 
@@ -105,64 +275,181 @@ pub fn run<A, F>(f: F, should_run: Arc<AtomicBool>)
         .present_vsync()
         .build()
         .unwrap();
+    if settings.fullscreen {
+        set_fullscreen(&mut canvas, true);
+    }
 
-    let mut events = sdl2_ctx.event_pump().unwrap();
+    let events = sdl2_ctx.event_pump().unwrap();
+    let gamepads = Gamepads::new(sdl2_ctx.game_controller().unwrap());
     let texture_creator = canvas.texture_creator();
+    // Fonts borrow the context for as long as they are around, which can be the whole run.
+    let ttf: &'static Sdl2TtfContext = Box::leak(Box::new(sdl2::ttf::init().unwrap()));
+
+    sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", "0");
+    let mut render_target = RenderTarget::new(&texture_creator, &canvas, settings.scale, settings.pixel_perfect);
+    let mut timestep = FixedTimestep::new(settings.update_rate, settings.max_updates_per_frame);
 
     let mut ctx = Context {
         events,
         texture_creator: &texture_creator,
+        ttf,
+        letterbox: render_target.letterbox,
+        window_size: canvas.window().size(),
+        fullscreen: settings.fullscreen,
+        requested_scale: None,
+        requested_pixel_perfect: None,
+        requested_fullscreen: None,
+        requested_quit: false,
+        dt: timestep.step(),
+        stats: FrameStats::new(),
+        gamepads,
     };
 
     let mut app = f(&mut ctx);
 
-    sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", "0");
-    let mut render_target = RenderTarget::new(&ctx.texture_creator, &canvas, CANVAS_SCALE);
-
     canvas.set_blend_mode(BlendMode::None);
     render_target.texture.set_blend_mode(BlendMode::None);
 
 
     app.init(&mut ctx);
 
+    debug!(width = settings.width, height = settings.height, scale = settings.scale, "Window created");
+
+    let mut last_frame = Instant::now();
     'main: while should_run.load(Ordering::Relaxed) {
+        let frame = trace_span!("frame").entered();
+
+        let now = Instant::now();
+        let frame_time = now.duration_since(last_frame).as_secs_f64();
+        last_frame = now;
+        ctx.stats.record(frame_time);
+
         'events: loop {
             let ev = ctx.events.poll_event();
             match ev {
                 None => break 'events,
                 Some(ev) => {
                     match ev {
-                        Event::KeyDown { keycode: Some(Keycode::Escape), .. }
+                        Event::Quit { .. }
                         | Event::Window { win_event: WindowEvent::Close, .. } => {
                             should_run.store(false, Ordering::Relaxed);
                             break 'main;
                         },
 
-                        Event::KeyDown { keycode: Some(keycode), .. } => {
-                            app.key_pressed(&mut ctx, keycode);
+                        Event::Window { win_event: WindowEvent::Resized(..), .. }
+                        | Event::Window { win_event: WindowEvent::SizeChanged(..), .. } => {
+                            render_target.needs_update = true;
+                        },
+                        Event::KeyDown { keycode: Some(Keycode::F11), repeat: false, .. } => {
+                            ctx.requested_fullscreen = Some(!ctx.fullscreen);
+                        },
+
+                        Event::KeyDown { keycode: Some(keycode), repeat, .. } => {
+                            app.key_pressed(&mut ctx, keycode, repeat);
                         },
                         Event::KeyUp { keycode: Some(keycode), .. } => {
                             app.key_released(&mut ctx, keycode);
                         }
+                        Event::TextInput { text, .. } => {
+                            app.text_input(&mut ctx, &text);
+                        }
+
+                        Event::MouseButtonDown { mouse_btn, x, y, .. } => {
+                            let (x, y) = ctx.letterbox.window_to_target(x, y);
+                            app.mouse_pressed(&mut ctx, mouse_btn, x, y);
+                        },
+                        Event::MouseButtonUp { mouse_btn, x, y, .. } => {
+                            let (x, y) = ctx.letterbox.window_to_target(x, y);
+                            app.mouse_released(&mut ctx, mouse_btn, x, y);
+                        },
+                        Event::MouseMotion { x, y, .. } => {
+                            let (x, y) = ctx.letterbox.window_to_target(x, y);
+                            app.mouse_moved(&mut ctx, x, y);
+                        },
+                        Event::MouseWheel { x, y, direction, .. } => {
+                            let (x, y) = match direction {
+                                MouseWheelDirection::Flipped => (-x, -y),
+                                _ => (x, y),
+                            };
+                            app.mouse_wheel(&mut ctx, x, y);
+                        },
+
+                        Event::Window { win_event: WindowEvent::FocusLost, .. } => {
+                            app.focus_lost(&mut ctx);
+                        },
+                        Event::Window { win_event: WindowEvent::FocusGained, .. } => {
+                            app.focus_gained(&mut ctx);
+                        },
+
+                        Event::ControllerDeviceAdded { which, .. } => {
+                            if let Some(id) = ctx.gamepads.add(which) {
+                                app.gamepad_added(&mut ctx, id);
+                            }
+                        },
+                        Event::ControllerDeviceRemoved { which, .. } => {
+                            if ctx.gamepads.remove(which) {
+                                app.gamepad_removed(&mut ctx, which);
+                            }
+                        },
+                        Event::ControllerButtonDown { which, button, .. } => {
+                            app.gamepad_pressed(&mut ctx, which, button);
+                        },
+                        Event::ControllerButtonUp { which, button, .. } => {
+                            app.gamepad_released(&mut ctx, which, button);
+                        },
+                        Event::ControllerAxisMotion { which, axis, value, .. } => {
+                            app.gamepad_axis(&mut ctx, which, axis, gamepad::normalize_axis(value));
+                        },
                         _ => ()
                     }
                 }
             }
         }
 
-        app.update(&mut ctx);
+        if ctx.requested_quit {
+            should_run.store(false, Ordering::Relaxed);
+            break 'main;
+        }
+        if let Some(fullscreen) = ctx.requested_fullscreen.take() {
+            set_fullscreen(&mut canvas, fullscreen);
+            ctx.fullscreen = fullscreen;
+            render_target.needs_update = true;
+        }
+        if let Some(scale) = ctx.requested_scale.take() {
+            render_target.set_scale(scale);
+        }
+        if let Some(pixel_perfect) = ctx.requested_pixel_perfect.take() {
+            render_target.set_pixel_perfect(pixel_perfect);
+        }
+        render_target.update(&texture_creator, &canvas);
+        ctx.letterbox = render_target.letterbox;
+        ctx.window_size = canvas.window().size();
+
+        let updates = timestep.advance(frame_time);
+        trace_span!("update", updates).in_scope(|| {
+            for _ in 0..updates {
+                app.update(&mut ctx);
+            }
+        });
+        let alpha = timestep.alpha();
 
-        canvas.set_draw_color(Color::RGB(85, 117, 139));
+        canvas.set_draw_color(LETTERBOX_COLOR);
         canvas.clear();
 
-        canvas.with_texture_canvas(&mut render_target.texture,|canvas| {
-            canvas.clear();
-            app.draw(&mut ctx, canvas);
+        trace_span!("draw").in_scope(|| {
+            canvas.with_texture_canvas(&mut render_target.texture,|canvas| {
+                canvas.set_draw_color(BACKGROUND_COLOR);
+                canvas.clear();
+                app.draw(&mut ctx, canvas, alpha);
+            });
         });
         canvas.set_draw_color(Color::RGB(255, 255, 255));
-        canvas.copy(&render_target.texture, None, None);
+        canvas.copy(&render_target.texture, None, render_target.destination());
+
+        // Presenting waits for vsync, keep it out of the frame timing.
+        drop(frame);
         canvas.present();
     }
 
-
+    app.quit(&mut ctx);
 }
@@ -0,0 +1,459 @@
+use serde::Deserialize;
+
+use sdl2::{
+    image::LoadTexture,
+    pixels::PixelFormatEnum,
+    render::{
+        Texture,
+        TextureCreator,
+    },
+    video::WindowContext,
+};
+
+use tracing::{info, warn};
+
+use crate::{
+    font::BitmapFont,
+    sprite::SpriteSheet,
+};
+
+use std::{
+    collections::HashMap,
+    fmt,
+    marker::PhantomData,
+    path::Path,
+    sync::{Arc, Weak},
+    time::SystemTime,
+};
+
+pub const DEFAULT_MANIFEST: &str = "static/assets.toml";
+
+/// Names of the assets a game can load, mapped to the files they are loaded from.
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Manifest {
+    pub textures: HashMap<String, String>,
+    pub fonts: HashMap<String, String>,
+    pub sounds: HashMap<String, String>,
+    pub sprites: HashMap<String, String>,
+}
+
+impl Manifest {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let manifest = std::fs::read_to_string(path)
+            .map_err(|e| format!("cannot read {}: {}", path, e))?;
+        toml::from_str(&manifest)
+            .map_err(|e| format!("cannot parse {}: {}", path, e))
+    }
+}
+
+/// Something a `Store` can load from a file.
+pub trait Asset: Sized {
+    /// Used in log messages.
+    const KIND: &'static str;
+
+    fn load(texture_creator: &TextureCreator<WindowContext>, path: &str) -> Result<Self, String>;
+    /// Stands in for assets that are not loaded yet or failed to load.
+    fn placeholder(texture_creator: &TextureCreator<WindowContext>) -> Self;
+}
+
+/// Reference to an asset in a `Store`.
+///
+/// The asset stays loaded as long as any handle to it is alive.
+pub struct Handle<T> {
+    id: usize,
+    refs: Arc<()>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    pub fn id(&self) -> usize {
+        self.id
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            id: self.id,
+            refs: self.refs.clone(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Handle({})", self.id)
+    }
+}
+
+struct Slot<T> {
+    name: String,
+    /// `None` when the name is not in the manifest.
+    path: Option<String>,
+    /// `None` until loaded, or when loading failed.
+    asset: Option<T>,
+    attempted: bool,
+    modified: Option<SystemTime>,
+    refs: Weak<()>,
+}
+
+/// Assets of one type, loaded by name.
+///
+/// Handles are given out right away and the files are loaded by `load_pending`,
+/// until then, or if loading fails, the placeholder is used instead.
+pub struct Store<T> {
+    paths: HashMap<String, String>,
+    slots: Vec<Option<Slot<T>>>,
+    ids: HashMap<String, usize>,
+    placeholder: T,
+}
+
+impl<T> Store<T> {
+    pub fn new(paths: HashMap<String, String>, placeholder: T) -> Self {
+        Self {
+            paths,
+            slots: Vec::new(),
+            ids: HashMap::new(),
+            placeholder,
+        }
+    }
+
+    /// Returns a handle to the asset called `name` in the manifest.
+    pub fn handle(&mut self, name: &str) -> Handle<T> {
+        if let Some(&id) = self.ids.get(name) {
+            let slot = self.slots[id].as_mut().unwrap();
+            let refs = slot.refs.upgrade().unwrap_or_else(|| {
+                // Not collected yet, the asset can be used again.
+                let refs = Arc::new(());
+                slot.refs = Arc::downgrade(&refs);
+                refs
+            });
+            return Handle { id, refs, marker: PhantomData };
+        }
+
+        let path = self.paths.get(name).cloned();
+        if path.is_none() {
+            warn!(%name, "Asset is not in the manifest");
+        }
+
+        let refs = Arc::new(());
+        let slot = Slot {
+            name: name.into(),
+            path,
+            asset: None,
+            attempted: false,
+            modified: None,
+            refs: Arc::downgrade(&refs),
+        };
+
+        let id = match self.slots.iter().position(Option::is_none) {
+            Some(id) => {
+                self.slots[id] = Some(slot);
+                id
+            }
+            None => {
+                self.slots.push(Some(slot));
+                self.slots.len() - 1
+            }
+        };
+        self.ids.insert(name.into(), id);
+
+        Handle { id, refs, marker: PhantomData }
+    }
+
+    /// Returns a handle to the asset loaded from `path`, which is also its name,
+    /// for files the manifest cannot list, like the tilesets of downloaded maps.
+    pub fn file(&mut self, path: &str) -> Handle<T> {
+        self.paths.entry(path.into()).or_insert_with(|| path.into());
+        self.handle(path)
+    }
+
+    pub fn get(&self, handle: &Handle<T>) -> &T {
+        self.slots.get(handle.id)
+            .and_then(Option::as_ref)
+            .and_then(|slot| slot.asset.as_ref())
+            .unwrap_or(&self.placeholder)
+    }
+
+    pub fn get_mut(&mut self, handle: &Handle<T>) -> &mut T {
+        let placeholder = &mut self.placeholder;
+        self.slots.get_mut(handle.id)
+            .and_then(Option::as_mut)
+            .and_then(|slot| slot.asset.as_mut())
+            .unwrap_or(placeholder)
+    }
+
+    pub fn is_loaded(&self, handle: &Handle<T>) -> bool {
+        self.slots.get(handle.id)
+            .and_then(Option::as_ref)
+            .map_or(false, |slot| slot.asset.is_some())
+    }
+
+    /// Number of assets with live handles or waiting to be collected.
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Unloads assets that have no handles left, returns how many were unloaded.
+    pub fn collect(&mut self) -> usize {
+        let mut count = 0;
+        for entry in &mut self.slots {
+            if entry.as_ref().map_or(false, |slot| slot.refs.strong_count() == 0) {
+                let slot = entry.take().unwrap();
+                self.ids.remove(&slot.name);
+                count += 1;
+            }
+        }
+        count
+    }
+}
+
+impl<T: Asset> Store<T> {
+    /// Loads the assets requested since the last call.
+    pub fn load_pending(&mut self, texture_creator: &TextureCreator<WindowContext>) {
+        for slot in self.slots.iter_mut().flatten().filter(|slot| !slot.attempted) {
+            slot.attempted = true;
+
+            let path = match &slot.path {
+                Some(path) => path,
+                None => continue,
+            };
+
+            slot.modified = modified_time(Path::new(path));
+            match T::load(texture_creator, path) {
+                Ok(asset) => slot.asset = Some(asset),
+                Err(e) => warn!(kind = T::KIND, name = %slot.name, error = %e, "Cannot load asset"),
+            }
+        }
+    }
+
+    /// Loads again the assets whose files changed since they were loaded, returns how many were reloaded.
+    ///
+    /// If loading fails the previous version is kept.
+    pub fn reload_changed(&mut self, texture_creator: &TextureCreator<WindowContext>) -> usize {
+        let mut count = 0;
+        for slot in self.slots.iter_mut().flatten().filter(|slot| slot.attempted) {
+            let path = match &slot.path {
+                Some(path) => path,
+                None => continue,
+            };
+
+            let modified = modified_time(Path::new(path));
+            if modified == slot.modified {
+                continue;
+            }
+            slot.modified = modified;
+
+            match T::load(texture_creator, path) {
+                Ok(asset) => {
+                    info!(kind = T::KIND, name = %slot.name, "Reloaded asset");
+                    slot.asset = Some(asset);
+                    count += 1;
+                }
+                Err(e) => warn!(kind = T::KIND, name = %slot.name, error = %e, "Cannot reload asset, keeping the previous version"),
+            }
+        }
+        count
+    }
+}
+
+/// Raw contents of a sound file.
+pub struct Sound {
+    pub data: Vec<u8>,
+}
+
+impl Asset for Sound {
+    const KIND: &'static str = "sound";
+
+    fn load(_: &TextureCreator<WindowContext>, path: &str) -> Result<Self, String> {
+        let data = std::fs::read(path)
+            .map_err(|e| format!("cannot read {}: {}", path, e))?;
+        Ok(Self { data })
+    }
+
+    fn placeholder(_: &TextureCreator<WindowContext>) -> Self {
+        Self { data: Vec::new() }
+    }
+}
+
+impl Asset for Texture {
+    const KIND: &'static str = "texture";
+
+    fn load(texture_creator: &TextureCreator<WindowContext>, path: &str) -> Result<Self, String> {
+        texture_creator.load_texture(path)
+    }
+
+    /// Magenta and black checkerboard, hard to miss.
+    fn placeholder(texture_creator: &TextureCreator<WindowContext>) -> Self {
+        const SIZE: u32 = 8;
+
+        let mut pixels = Vec::with_capacity((SIZE * SIZE * 3) as usize);
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                let color = if (x / 2 + y / 2) % 2 == 0 { [255, 0, 255] } else { [0, 0, 0] };
+                pixels.extend_from_slice(&color);
+            }
+        }
+
+        let mut texture = texture_creator.create_texture_static(PixelFormatEnum::RGB24, SIZE, SIZE)
+            .expect("cannot create the placeholder texture");
+        texture.update(None, &pixels, (SIZE * 3) as usize)
+            .expect("cannot fill the placeholder texture");
+        texture
+    }
+}
+
+impl Asset for BitmapFont {
+    const KIND: &'static str = "font";
+
+    fn load(texture_creator: &TextureCreator<WindowContext>, path: &str) -> Result<Self, String> {
+        BitmapFont::load(texture_creator, path)
+    }
+
+    fn placeholder(texture_creator: &TextureCreator<WindowContext>) -> Self {
+        BitmapFont::placeholder(texture_creator)
+    }
+}
+
+/// Every asset of a game, listed in a manifest.
+pub struct Assets {
+    pub textures: Store<Texture>,
+    pub fonts: Store<BitmapFont>,
+    pub sounds: Store<Sound>,
+    pub sprites: Store<SpriteSheet>,
+}
+
+impl Assets {
+    pub fn new(texture_creator: &TextureCreator<WindowContext>, manifest: Manifest) -> Self {
+        Self {
+            textures: Store::new(manifest.textures, Texture::placeholder(texture_creator)),
+            fonts: Store::new(manifest.fonts, BitmapFont::placeholder(texture_creator)),
+            sounds: Store::new(manifest.sounds, Sound::placeholder(texture_creator)),
+            sprites: Store::new(manifest.sprites, SpriteSheet::placeholder(texture_creator)),
+        }
+    }
+
+    /// Reads the manifest at `path`, a broken manifest leaves every asset as a placeholder.
+    pub fn load(texture_creator: &TextureCreator<WindowContext>, path: &str) -> Self {
+        let manifest = Manifest::load(path).unwrap_or_else(|e| {
+            warn!(error = %e, "Cannot load the asset manifest");
+            Manifest::default()
+        });
+        Self::new(texture_creator, manifest)
+    }
+
+    pub fn texture(&mut self, name: &str) -> Handle<Texture> {
+        self.textures.handle(name)
+    }
+
+    pub fn font(&mut self, name: &str) -> Handle<BitmapFont> {
+        self.fonts.handle(name)
+    }
+
+    pub fn sound(&mut self, name: &str) -> Handle<Sound> {
+        self.sounds.handle(name)
+    }
+
+    pub fn sprite(&mut self, name: &str) -> Handle<SpriteSheet> {
+        self.sprites.handle(name)
+    }
+
+    pub fn load_pending(&mut self, texture_creator: &TextureCreator<WindowContext>) {
+        self.textures.load_pending(texture_creator);
+        self.fonts.load_pending(texture_creator);
+        self.sounds.load_pending(texture_creator);
+        self.sprites.load_pending(texture_creator);
+    }
+
+    pub fn collect(&mut self) -> usize {
+        self.textures.collect() + self.fonts.collect() + self.sounds.collect() + self.sprites.collect()
+    }
+
+    /// Meant to be called about once a second in development builds.
+    pub fn reload_changed(&mut self, texture_creator: &TextureCreator<WindowContext>) -> usize {
+        self.textures.reload_changed(texture_creator)
+            + self.fonts.reload_changed(texture_creator)
+            + self.sounds.reload_changed(texture_creator)
+            + self.sprites.reload_changed(texture_creator)
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store() -> Store<u32> {
+        let mut paths = HashMap::new();
+        paths.insert("guy".to_string(), "static/guy.png".to_string());
+        Store::new(paths, 0)
+    }
+
+    #[test]
+    fn test_handles() {
+        let mut store = store();
+
+        let guy = store.handle("guy");
+        assert_eq!(store.handle("guy"), guy);
+        assert_eq!(*store.get(&guy), 0);
+
+        let missing = store.handle("missing");
+        assert_ne!(missing, guy);
+        assert_eq!(store.len(), 2);
+
+        drop(missing);
+        assert_eq!(store.collect(), 1);
+        assert_eq!(store.len(), 1);
+
+        // The freed slot is reused.
+        let other = store.handle("other");
+        assert_eq!(other.id(), 1);
+        drop(other);
+        assert_eq!(store.collect(), 1);
+
+        let copy = guy.clone();
+        drop(guy);
+        assert_eq!(store.collect(), 0);
+        assert_eq!(store.len(), 1);
+        drop(copy);
+        assert_eq!(store.collect(), 1);
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    fn test_file() {
+        let mut store = store();
+
+        let tiles = store.file("maps/tiles.png");
+        assert_eq!(store.file("maps/tiles.png"), tiles);
+        assert_eq!(store.slots[tiles.id()].as_ref().unwrap().path.as_deref(), Some("maps/tiles.png"));
+    }
+
+    #[test]
+    fn test_manifest() {
+        let manifest: Manifest = toml::from_str(r#"
+            [textures]
+            guy = "static/guy.png"
+        "#).unwrap();
+
+        assert_eq!(manifest.textures["guy"], "static/guy.png");
+        assert!(manifest.fonts.is_empty());
+        assert!(manifest.sounds.is_empty());
+        assert!(manifest.sprites.is_empty());
+    }
+}
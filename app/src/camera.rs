@@ -0,0 +1,232 @@
+/// 2D camera mapping world coordinates to the render target.
+///
+/// The camera follows a target with a deadzone and exponential smoothing, stays inside its
+/// bounds and can shake. Drawing goes through `world_to_screen`, input through `screen_to_world`.
+pub struct Camera {
+    /// Center of the view in world coordinates, without shake.
+    x: f64,
+    y: f64,
+    viewport_width: f64,
+    viewport_height: f64,
+    /// Half size of the area around the center the target can move in without moving the camera.
+    pub deadzone: (f64, f64),
+    /// Time in seconds the camera takes to cover ~63% of the distance to where it should be,
+    /// 0 follows instantly.
+    pub smoothing: f64,
+    /// World area that is shown, `(x, y, width, height)`.
+    bounds: Option<(f64, f64, f64, f64)>,
+    shake: Shake,
+}
+
+struct Shake {
+    amplitude: f64,
+    duration: f64,
+    remaining: f64,
+    offset: (f64, f64),
+    seed: u32,
+}
+
+impl Camera {
+    pub fn new(viewport_width: f64, viewport_height: f64) -> Self {
+        Self {
+            x: viewport_width / 2.0,
+            y: viewport_height / 2.0,
+            viewport_width,
+            viewport_height,
+            deadzone: (0.0, 0.0),
+            smoothing: 0.0,
+            bounds: None,
+            shake: Shake {
+                amplitude: 0.0,
+                duration: 0.0,
+                remaining: 0.0,
+                offset: (0.0, 0.0),
+                seed: 0x2545_f491,
+            },
+        }
+    }
+
+    pub fn center(&self) -> (f64, f64) {
+        (self.x, self.y)
+    }
+
+    pub fn set_viewport(&mut self, width: f64, height: f64) {
+        self.viewport_width = width;
+        self.viewport_height = height;
+        self.clamp();
+    }
+
+    /// Limits the view to the given world area, maps smaller than the view are centered.
+    pub fn set_bounds(&mut self, bounds: Option<(f64, f64, f64, f64)>) {
+        self.bounds = bounds;
+        self.clamp();
+    }
+
+    /// Centers the camera on `(x, y)` right away, e.g. after spawning.
+    pub fn snap_to(&mut self, x: f64, y: f64) {
+        self.x = x;
+        self.y = y;
+        self.clamp();
+    }
+
+    /// Moves towards the target `(x, y)` and advances the shake by `dt` seconds.
+    pub fn follow(&mut self, x: f64, y: f64, dt: f64) {
+        let (deadzone_x, deadzone_y) = self.deadzone;
+        // Only the part of the target outside the deadzone moves the camera.
+        let goal_x = self.x + x - x.max(self.x - deadzone_x).min(self.x + deadzone_x);
+        let goal_y = self.y + y - y.max(self.y - deadzone_y).min(self.y + deadzone_y);
+
+        let t = if self.smoothing > 0.0 { 1.0 - (-dt / self.smoothing).exp() } else { 1.0 };
+        self.x += (goal_x - self.x) * t;
+        self.y += (goal_y - self.y) * t;
+        self.clamp();
+
+        self.shake.update(dt);
+    }
+
+    /// Shakes the view by up to `amplitude` pixels, fading out over `duration` seconds.
+    /// A stronger shake replaces a weaker one.
+    pub fn shake(&mut self, amplitude: f64, duration: f64) {
+        if amplitude >= self.shake.current_amplitude() {
+            self.shake.amplitude = amplitude;
+            self.shake.duration = duration;
+            self.shake.remaining = duration;
+        }
+    }
+
+    /// World coordinates of the top left corner of the view, rounded to whole pixels so
+    /// sprites do not jitter relative to each other.
+    pub fn origin(&self) -> (f64, f64) {
+        (
+            (self.x - self.viewport_width / 2.0 + self.shake.offset.0).round(),
+            (self.y - self.viewport_height / 2.0 + self.shake.offset.1).round(),
+        )
+    }
+
+    pub fn world_to_screen(&self, x: f64, y: f64) -> (f64, f64) {
+        let (origin_x, origin_y) = self.origin();
+        (x - origin_x, y - origin_y)
+    }
+
+    pub fn screen_to_world(&self, x: f64, y: f64) -> (f64, f64) {
+        let (origin_x, origin_y) = self.origin();
+        (x + origin_x, y + origin_y)
+    }
+
+    fn clamp(&mut self) {
+        let (left, top, width, height) = match self.bounds {
+            Some(bounds) => bounds,
+            None => return,
+        };
+
+        self.x = clamp_axis(self.x, left, width, self.viewport_width);
+        self.y = clamp_axis(self.y, top, height, self.viewport_height);
+    }
+}
+
+impl Shake {
+    fn current_amplitude(&self) -> f64 {
+        if self.duration > 0.0 {
+            self.amplitude * self.remaining / self.duration
+        } else {
+            0.0
+        }
+    }
+
+    fn update(&mut self, dt: f64) {
+        self.remaining = (self.remaining - dt).max(0.0);
+        let amplitude = self.current_amplitude();
+
+        self.offset = if amplitude > 0.0 {
+            (self.next_noise() * amplitude, self.next_noise() * amplitude)
+        } else {
+            (0.0, 0.0)
+        };
+    }
+
+    /// Pseudo random value in [-1, 1] from a xorshift generator.
+    fn next_noise(&mut self) -> f64 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        self.seed as f64 / u32::MAX as f64 * 2.0 - 1.0
+    }
+}
+
+/// Keeps a view of `size` centered on `center` inside `[start, start + length]`.
+fn clamp_axis(center: f64, start: f64, length: f64, size: f64) -> f64 {
+    if length <= size {
+        start + length / 2.0
+    } else {
+        center.max(start + size / 2.0).min(start + length - size / 2.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Camera;
+
+    #[test]
+    fn test_world_screen_conversion() {
+        let mut camera = Camera::new(100.0, 80.0);
+        camera.snap_to(200.0, 100.0);
+
+        assert_eq!(camera.origin(), (150.0, 60.0));
+        assert_eq!(camera.world_to_screen(200.0, 100.0), (50.0, 40.0), "The followed point is in the middle of the screen");
+        assert_eq!(camera.screen_to_world(0.0, 0.0), (150.0, 60.0));
+
+        let (x, y) = camera.world_to_screen(12.5, 7.0);
+        assert_eq!(camera.screen_to_world(x, y), (12.5, 7.0));
+    }
+
+    #[test]
+    fn test_follow_deadzone_and_smoothing() {
+        let mut camera = Camera::new(100.0, 100.0);
+        camera.snap_to(50.0, 50.0);
+        camera.deadzone = (10.0, 10.0);
+
+        camera.follow(58.0, 45.0, 1.0 / 60.0);
+        assert_eq!(camera.center(), (50.0, 50.0), "Moving inside the deadzone keeps the camera still");
+
+        camera.follow(70.0, 50.0, 1.0 / 60.0);
+        assert_eq!(camera.center(), (60.0, 50.0), "The camera moves just enough to keep the target in the deadzone");
+
+        camera.deadzone = (0.0, 0.0);
+        camera.smoothing = 0.5;
+        camera.follow(160.0, 50.0, 0.5);
+        let (x, _) = camera.center();
+        assert!((x - (60.0 + 100.0 * (1.0 - (-1.0f64).exp()))).abs() < 1e-9, "Smoothing covers part of the distance, got {}", x);
+    }
+
+    #[test]
+    fn test_bounds() {
+        let mut camera = Camera::new(100.0, 100.0);
+        camera.set_bounds(Some((0.0, 0.0, 300.0, 60.0)));
+
+        camera.snap_to(10.0, 10.0);
+        assert_eq!(camera.center(), (50.0, 30.0), "The view stays inside wide bounds and centers on short ones");
+
+        camera.snap_to(290.0, 10.0);
+        assert_eq!(camera.center(), (250.0, 30.0));
+    }
+
+    #[test]
+    fn test_shake() {
+        let mut camera = Camera::new(100.0, 100.0);
+        camera.snap_to(50.0, 50.0);
+        camera.shake(4.0, 1.0);
+
+        let mut moved = false;
+        for _ in 0..30 {
+            camera.follow(50.0, 50.0, 1.0 / 60.0);
+            let (x, y) = camera.origin();
+            assert!(x.abs() <= 4.0 && y.abs() <= 4.0, "Shake stays within its amplitude, got {:?}", (x, y));
+            moved |= (x, y) != (0.0, 0.0);
+        }
+        assert!(moved);
+
+        camera.follow(50.0, 50.0, 1.0);
+        assert_eq!(camera.origin(), (0.0, 0.0), "Shake fades out");
+        assert_eq!(camera.center(), (50.0, 50.0), "Shake does not move the camera itself");
+    }
+}
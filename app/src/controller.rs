@@ -1,19 +1,72 @@
+use sdl2::{
+    controller::{Axis, Button as PadButton},
+    keyboard::Keycode,
+    mouse::MouseButton,
+};
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::hash::Hash;
+
+/// Stick positions closer to the center than this are read as centered, worn sticks
+/// rarely rest exactly in the middle.
+pub const DEFAULT_DEADZONE: f64 = 0.25;
+/// How far a trigger is pulled before it counts as pressed.
+pub const DEFAULT_TRIGGER_THRESHOLD: f64 = 0.5;
+/// Ticks of input kept by a `Controller`, 10 seconds at 60 updates per second.
+pub const DEFAULT_HISTORY_LENGTH: usize = 600;
+
 pub struct Button {
     pressed: bool,
     previous: bool,
 }
 
-pub struct Direction {
-    x: f64,
-    y: f64
+/// A key, mouse button or gamepad button that actions are bound to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Input {
+    Key(Keycode),
+    Mouse(MouseButton),
+    Pad(PadButton),
+    /// A gamepad trigger, `Axis::TriggerLeft` or `Axis::TriggerRight`, used as a button.
+    Trigger(Axis),
+}
+
+/// State of the actions during one tick.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot<A: Eq + Hash> {
+    /// Actions that were held, or pressed and released again, since the previous tick.
+    pub actions: HashSet<A>,
+    /// Left stick with the deadzone applied.
+    pub direction: (f64, f64),
+    /// Right stick when it is out of the deadzone.
+    pub aim: Option<(f64, f64)>,
 }
 
-pub struct Controller {
-    pub left: Button,
-    pub right: Button,
-    pub up: Button,
-    pub down: Button,
-    pub direction: Direction
+/// Maps inputs to actions of type `A` and tracks which actions are held.
+///
+/// An input triggers at most one action, an action may have any number of inputs.
+/// It is held while any of its inputs is.
+///
+/// Input events only record what is held. `update` turns that into a `Snapshot` once per tick,
+/// so `rising` and `falling` are true for exactly one tick and the recent snapshots can be
+/// replayed later.
+pub struct Controller<A: Eq + Hash> {
+    bindings: HashMap<Input, A>,
+    held: HashSet<Input>,
+    /// Inputs pressed since the last tick, a tap between two ticks still counts.
+    tapped: HashSet<Input>,
+    buttons: HashMap<A, Button>,
+    /// Gamepad axes of the active gamepad from -1 to 1, triggers from 0 to 1.
+    axes: HashMap<Axis, f64>,
+    /// Gamepad whose input is used, the last one to press a button or push a stick or trigger.
+    active_pad: Option<u32>,
+    current: Snapshot<A>,
+    history: VecDeque<Snapshot<A>>,
+    tick: u64,
+    pub deadzone: f64,
+    pub trigger_threshold: f64,
+    /// Snapshots kept in the history, 0 disables it.
+    pub history_length: usize,
 }
 
 impl Button {
@@ -42,42 +95,317 @@ impl Button {
     }
 }
 
-impl Direction {
-    pub fn new(x: f64, y: f64) -> Self {
+impl Input {
+    /// Parses names like `W`, `Left Shift`, `Mouse:Left` or `Pad:a`,
+    /// keys use SDL key names and gamepad buttons SDL game controller button names.
+    pub fn parse(name: &str) -> Option<Self> {
+        let name = name.trim();
+
+        if let Some(button) = strip_prefix(name, "Mouse:") {
+            let button = match button.to_ascii_lowercase().as_str() {
+                "left" => MouseButton::Left,
+                "middle" => MouseButton::Middle,
+                "right" => MouseButton::Right,
+                "x1" => MouseButton::X1,
+                "x2" => MouseButton::X2,
+                _ => return None,
+            };
+            Some(Input::Mouse(button))
+        } else if let Some(button) = strip_prefix(name, "Pad:") {
+            let button = button.to_ascii_lowercase();
+            match Axis::from_string(&button) {
+                Some(axis @ Axis::TriggerLeft) | Some(axis @ Axis::TriggerRight) => Some(Input::Trigger(axis)),
+                _ => PadButton::from_string(&button).map(Input::Pad),
+            }
+        } else {
+            Keycode::from_name(name).map(Input::Key)
+        }
+    }
+}
+
+/// Case insensitive `str::strip_prefix`.
+fn strip_prefix<'a>(name: &'a str, prefix: &str) -> Option<&'a str> {
+    if name.len() >= prefix.len() && name.is_char_boundary(prefix.len())
+        && name[..prefix.len()].eq_ignore_ascii_case(prefix)
+    {
+        Some(&name[prefix.len()..])
+    } else {
+        None
+    }
+}
+
+/// Writes the name `Input::parse` reads.
+impl fmt::Display for Input {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Input::Key(keycode) => write!(f, "{}", keycode.name()),
+            Input::Mouse(button) => {
+                let name = match button {
+                    MouseButton::Left => "Left",
+                    MouseButton::Middle => "Middle",
+                    MouseButton::Right => "Right",
+                    MouseButton::X1 => "X1",
+                    MouseButton::X2 => "X2",
+                    MouseButton::Unknown => "Unknown",
+                };
+                write!(f, "Mouse:{}", name)
+            },
+            Input::Pad(button) => write!(f, "Pad:{}", button.string()),
+            Input::Trigger(axis) => write!(f, "Pad:{}", axis.string()),
+        }
+    }
+}
+
+impl<A: Copy + Eq + Hash> Controller<A> {
+    pub fn new() -> Self {
         Self {
-            x,
-            y
+            bindings: HashMap::new(),
+            held: HashSet::new(),
+            tapped: HashSet::new(),
+            buttons: HashMap::new(),
+            axes: HashMap::new(),
+            active_pad: None,
+            current: Snapshot {
+                actions: HashSet::new(),
+                direction: (0.0, 0.0),
+                aim: None,
+            },
+            history: VecDeque::new(),
+            tick: 0,
+            deadzone: DEFAULT_DEADZONE,
+            trigger_threshold: DEFAULT_TRIGGER_THRESHOLD,
+            history_length: DEFAULT_HISTORY_LENGTH,
+        }
+    }
+
+    /// Makes `input` trigger `action`, replacing its previous action.
+    pub fn bind(&mut self, input: Input, action: A) {
+        self.release(input);
+        self.bindings.insert(input, action);
+        self.buttons.entry(action).or_insert_with(Button::new);
+    }
+
+    /// Removes all inputs of `action`.
+    pub fn unbind(&mut self, action: A) {
+        let inputs = self.inputs(action);
+        for input in inputs {
+            self.release(input);
+            self.bindings.remove(&input);
+        }
+    }
+
+    pub fn action(&self, input: Input) -> Option<A> {
+        self.bindings.get(&input).copied()
+    }
+
+    /// Inputs bound to `action`, in no particular order.
+    pub fn inputs(&self, action: A) -> Vec<Input> {
+        self.bindings.iter()
+            .filter(|&(_, &bound)| bound == action)
+            .map(|(&input, _)| input)
+            .collect()
+    }
+
+    /// Reports a press or a release of `input`, returns the action it is bound to.
+    /// The actions only change on the next `update`, repeated presses of a held input do nothing.
+    pub fn input(&mut self, input: Input, pressed: bool) -> Option<A> {
+        let action = self.action(input)?;
+        if pressed {
+            self.held.insert(input);
+            self.tapped.insert(input);
+        } else {
+            self.held.remove(&input);
+        }
+
+        Some(action)
+    }
+
+    /// Takes the snapshot of this tick from the input, call it once at the start of every update.
+    pub fn update(&mut self) -> &Snapshot<A> {
+        let actions = self.held.iter()
+            .chain(&self.tapped)
+            .filter_map(|input| self.bindings.get(input).copied())
+            .collect();
+        self.tapped.clear();
+
+        let aim = match self.stick(Axis::RightX, Axis::RightY) {
+            (x, y) if x == 0.0 && y == 0.0 => None,
+            aim => Some(aim),
+        };
+        let snapshot = Snapshot {
+            actions,
+            direction: self.stick(Axis::LeftX, Axis::LeftY),
+            aim,
+        };
+
+        self.apply(snapshot)
+    }
+
+    /// Uses a recorded snapshot for this tick instead of the input, e.g. one from `history`.
+    pub fn replay(&mut self, snapshot: Snapshot<A>) -> &Snapshot<A> {
+        self.apply(snapshot)
+    }
+
+    fn apply(&mut self, snapshot: Snapshot<A>) -> &Snapshot<A> {
+        for (action, button) in self.buttons.iter_mut() {
+            button.update(snapshot.actions.contains(action));
+        }
+
+        if self.history_length > 0 {
+            while self.history.len() >= self.history_length {
+                self.history.pop_front();
+            }
+            self.history.push_back(snapshot.clone());
+        }
+        self.current = snapshot;
+        self.tick += 1;
+
+        &self.current
+    }
+
+    /// Updates so far.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// The last `history_length` snapshots, oldest first. The last one belongs to `tick`.
+    pub fn history(&self) -> impl Iterator<Item = &Snapshot<A>> {
+        self.history.iter()
+    }
+
+    /// Makes gamepad `id` the one whose input is used, letting go of what the previous one held.
+    /// Call it when a gamepad button is pressed.
+    pub fn activate_gamepad(&mut self, id: u32) {
+        if self.active_pad != Some(id) {
+            self.release_gamepad();
+            self.active_pad = Some(id);
         }
     }
 
+    /// Whether input of gamepad `id` is used, releases of other gamepads should be ignored.
+    pub fn is_active_gamepad(&self, id: u32) -> bool {
+        self.active_pad == Some(id)
+    }
+
+    /// Lets go of what gamepad `id` held when it is unplugged, other devices are not affected.
+    pub fn remove_gamepad(&mut self, id: u32) {
+        if self.active_pad == Some(id) {
+            self.release_gamepad();
+            self.active_pad = None;
+        }
+    }
+
+    fn release_gamepad(&mut self) {
+        let is_pad = |input: &Input| matches!(input, Input::Pad(_) | Input::Trigger(_));
+        self.held.retain(|input| !is_pad(input));
+        self.tapped.retain(|input| !is_pad(input));
+        self.axes.clear();
+    }
+
+    /// Reports an axis of gamepad `id` moving to `value`, from -1 to 1 or 0 to 1 for triggers.
+    /// Other gamepads than the active one are ignored until they push an axis past the deadzone
+    /// or the trigger threshold, which makes them the active one.
+    ///
+    /// Returns the press or release of a trigger crossing `trigger_threshold`,
+    /// to be handled like a button with `input`.
+    pub fn axis_motion(&mut self, id: u32, axis: Axis, value: f64) -> Option<(Input, bool)> {
+        if !self.is_active_gamepad(id) {
+            let pushed = match axis {
+                Axis::TriggerLeft | Axis::TriggerRight => value >= self.trigger_threshold,
+                _ => value.abs() > self.deadzone,
+            };
+            if !pushed {
+                return None;
+            }
+            self.activate_gamepad(id);
+        }
+
+        let previous = self.axes.insert(axis, value).unwrap_or(0.0);
+
+        match axis {
+            Axis::TriggerLeft | Axis::TriggerRight => {
+                let (was_pulled, pulled) = (previous >= self.trigger_threshold, value >= self.trigger_threshold);
+                if was_pulled != pulled {
+                    Some((Input::Trigger(axis), pulled))
+                } else {
+                    None
+                }
+            },
+            _ => None,
+        }
+    }
+
+    /// Left stick position with the deadzone applied, for movement.
     pub fn direction(&self) -> (f64, f64) {
-        (self.x, self.y)
+        self.current.direction
+    }
+
+    /// Right stick direction when it is pushed out of the deadzone, for aiming.
+    pub fn aim(&self) -> Option<(f64, f64)> {
+        self.current.aim
+    }
+
+    fn stick(&self, x: Axis, y: Axis) -> (f64, f64) {
+        let axis = |axis| self.axes.get(&axis).copied().unwrap_or(0.0);
+
+        apply_deadzone(axis(x), axis(y), self.deadzone)
     }
 
-    pub fn x(&self) -> f64 {
-        self.x
+    /// Lets go of everything and centers the sticks, e.g. when the window loses focus and
+    /// releases would go unnoticed.
+    pub fn release_all(&mut self) {
+        self.held.clear();
+        self.tapped.clear();
+        self.axes.clear();
     }
 
-    pub fn y(&self) -> f64 {
-        self.y
+    fn release(&mut self, input: Input) {
+        self.held.remove(&input);
+        self.tapped.remove(&input);
+    }
+
+    pub fn pressed(&self, action: A) -> bool {
+        matches!(self.buttons.get(&action), Some(button) if button.pressed())
+    }
+
+    pub fn rising(&self, action: A) -> bool {
+        matches!(self.buttons.get(&action), Some(button) if button.rising())
+    }
+
+    pub fn falling(&self, action: A) -> bool {
+        matches!(self.buttons.get(&action), Some(button) if button.falling())
+    }
+
+    /// -1, 0 or 1 depending on which of the two actions is held.
+    pub fn axis(&self, negative: A, positive: A) -> f64 {
+        let value = |action| if self.pressed(action) { 1.0 } else { 0.0 };
+
+        value(positive) - value(negative)
     }
 }
 
-impl Controller {
-    pub fn new() -> Self {
-        Self {
-            left: Button::new(),
-            right: Button::new(),
-            up: Button::new(),
-            down: Button::new(),
-            direction: Direction::new(0.0, 0.0)
-        }
+/// Reads stick positions inside a circle of radius `deadzone` as centered and rescales the rest,
+/// so the output still goes smoothly from 0 to 1 in every direction.
+pub fn apply_deadzone(x: f64, y: f64, deadzone: f64) -> (f64, f64) {
+    let magnitude = (x * x + y * y).sqrt();
+    if magnitude <= deadzone || magnitude == 0.0 {
+        return (0.0, 0.0);
+    }
+
+    let scale = ((magnitude - deadzone) / (1.0 - deadzone)).min(1.0) / magnitude;
+    (x * scale, y * scale)
+}
+
+impl<A: Copy + Eq + Hash> Default for Controller<A> {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Button;
+    use super::{apply_deadzone, Button, Controller, Input};
+    use sdl2::{controller::{Axis, Button as PadButton}, keyboard::Keycode, mouse::MouseButton};
     #[test]
     fn test_button_update() {
         let mut button = Button::new();
@@ -115,4 +443,201 @@ mod tests {
         button.update(false);
         assert_eq!(button.falling(), true);
     }
+
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+    enum Action {
+        Left,
+        Right,
+        Fire,
+    }
+
+    #[test]
+    fn test_parse_input() {
+        assert_eq!(Input::parse("W"), Some(Input::Key(Keycode::W)));
+        assert_eq!(Input::parse("left shift"), Some(Input::Key(Keycode::LShift)));
+        assert_eq!(Input::parse("Mouse:Left"), Some(Input::Mouse(MouseButton::Left)));
+        assert_eq!(Input::parse("pad:A"), Some(Input::Pad(PadButton::A)));
+        assert_eq!(Input::parse("Pad:righttrigger"), Some(Input::Trigger(Axis::TriggerRight)));
+        assert_eq!(Input::parse("Pad:leftx"), None, "Sticks are not buttons");
+        assert_eq!(Input::parse("Mouse:Thumb"), None);
+        assert_eq!(Input::parse("Nonsense"), None);
+
+        let inputs = [
+            Input::Key(Keycode::Return),
+            Input::Mouse(MouseButton::X1),
+            Input::Pad(PadButton::LeftShoulder),
+            Input::Trigger(Axis::TriggerLeft),
+        ];
+        for &input in &inputs {
+            assert_eq!(Input::parse(&input.to_string()), Some(input), "{} survives a roundtrip", input);
+        }
+    }
+
+    #[test]
+    fn test_controller_actions() {
+        let mut controller = Controller::new();
+        controller.bind(Input::Key(Keycode::A), Action::Left);
+        controller.bind(Input::Key(Keycode::Left), Action::Left);
+        controller.bind(Input::Key(Keycode::D), Action::Right);
+        controller.bind(Input::Mouse(MouseButton::Left), Action::Fire);
+
+        assert_eq!(controller.input(Input::Key(Keycode::A), true), Some(Action::Left));
+        assert_eq!(controller.input(Input::Key(Keycode::W), true), None, "Unbound inputs are ignored");
+        assert!(!controller.pressed(Action::Left), "Actions change on the next update");
+        controller.update();
+        assert!(controller.rising(Action::Left));
+        assert_eq!(controller.axis(Action::Left, Action::Right), -1.0);
+
+        controller.input(Input::Key(Keycode::Left), true);
+        controller.input(Input::Key(Keycode::A), false);
+        controller.update();
+        assert!(controller.pressed(Action::Left), "Held while any of its inputs is");
+        controller.input(Input::Key(Keycode::Left), false);
+        controller.update();
+        assert!(controller.falling(Action::Left));
+
+        controller.input(Input::Key(Keycode::D), true);
+        controller.input(Input::Mouse(MouseButton::Left), true);
+        controller.update();
+        controller.release_all();
+        controller.update();
+        assert!(!controller.pressed(Action::Right) && !controller.pressed(Action::Fire));
+    }
+
+    #[test]
+    fn test_edges_per_tick() {
+        let mut controller = Controller::new();
+        controller.bind(Input::Key(Keycode::A), Action::Left);
+
+        controller.input(Input::Key(Keycode::A), true);
+        controller.update();
+        assert!(controller.rising(Action::Left));
+        controller.input(Input::Key(Keycode::A), true);
+        controller.update();
+        assert!(!controller.rising(Action::Left), "Key repeat does not press again");
+        controller.update();
+        assert!(controller.pressed(Action::Left) && !controller.rising(Action::Left), "Edges last one tick");
+
+        controller.input(Input::Key(Keycode::A), false);
+        controller.input(Input::Key(Keycode::A), true);
+        controller.input(Input::Key(Keycode::A), false);
+        controller.update();
+        assert!(controller.pressed(Action::Left), "Tapped again before the tick, so it stays held one more tick");
+        controller.update();
+        assert!(controller.falling(Action::Left));
+
+        controller.input(Input::Key(Keycode::A), true);
+        controller.input(Input::Key(Keycode::A), false);
+        controller.update();
+        assert!(controller.rising(Action::Left), "A tap between two ticks is not lost");
+        controller.update();
+        assert!(controller.falling(Action::Left));
+    }
+
+    #[test]
+    fn test_history_replay() {
+        let mut recording = Controller::new();
+        recording.bind(Input::Key(Keycode::A), Action::Left);
+        recording.history_length = 3;
+
+        for &pressed in &[true, true, false, true] {
+            recording.input(Input::Key(Keycode::A), pressed);
+            recording.update();
+        }
+        assert_eq!(recording.tick(), 4);
+        let history = recording.history().cloned().collect::<Vec<_>>();
+        assert_eq!(history.len(), 3, "Only history_length snapshots are kept");
+
+        let mut replaying = Controller::new();
+        replaying.bind(Input::Key(Keycode::D), Action::Left);
+        let mut rising = Vec::new();
+        for snapshot in history {
+            replaying.replay(snapshot);
+            rising.push(replaying.rising(Action::Left));
+        }
+        assert_eq!(rising, vec![true, false, true], "Replays ignore the live input and bindings");
+    }
+
+    #[test]
+    fn test_controller_rebind() {
+        let mut controller = Controller::new();
+        controller.bind(Input::Key(Keycode::A), Action::Left);
+        controller.input(Input::Key(Keycode::A), true);
+        controller.update();
+
+        controller.bind(Input::Key(Keycode::A), Action::Fire);
+        controller.update();
+        assert!(!controller.pressed(Action::Left), "Rebinding a held input releases its old action");
+        assert_eq!(controller.action(Input::Key(Keycode::A)), Some(Action::Fire));
+        assert!(controller.inputs(Action::Left).is_empty());
+
+        controller.unbind(Action::Fire);
+        assert_eq!(controller.input(Input::Key(Keycode::A), true), None);
+    }
+
+    #[test]
+    fn test_deadzone() {
+        assert_eq!(apply_deadzone(0.2, 0.1, 0.25), (0.0, 0.0));
+        assert_eq!(apply_deadzone(1.0, 0.0, 0.25), (1.0, 0.0));
+
+        let (x, y) = apply_deadzone(0.0, -0.625, 0.25);
+        assert!(x == 0.0 && (y + 0.5).abs() < 1e-9, "The rest of the range is rescaled, got {}", y);
+        let (x, y) = apply_deadzone(1.0, 1.0, 0.25);
+        assert!(((x * x + y * y).sqrt() - 1.0).abs() < 1e-9, "Corners are clamped to the unit circle");
+    }
+
+    #[test]
+    fn test_sticks_and_triggers() {
+        let mut controller = Controller::new();
+        controller.bind(Input::Trigger(Axis::TriggerRight), Action::Fire);
+
+        controller.axis_motion(0, Axis::LeftX, 1.0);
+        controller.axis_motion(0, Axis::RightY, 0.1);
+        controller.update();
+        assert_eq!(controller.direction(), (1.0, 0.0));
+        assert_eq!(controller.aim(), None, "Sticks in the deadzone do not aim");
+
+        assert_eq!(controller.axis_motion(0, Axis::TriggerRight, 0.3), None);
+        let pulled = controller.axis_motion(0, Axis::TriggerRight, 0.8);
+        assert_eq!(pulled, Some((Input::Trigger(Axis::TriggerRight), true)));
+        assert_eq!(controller.axis_motion(0, Axis::TriggerRight, 0.9), None, "Only crossing the threshold is reported");
+        controller.input(Input::Trigger(Axis::TriggerRight), true);
+        controller.update();
+        assert!(controller.pressed(Action::Fire));
+
+        controller.release_all();
+        controller.update();
+        assert!(!controller.pressed(Action::Fire));
+        assert_eq!(controller.direction(), (0.0, 0.0));
+    }
+
+    #[test]
+    fn test_multiple_gamepads() {
+        let mut controller = Controller::new();
+        controller.bind(Input::Key(Keycode::W), Action::Left);
+        controller.bind(Input::Pad(PadButton::A), Action::Fire);
+
+        controller.axis_motion(0, Axis::LeftX, 1.0);
+        assert_eq!(controller.axis_motion(1, Axis::LeftX, 0.05), None);
+        controller.update();
+        assert_eq!(controller.direction(), (1.0, 0.0), "Resting sticks of another gamepad are ignored");
+
+        controller.input(Input::Key(Keycode::W), true);
+        controller.activate_gamepad(0);
+        controller.input(Input::Pad(PadButton::A), true);
+        controller.axis_motion(1, Axis::LeftX, -1.0);
+        controller.update();
+        assert_eq!(controller.direction(), (-1.0, 0.0), "Pushing a stick switches gamepads");
+        assert!(!controller.pressed(Action::Fire), "Buttons of the previous gamepad are let go of");
+        assert!(controller.pressed(Action::Left));
+
+        controller.remove_gamepad(0);
+        controller.update();
+        assert_eq!(controller.direction(), (-1.0, 0.0), "Removing an inactive gamepad changes nothing");
+
+        controller.remove_gamepad(1);
+        controller.update();
+        assert_eq!(controller.direction(), (0.0, 0.0));
+        assert!(controller.pressed(Action::Left), "Other devices are not released");
+    }
 }
@@ -0,0 +1,404 @@
+use serde::Deserialize;
+
+use sdl2::{
+    image::LoadTexture,
+    pixels::{Color, PixelFormatEnum},
+    rect::Rect,
+    render::{
+        BlendMode,
+        Canvas,
+        Texture,
+        TextureCreator,
+    },
+    ttf::Sdl2TtfContext,
+    video::{
+        Window,
+        WindowContext
+    },
+};
+
+use std::collections::HashMap;
+
+/// What `draw_text` needs from a font.
+pub trait Font {
+    fn line_height(&self) -> u32;
+    /// Width in pixels of a single line of text.
+    fn line_width(&self, line: &str) -> u32;
+    fn draw_line(&mut self, canvas: &mut Canvas<Window>, line: &str, x: i32, y: i32, color: Color);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+/// How `draw_text` draws text.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextStyle {
+    pub color: Color,
+    /// Lines are aligned to the `x` given to `draw_text`.
+    pub align: Align,
+    /// Lines longer than this many pixels are wrapped, between words when possible.
+    pub wrap: Option<u32>,
+    /// Color of a one pixel outline around the glyphs.
+    pub outline: Option<Color>,
+}
+
+#[derive(Deserialize)]
+struct BitmapFontDescriptor {
+    texture: String,
+    glyph_width: u32,
+    glyph_height: u32,
+    columns: u32,
+    first_char: u32,
+}
+
+/// Font file rendered by SDL2_ttf, glyphs are rendered into textures the first time they are drawn.
+pub struct TtfFont {
+    font: sdl2::ttf::Font<'static, 'static>,
+    /// `None` for characters the font has no glyph for, they take no space.
+    glyphs: HashMap<char, Option<Glyph>>,
+}
+
+struct Glyph {
+    /// `None` for glyphs that do not render, like spaces, they only move the pen.
+    texture: Option<Texture>,
+    width: u32,
+    height: u32,
+    advance: i32,
+}
+
+/// Monospaced font stored as a grid of glyphs in a single texture.
+///
+/// Glyphs are expected to be white on a transparent background, so they can be
+/// tinted with any color when drawn.
+pub struct BitmapFont {
+    texture: Texture,
+    glyph_width: u32,
+    glyph_height: u32,
+    columns: u32,
+    first_char: u32,
+    glyph_count: u32,
+}
+
+impl BitmapFont {
+    /// Loads a font from a TOML descriptor naming the glyph texture and the grid layout.
+    pub fn load(texture_creator: &TextureCreator<WindowContext>, descriptor_path: &str) -> Result<Self, String> {
+        let descriptor = std::fs::read_to_string(descriptor_path)
+            .map_err(|e| format!("cannot read {}: {}", descriptor_path, e))?;
+        let descriptor: BitmapFontDescriptor = toml::from_str(&descriptor)
+            .map_err(|e| format!("cannot parse {}: {}", descriptor_path, e))?;
+
+        let mut texture = texture_creator.load_texture(&descriptor.texture)?;
+        texture.set_blend_mode(BlendMode::Blend);
+
+        let query = texture.query();
+        let rows = query.height / descriptor.glyph_height;
+
+        Ok(Self {
+            texture,
+            glyph_width: descriptor.glyph_width,
+            glyph_height: descriptor.glyph_height,
+            columns: descriptor.columns,
+            first_char: descriptor.first_char,
+            glyph_count: descriptor.columns * rows,
+        })
+    }
+
+    /// Font drawing every character as a hollow box, used when the real font cannot be loaded.
+    pub fn placeholder(texture_creator: &TextureCreator<WindowContext>) -> Self {
+        const WIDTH: u32 = 6;
+        const HEIGHT: u32 = 8;
+
+        let mut pixels = vec![0u8; (WIDTH * HEIGHT * 4) as usize];
+        for y in 1..HEIGHT - 1 {
+            for x in 0..WIDTH - 1 {
+                if x == 0 || x == WIDTH - 2 || y == 1 || y == HEIGHT - 2 {
+                    let i = ((y * WIDTH + x) * 4) as usize;
+                    pixels[i..i + 4].copy_from_slice(&[255; 4]);
+                }
+            }
+        }
+
+        let mut texture = texture_creator.create_texture_static(PixelFormatEnum::ABGR8888, WIDTH, HEIGHT)
+            .expect("cannot create the placeholder font texture");
+        texture.update(None, &pixels, (WIDTH * 4) as usize)
+            .expect("cannot fill the placeholder font texture");
+        texture.set_blend_mode(BlendMode::Blend);
+
+        Self {
+            texture,
+            glyph_width: WIDTH,
+            glyph_height: HEIGHT,
+            columns: 1,
+            first_char: '?' as u32,
+            glyph_count: 1,
+        }
+    }
+
+    pub fn line_height(&self) -> u32 {
+        self.glyph_height
+    }
+
+    /// Size in pixels of the bounding box of `text`, including line breaks.
+    pub fn text_size(&self, text: &str) -> (u32, u32) {
+        let lines = text.split('\n');
+        let (mut width, mut height) = (0, 0);
+
+        for line in lines {
+            width = width.max(line.chars().count() as u32 * self.glyph_width);
+            height += self.glyph_height;
+        }
+
+        (width, height)
+    }
+
+    pub fn draw(&mut self, canvas: &mut Canvas<Window>, text: &str, x: i32, y: i32, color: Color) {
+        self.texture.set_color_mod(color.r, color.g, color.b);
+        self.texture.set_alpha_mod(color.a);
+
+        let (mut pen_x, mut pen_y) = (x, y);
+        for c in text.chars() {
+            if c == '\n' {
+                pen_x = x;
+                pen_y += self.glyph_height as i32;
+                continue;
+            }
+
+            if let Some(src) = self.glyph_rect(c) {
+                let dst = Rect::new(pen_x, pen_y, self.glyph_width, self.glyph_height);
+                canvas.copy(&self.texture, src, dst).unwrap();
+            }
+            pen_x += self.glyph_width as i32;
+        }
+    }
+
+    fn glyph_rect(&self, c: char) -> Option<Rect> {
+        let index = (c as u32).checked_sub(self.first_char)
+            .filter(|&index| index < self.glyph_count)
+            .or_else(|| ('?' as u32).checked_sub(self.first_char).filter(|&index| index < self.glyph_count))?;
+
+        if c == ' ' {
+            return None;
+        }
+
+        Some(Rect::new(
+            ((index % self.columns) * self.glyph_width) as i32,
+            ((index / self.columns) * self.glyph_height) as i32,
+            self.glyph_width,
+            self.glyph_height
+        ))
+    }
+}
+
+impl Font for BitmapFont {
+    fn line_height(&self) -> u32 {
+        self.glyph_height
+    }
+
+    fn line_width(&self, line: &str) -> u32 {
+        line.chars().count() as u32 * self.glyph_width
+    }
+
+    fn draw_line(&mut self, canvas: &mut Canvas<Window>, line: &str, x: i32, y: i32, color: Color) {
+        self.draw(canvas, line, x, y, color);
+    }
+}
+
+impl TtfFont {
+    pub fn load(ttf: &'static Sdl2TtfContext, path: &str, point_size: u16) -> Result<Self, String> {
+        let font = ttf.load_font(path, point_size)
+            .map_err(|e| format!("cannot load {}: {}", path, e))?;
+
+        Ok(Self {
+            font,
+            glyphs: HashMap::new(),
+        })
+    }
+
+    fn glyph(&mut self, canvas: &Canvas<Window>, c: char) -> Option<&mut Glyph> {
+        let font = &self.font;
+        self.glyphs.entry(c)
+            .or_insert_with(|| {
+                let advance = font.find_glyph_metrics(c)?.advance;
+                // White glyphs are tinted when drawn, like the bitmap font's.
+                let surface = match font.render_char(c).blended(Color::RGB(255, 255, 255)) {
+                    Ok(surface) => surface,
+                    Err(_) => return Some(Glyph { texture: None, width: 0, height: 0, advance }),
+                };
+                let texture = canvas.texture_creator().create_texture_from_surface(&surface).ok()
+                    .map(|mut texture| {
+                        texture.set_blend_mode(BlendMode::Blend);
+                        texture
+                    });
+
+                Some(Glyph {
+                    texture,
+                    width: surface.width(),
+                    height: surface.height(),
+                    advance,
+                })
+            })
+            .as_mut()
+    }
+}
+
+impl Font for TtfFont {
+    fn line_height(&self) -> u32 {
+        self.font.height().max(1) as u32
+    }
+
+    fn line_width(&self, line: &str) -> u32 {
+        line.chars()
+            .filter_map(|c| self.font.find_glyph_metrics(c))
+            .map(|metrics| metrics.advance.max(0) as u32)
+            .sum()
+    }
+
+    fn draw_line(&mut self, canvas: &mut Canvas<Window>, line: &str, x: i32, y: i32, color: Color) {
+        let mut pen_x = x;
+        for c in line.chars() {
+            let glyph = match self.glyph(canvas, c) {
+                Some(glyph) => glyph,
+                None => continue,
+            };
+            if let Some(texture) = &mut glyph.texture {
+                texture.set_color_mod(color.r, color.g, color.b);
+                texture.set_alpha_mod(color.a);
+                canvas.copy(texture, None, Rect::new(pen_x, y, glyph.width, glyph.height)).unwrap();
+            }
+            pen_x += glyph.advance;
+        }
+    }
+}
+
+impl TextStyle {
+    pub fn new(color: Color) -> Self {
+        Self {
+            color,
+            align: Align::Left,
+            wrap: None,
+            outline: None,
+        }
+    }
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        Self::new(Color::RGB(255, 255, 255))
+    }
+}
+
+/// Splits `text` into lines at most `max_width` wide as measured by `measure`, breaking
+/// between words and inside words longer than a line.
+pub fn wrap_text<M: Fn(&str) -> u32>(text: &str, max_width: u32, measure: M) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    for paragraph in text.split('\n') {
+        let mut line = String::new();
+        for word in paragraph.split(' ') {
+            let candidate = if line.is_empty() { word.to_string() } else { format!("{} {}", line, word) };
+            if measure(&candidate) <= max_width {
+                line = candidate;
+                continue;
+            }
+
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            for c in word.chars() {
+                line.push(c);
+                if measure(&line) > max_width && line.chars().count() > 1 {
+                    line.pop();
+                    lines.push(std::mem::take(&mut line));
+                    line.push(c);
+                }
+            }
+        }
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// Lines of `text` as `draw_text` draws them.
+pub fn layout_text<F: Font + ?Sized>(font: &F, text: &str, style: &TextStyle) -> Vec<String> {
+    match style.wrap {
+        Some(max_width) => wrap_text(text, max_width, |line| font.line_width(line)),
+        None => text.split('\n').map(String::from).collect(),
+    }
+}
+
+/// Size in pixels of `text` drawn with `style`.
+pub fn text_size<F: Font + ?Sized>(font: &F, text: &str, style: &TextStyle) -> (u32, u32) {
+    let lines = layout_text(font, text, style);
+    let width = lines.iter().map(|line| font.line_width(line)).max().unwrap_or(0);
+
+    (width, lines.len() as u32 * font.line_height())
+}
+
+/// Left edge of a line `width` pixels wide aligned to `x`.
+fn aligned_x(x: i32, width: u32, align: Align) -> i32 {
+    match align {
+        Align::Left => x,
+        Align::Center => x - width as i32 / 2,
+        Align::Right => x - width as i32,
+    }
+}
+
+/// Text drawing on the canvas the app draws to.
+pub trait DrawText {
+    /// Draws `text` with the top of its first line at `y`. Returns the area it covers.
+    fn draw_text<F: Font + ?Sized>(&mut self, font: &mut F, text: &str, x: i32, y: i32, style: &TextStyle) -> Rect;
+}
+
+impl DrawText for Canvas<Window> {
+    fn draw_text<F: Font + ?Sized>(&mut self, font: &mut F, text: &str, x: i32, y: i32, style: &TextStyle) -> Rect {
+        let lines = layout_text(font, text, style);
+        let line_height = font.line_height() as i32;
+
+        let mut bounds: Option<Rect> = None;
+        for (i, line) in lines.iter().enumerate() {
+            let width = font.line_width(line);
+            let (line_x, line_y) = (aligned_x(x, width, style.align), y + i as i32 * line_height);
+
+            if let Some(outline) = style.outline {
+                for &(dx, dy) in &[(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)] {
+                    font.draw_line(self, line, line_x + dx, line_y + dy, outline);
+                }
+            }
+            font.draw_line(self, line, line_x, line_y, style.color);
+
+            let area = Rect::new(line_x, line_y, width, line_height as u32);
+            bounds = Some(bounds.map_or(area, |bounds| bounds.union(area)));
+        }
+
+        bounds.unwrap_or_else(|| Rect::new(x, y, 1, 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{aligned_x, wrap_text, Align};
+
+    fn measure(text: &str) -> u32 {
+        text.chars().count() as u32 * 6
+    }
+
+    #[test]
+    fn test_wrap_text() {
+        assert_eq!(wrap_text("the quick brown fox", 60, measure), vec!["the quick", "brown fox"]);
+        assert_eq!(wrap_text("one\ntwo", 60, measure), vec!["one", "two"], "Line breaks are kept");
+        assert_eq!(wrap_text("abcdefghijkl", 36, measure), vec!["abcdef", "ghijkl"], "Long words are split");
+        assert_eq!(wrap_text("", 36, measure), vec![""]);
+    }
+
+    #[test]
+    fn test_aligned_x() {
+        assert_eq!(aligned_x(100, 40, Align::Left), 100);
+        assert_eq!(aligned_x(100, 40, Align::Center), 80);
+        assert_eq!(aligned_x(100, 40, Align::Right), 60);
+    }
+}
@@ -0,0 +1,79 @@
+use sdl2::{
+    GameControllerSubsystem,
+    controller::GameController,
+};
+use tracing::{info, warn};
+
+use std::collections::HashMap;
+
+/// Open game controllers by instance id.
+///
+/// SDL only reports a controller's events while it is open. Controllers that are already
+/// plugged in at startup are reported as added too, so all of them go through `add`.
+pub struct Gamepads {
+    subsystem: GameControllerSubsystem,
+    open: HashMap<u32, GameController>,
+}
+
+impl Gamepads {
+    pub fn new(subsystem: GameControllerSubsystem) -> Self {
+        Self {
+            subsystem,
+            open: HashMap::new(),
+        }
+    }
+
+    /// Opens the controller at joystick `index`, returns its instance id.
+    pub fn add(&mut self, index: u32) -> Option<u32> {
+        match self.subsystem.open(index) {
+            Ok(controller) => {
+                let id = controller.instance_id();
+                info!(id, name = %controller.name(), "Gamepad connected");
+                self.open.insert(id, controller);
+                Some(id)
+            },
+            Err(e) => {
+                warn!(index, error = %e, "Cannot open gamepad");
+                None
+            },
+        }
+    }
+
+    /// Closes the controller with instance `id`, returns whether it was open.
+    pub fn remove(&mut self, id: u32) -> bool {
+        match self.open.remove(&id) {
+            Some(controller) => {
+                info!(id, name = %controller.name(), "Gamepad disconnected");
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Instance ids and names of the connected controllers.
+    pub fn connected(&self) -> Vec<(u32, String)> {
+        let mut connected = self.open.iter()
+            .map(|(&id, controller)| (id, controller.name()))
+            .collect::<Vec<_>>();
+        connected.sort();
+
+        connected
+    }
+}
+
+/// Converts a raw SDL axis value to the range from -1 to 1.
+pub fn normalize_axis(value: i16) -> f64 {
+    (value as f64 / i16::MAX as f64).max(-1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::normalize_axis;
+
+    #[test]
+    fn test_normalize_axis() {
+        assert_eq!(normalize_axis(0), 0.0);
+        assert_eq!(normalize_axis(i16::MAX), 1.0);
+        assert_eq!(normalize_axis(i16::MIN), -1.0, "The negative side is one step longer");
+    }
+}
@@ -1,4 +1,13 @@
 pub mod app;
+pub mod assets;
+pub mod camera;
 pub mod controller;
+pub mod font;
+pub mod gamepad;
+pub mod scaling;
+pub mod scene;
+pub mod sprite;
+pub mod timing;
+pub mod ui;
 
-pub use app::run;
\ No newline at end of file
+pub use app::run;
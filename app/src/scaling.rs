@@ -0,0 +1,90 @@
+/// Where the render target ends up in the window.
+///
+/// The app draws on a small render target that is scaled up to the window. With pixel perfect
+/// scaling the factor is a whole number and the rest of the window is left as bars around
+/// the picture, otherwise the target is stretched over the whole window.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Letterbox {
+    /// Size of the render target, in game pixels.
+    pub target_width: u32,
+    pub target_height: u32,
+    /// Area of the window the target is drawn to, in window pixels.
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Letterbox {
+    /// Fits a render target showing `window / scale` game pixels into the window.
+    pub fn fit(window_width: u32, window_height: u32, scale: f64, pixel_perfect: bool) -> Self {
+        let (window_width, window_height) = (window_width.max(1), window_height.max(1));
+
+        if pixel_perfect {
+            let scale = (scale.round() as u32).max(1);
+            let (target_width, target_height) = ((window_width / scale).max(1), (window_height / scale).max(1));
+            let (width, height) = (target_width * scale, target_height * scale);
+
+            Self {
+                target_width,
+                target_height,
+                x: (window_width as i32 - width as i32) / 2,
+                y: (window_height as i32 - height as i32) / 2,
+                width,
+                height,
+            }
+        } else {
+            let scale = scale.max(1.0);
+
+            Self {
+                target_width: ((window_width as f64 / scale) as u32).max(1),
+                target_height: ((window_height as f64 / scale) as u32).max(1),
+                x: 0,
+                y: 0,
+                width: window_width,
+                height: window_height,
+            }
+        }
+    }
+
+    /// Converts window coordinates (e.g. of the mouse) to render target coordinates.
+    /// Points on the bars end up outside of the target.
+    pub fn window_to_target(&self, x: i32, y: i32) -> (f64, f64) {
+        (
+            (x - self.x) as f64 * self.target_width as f64 / self.width as f64,
+            (y - self.y) as f64 * self.target_height as f64 / self.height as f64,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Letterbox;
+
+    #[test]
+    fn test_pixel_perfect() {
+        let letterbox = Letterbox::fit(1000, 700, 6.0, true);
+
+        assert_eq!((letterbox.target_width, letterbox.target_height), (166, 116));
+        assert_eq!((letterbox.x, letterbox.y, letterbox.width, letterbox.height), (2, 2, 996, 696), "Leftover pixels become bars");
+        assert_eq!(Letterbox::fit(1000, 700, 5.6, true).target_width, 166, "Scale is rounded to a whole number");
+        assert_eq!(Letterbox::fit(3, 3, 6.0, true).target_width, 1, "Tiny windows still get a target");
+    }
+
+    #[test]
+    fn test_stretched() {
+        let letterbox = Letterbox::fit(1000, 700, 6.0, false);
+
+        assert_eq!((letterbox.target_width, letterbox.target_height), (166, 116));
+        assert_eq!((letterbox.x, letterbox.y, letterbox.width, letterbox.height), (0, 0, 1000, 700));
+    }
+
+    #[test]
+    fn test_window_to_target() {
+        let letterbox = Letterbox::fit(1000, 700, 6.0, true);
+
+        assert_eq!(letterbox.window_to_target(2, 2), (0.0, 0.0));
+        assert_eq!(letterbox.window_to_target(2 + 6 * 10, 2 + 6 * 20 + 3), (10.0, 20.5));
+        assert!(letterbox.window_to_target(0, 0).0 < 0.0, "The bars are outside the target");
+    }
+}
@@ -0,0 +1,361 @@
+use sdl2::{
+    controller::{Axis, Button as PadButton},
+    keyboard::Keycode,
+    mouse::MouseButton,
+    render::Canvas,
+    video::Window,
+};
+use tracing::debug;
+
+use crate::app::{App, Context};
+
+/// What the scene stack does after a scene callback.
+pub enum Transition<S> {
+    None,
+    /// Puts a scene on top, the current one stays below and is covered.
+    Push(Box<dyn Scene<S>>),
+    /// Removes the top scene. The app quits when the last one is popped.
+    Pop,
+    /// Swaps the top scene for another one.
+    Replace(Box<dyn Scene<S>>),
+    /// Removes all scenes and starts over with one.
+    Reset(Box<dyn Scene<S>>),
+    Quit,
+}
+
+/// A screen of the app, like a menu or the game itself, with state `S` shared by all scenes.
+///
+/// Input only goes to the top scene. Callbacks that handle something return the transition
+/// it causes, so a scene changes scenes from its input handling and its update, and the
+/// network layer through the shared state the update looks at.
+pub trait Scene<S> {
+    /// Shown in logs.
+    fn name(&self) -> &str;
+
+    /// Called when the scene gets on the stack.
+    fn enter(&mut self, _ctx: &mut Context, _state: &mut S) {}
+    /// Called when the scene leaves the stack, by a pop, a replace or a reset.
+    fn exit(&mut self, _ctx: &mut Context, _state: &mut S) {}
+    /// Another scene was pushed on top of this one.
+    fn covered(&mut self, _ctx: &mut Context, _state: &mut S) {}
+    /// The scene on top of this one was popped.
+    fn uncovered(&mut self, _ctx: &mut Context, _state: &mut S) {}
+    /// Overlays are drawn on top of the scene below them, like a pause menu over the game.
+    fn is_overlay(&self) -> bool {
+        false
+    }
+
+    fn update(&mut self, _ctx: &mut Context, _state: &mut S) -> Transition<S> {
+        Transition::None
+    }
+    fn draw(&mut self, _ctx: &mut Context, _state: &mut S, _canvas: &mut Canvas<Window>, _alpha: f64) {}
+
+    fn key_pressed(&mut self, _ctx: &mut Context, _state: &mut S, _keycode: Keycode, _repeat: bool) -> Transition<S> {
+        Transition::None
+    }
+    fn key_released(&mut self, _ctx: &mut Context, _state: &mut S, _keycode: Keycode) -> Transition<S> {
+        Transition::None
+    }
+    fn mouse_pressed(&mut self, _ctx: &mut Context, _state: &mut S, _button: MouseButton, _x: f64, _y: f64)
+        -> Transition<S>
+    {
+        Transition::None
+    }
+    fn mouse_released(&mut self, _ctx: &mut Context, _state: &mut S, _button: MouseButton, _x: f64, _y: f64)
+        -> Transition<S>
+    {
+        Transition::None
+    }
+    fn mouse_moved(&mut self, _ctx: &mut Context, _state: &mut S, _x: f64, _y: f64) -> Transition<S> {
+        Transition::None
+    }
+    fn mouse_wheel(&mut self, _ctx: &mut Context, _state: &mut S, _x: i32, _y: i32) -> Transition<S> {
+        Transition::None
+    }
+    fn text_input(&mut self, _ctx: &mut Context, _state: &mut S, _text: &str) -> Transition<S> {
+        Transition::None
+    }
+    fn gamepad_pressed(&mut self, _ctx: &mut Context, _state: &mut S, _id: u32, _button: PadButton) -> Transition<S> {
+        Transition::None
+    }
+    fn gamepad_released(&mut self, _ctx: &mut Context, _state: &mut S, _id: u32, _button: PadButton)
+        -> Transition<S>
+    {
+        Transition::None
+    }
+    fn gamepad_axis(&mut self, _ctx: &mut Context, _state: &mut S, _id: u32, _axis: Axis, _value: f64)
+        -> Transition<S>
+    {
+        Transition::None
+    }
+}
+
+/// Scene callbacks a stack change makes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Lifecycle {
+    Enter,
+    Exit,
+    Covered,
+    Uncovered,
+}
+
+/// The stack changing part of a `Transition`, for any kind of scene.
+enum Change<T> {
+    Push(T),
+    Pop,
+    Replace(T),
+    Reset(T),
+}
+
+/// Runs a stack of scenes as an `App`.
+///
+/// The shared state is an `App` too and gets the callbacks that do not belong to a single
+/// scene: `init`, `update` (before the top scene's), focus changes, gamepad hotplug and `quit`.
+pub struct SceneStack<S> {
+    state: S,
+    scenes: Vec<Box<dyn Scene<S>>>,
+    /// Entered on `init`, the context is not around before that.
+    first: Option<Box<dyn Scene<S>>>,
+}
+
+impl<S> Transition<S> {
+    pub fn is_none(&self) -> bool {
+        matches!(self, Transition::None)
+    }
+}
+
+impl<S: App> SceneStack<S> {
+    pub fn new(state: S, first: Box<dyn Scene<S>>) -> Self {
+        Self {
+            state,
+            scenes: Vec::new(),
+            first: Some(first),
+        }
+    }
+
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+
+    pub fn state_mut(&mut self) -> &mut S {
+        &mut self.state
+    }
+
+    /// Names of the scenes, bottom first.
+    pub fn names(&self) -> Vec<&str> {
+        self.scenes.iter().map(|scene| scene.name()).collect()
+    }
+
+    pub fn apply(&mut self, ctx: &mut Context, transition: Transition<S>) {
+        let change = match transition {
+            Transition::None => return,
+            Transition::Push(scene) => Change::Push(scene),
+            Transition::Pop => Change::Pop,
+            Transition::Replace(scene) => Change::Replace(scene),
+            Transition::Reset(scene) => Change::Reset(scene),
+            Transition::Quit => {
+                ctx.quit();
+                return;
+            },
+        };
+
+        let state = &mut self.state;
+        let emptied = change_scenes(&mut self.scenes, change, |scene, lifecycle| match lifecycle {
+            Lifecycle::Enter => scene.enter(ctx, state),
+            Lifecycle::Exit => scene.exit(ctx, state),
+            Lifecycle::Covered => scene.covered(ctx, state),
+            Lifecycle::Uncovered => scene.uncovered(ctx, state),
+        });
+        if emptied {
+            ctx.quit();
+        }
+
+        debug!(scenes = ?self.names(), "Scenes changed");
+    }
+
+    /// Calls `f` with the top scene and applies the transition it returns.
+    fn top<F>(&mut self, ctx: &mut Context, f: F)
+        where F: FnOnce(&mut Box<dyn Scene<S>>, &mut Context, &mut S) -> Transition<S>
+    {
+        let transition = match self.scenes.last_mut() {
+            Some(scene) => f(scene, ctx, &mut self.state),
+            None => return,
+        };
+        self.apply(ctx, transition);
+    }
+}
+
+/// Applies `change` to `scenes`, calling `call` for every scene it affects in order.
+/// Returns whether the last scene was popped.
+fn change_scenes<T>(scenes: &mut Vec<T>, change: Change<T>, mut call: impl FnMut(&mut T, Lifecycle)) -> bool {
+    match change {
+        Change::Push(mut scene) => {
+            if let Some(top) = scenes.last_mut() {
+                call(top, Lifecycle::Covered);
+            }
+            call(&mut scene, Lifecycle::Enter);
+            scenes.push(scene);
+        },
+        Change::Pop => {
+            if let Some(mut top) = scenes.pop() {
+                call(&mut top, Lifecycle::Exit);
+            }
+            match scenes.last_mut() {
+                Some(top) => call(top, Lifecycle::Uncovered),
+                None => return true,
+            }
+        },
+        Change::Replace(mut scene) => {
+            if let Some(mut top) = scenes.pop() {
+                call(&mut top, Lifecycle::Exit);
+            }
+            call(&mut scene, Lifecycle::Enter);
+            scenes.push(scene);
+        },
+        Change::Reset(mut scene) => {
+            while let Some(mut top) = scenes.pop() {
+                call(&mut top, Lifecycle::Exit);
+            }
+            call(&mut scene, Lifecycle::Enter);
+            scenes.push(scene);
+        },
+    }
+
+    false
+}
+
+impl<S: App> App for SceneStack<S> {
+    fn init(&mut self, ctx: &mut Context) {
+        self.state.init(ctx);
+        if let Some(first) = self.first.take() {
+            self.apply(ctx, Transition::Push(first));
+        }
+    }
+
+    fn update(&mut self, ctx: &mut Context) {
+        self.state.update(ctx);
+        self.top(ctx, |scene, ctx, state| scene.update(ctx, state));
+    }
+
+    fn draw(&mut self, ctx: &mut Context, canvas: &mut Canvas<Window>, alpha: f64) {
+        // Everything from the topmost scene that is not an overlay up.
+        let bottom = self.scenes.iter().rposition(|scene| !scene.is_overlay()).unwrap_or(0);
+        for scene in &mut self.scenes[bottom..] {
+            scene.draw(ctx, &mut self.state, canvas, alpha);
+        }
+    }
+
+    fn key_pressed(&mut self, ctx: &mut Context, keycode: Keycode, repeat: bool) {
+        self.top(ctx, |scene, ctx, state| scene.key_pressed(ctx, state, keycode, repeat));
+    }
+
+    fn key_released(&mut self, ctx: &mut Context, keycode: Keycode) {
+        self.top(ctx, |scene, ctx, state| scene.key_released(ctx, state, keycode));
+    }
+
+    fn mouse_pressed(&mut self, ctx: &mut Context, button: MouseButton, x: f64, y: f64) {
+        self.top(ctx, |scene, ctx, state| scene.mouse_pressed(ctx, state, button, x, y));
+    }
+
+    fn mouse_released(&mut self, ctx: &mut Context, button: MouseButton, x: f64, y: f64) {
+        self.top(ctx, |scene, ctx, state| scene.mouse_released(ctx, state, button, x, y));
+    }
+
+    fn mouse_moved(&mut self, ctx: &mut Context, x: f64, y: f64) {
+        self.top(ctx, |scene, ctx, state| scene.mouse_moved(ctx, state, x, y));
+    }
+
+    fn mouse_wheel(&mut self, ctx: &mut Context, x: i32, y: i32) {
+        self.top(ctx, |scene, ctx, state| scene.mouse_wheel(ctx, state, x, y));
+    }
+
+    fn text_input(&mut self, ctx: &mut Context, text: &str) {
+        self.top(ctx, |scene, ctx, state| scene.text_input(ctx, state, text));
+    }
+
+    fn focus_lost(&mut self, ctx: &mut Context) {
+        self.state.focus_lost(ctx);
+    }
+
+    fn focus_gained(&mut self, ctx: &mut Context) {
+        self.state.focus_gained(ctx);
+    }
+
+    fn gamepad_added(&mut self, ctx: &mut Context, id: u32) {
+        self.state.gamepad_added(ctx, id);
+    }
+
+    fn gamepad_removed(&mut self, ctx: &mut Context, id: u32) {
+        self.state.gamepad_removed(ctx, id);
+    }
+
+    fn gamepad_pressed(&mut self, ctx: &mut Context, id: u32, button: PadButton) {
+        self.top(ctx, |scene, ctx, state| scene.gamepad_pressed(ctx, state, id, button));
+    }
+
+    fn gamepad_released(&mut self, ctx: &mut Context, id: u32, button: PadButton) {
+        self.top(ctx, |scene, ctx, state| scene.gamepad_released(ctx, state, id, button));
+    }
+
+    fn gamepad_axis(&mut self, ctx: &mut Context, id: u32, axis: Axis, value: f64) {
+        self.top(ctx, |scene, ctx, state| scene.gamepad_axis(ctx, state, id, axis, value));
+    }
+
+    fn quit(&mut self, ctx: &mut Context) {
+        while let Some(mut top) = self.scenes.pop() {
+            top.exit(ctx, &mut self.state);
+        }
+        self.state.quit(ctx);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{change_scenes, Change, Lifecycle};
+    use Lifecycle::*;
+
+    /// Applies `change` and returns whether the stack emptied and the callbacks it made.
+    fn apply(scenes: &mut Vec<&'static str>, change: Change<&'static str>) -> (bool, Vec<(&'static str, Lifecycle)>) {
+        let mut calls = Vec::new();
+        let emptied = change_scenes(scenes, change, |scene, lifecycle| calls.push((*scene, lifecycle)));
+
+        (emptied, calls)
+    }
+
+    #[test]
+    fn test_push_and_pop() {
+        let mut scenes = Vec::new();
+
+        assert_eq!(apply(&mut scenes, Change::Push("menu")), (false, vec![("menu", Enter)]));
+        assert_eq!(apply(&mut scenes, Change::Push("game")), (false, vec![("menu", Covered), ("game", Enter)]));
+        assert_eq!(scenes, ["menu", "game"]);
+
+        assert_eq!(apply(&mut scenes, Change::Pop), (false, vec![("game", Exit), ("menu", Uncovered)]));
+        assert_eq!(apply(&mut scenes, Change::Pop), (true, vec![("menu", Exit)]), "Popping the last scene quits");
+        assert!(scenes.is_empty());
+        assert_eq!(apply(&mut scenes, Change::Pop), (true, vec![]));
+    }
+
+    #[test]
+    fn test_replace_and_reset() {
+        let mut scenes = vec!["menu", "game", "pause"];
+
+        assert_eq!(
+            apply(&mut scenes, Change::Replace("settings")),
+            (false, vec![("pause", Exit), ("settings", Enter)]),
+            "The scene below is neither covered nor uncovered"
+        );
+        assert_eq!(scenes, ["menu", "game", "settings"]);
+
+        assert_eq!(
+            apply(&mut scenes, Change::Reset("disconnected")),
+            (false, vec![("settings", Exit), ("game", Exit), ("menu", Exit), ("disconnected", Enter)]),
+            "Scenes exit from the top"
+        );
+        assert_eq!(scenes, ["disconnected"]);
+
+        scenes.clear();
+        assert_eq!(apply(&mut scenes, Change::Replace("menu")), (false, vec![("menu", Enter)]));
+        assert_eq!(scenes, ["menu"]);
+    }
+}
@@ -0,0 +1,223 @@
+use serde::Deserialize;
+
+use sdl2::{
+    rect::Rect,
+    render::TextureCreator,
+    video::WindowContext,
+};
+
+use crate::assets::Asset;
+
+use std::collections::HashMap;
+
+/// Layout of a sprite sheet, loaded from a TOML file like `static/guy.toml`.
+///
+/// Frames of an animation are laid out in columns. Every hold type has a group of
+/// `directions` rows, one for each direction the sprite faces between two right angles,
+/// the sprite is rotated for the other quarters.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct SpriteSheet {
+    pub frame_width: u32,
+    pub frame_height: u32,
+    pub directions: usize,
+    /// Used by animators that do not set a hold type.
+    pub default_hold_type: String,
+    /// Index of the group of rows of each hold type.
+    pub hold_types: HashMap<String, usize>,
+    pub animations: HashMap<String, Animation>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub struct Animation {
+    /// First and last column, inclusive.
+    pub frames: [usize; 2],
+    /// Seconds each frame is shown for.
+    pub frame_duration: f64,
+    /// Animations that do not loop stop on their last frame.
+    #[serde(default)]
+    pub looping: bool,
+}
+
+/// Plays animations of a `SpriteSheet`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Animator {
+    animation: String,
+    hold_type: Option<String>,
+    /// Seconds since the animation started.
+    time: f64,
+}
+
+impl SpriteSheet {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let sheet = std::fs::read_to_string(path)
+            .map_err(|e| format!("cannot read {}: {}", path, e))?;
+        let sheet: Self = toml::from_str(&sheet)
+            .map_err(|e| format!("cannot parse {}: {}", path, e))?;
+
+        if sheet.directions == 0 {
+            return Err(format!("{} has no directions", path));
+        }
+        if let Some((name, _)) = sheet.animations.iter().find(|(_, a)| a.frames[0] > a.frames[1]) {
+            return Err(format!("animation {} in {} ends before it starts", name, path));
+        }
+
+        Ok(sheet)
+    }
+
+    /// Source rectangle of the frame `animator` is on, facing `direction` (below `directions`).
+    pub fn frame_rect(&self, animator: &Animator, direction: usize) -> Rect {
+        let column = self.animations.get(&animator.animation)
+            .map_or(0, |animation| animation.frame(animator.time));
+        let hold_type = animator.hold_type.as_ref().unwrap_or(&self.default_hold_type);
+        let group = self.hold_types.get(hold_type).copied().unwrap_or(0);
+        let row = group * self.directions + direction.min(self.directions - 1);
+
+        Rect::new(
+            (column as u32 * self.frame_width) as i32,
+            (row as u32 * self.frame_height) as i32,
+            self.frame_width,
+            self.frame_height,
+        )
+    }
+}
+
+impl Asset for SpriteSheet {
+    const KIND: &'static str = "sprite sheet";
+
+    fn load(_: &TextureCreator<WindowContext>, path: &str) -> Result<Self, String> {
+        SpriteSheet::load(path)
+    }
+
+    /// A single frame the size of the placeholder texture.
+    fn placeholder(_: &TextureCreator<WindowContext>) -> Self {
+        Self {
+            frame_width: 8,
+            frame_height: 8,
+            directions: 1,
+            default_hold_type: String::new(),
+            hold_types: HashMap::new(),
+            animations: HashMap::new(),
+        }
+    }
+}
+
+impl Animation {
+    /// Column shown `time` seconds after the animation started.
+    pub fn frame(&self, time: f64) -> usize {
+        let [first, last] = self.frames;
+        let count = last.saturating_sub(first) + 1;
+        let index = if self.frame_duration > 0.0 { (time / self.frame_duration) as usize } else { 0 };
+
+        if self.looping {
+            first + index % count
+        } else {
+            first + index.min(count - 1)
+        }
+    }
+
+    pub fn is_finished(&self, time: f64) -> bool {
+        let [first, last] = self.frames;
+        !self.looping && time >= (last.saturating_sub(first) + 1) as f64 * self.frame_duration
+    }
+}
+
+impl Animator {
+    pub fn new(animation: &str) -> Self {
+        Self {
+            animation: animation.into(),
+            hold_type: None,
+            time: 0.0,
+        }
+    }
+
+    pub fn animation(&self) -> &str {
+        &self.animation
+    }
+
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    /// Switches to `animation`, starting it over unless it is already playing.
+    pub fn play(&mut self, animation: &str) {
+        if self.animation != animation {
+            self.animation = animation.into();
+            self.time = 0.0;
+        }
+    }
+
+    /// `None` uses the sheet's default hold type.
+    pub fn set_hold_type(&mut self, hold_type: Option<&str>) {
+        self.hold_type = hold_type.map(Into::into);
+    }
+
+    pub fn advance(&mut self, dt: f64) {
+        self.time += dt;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sheet() -> SpriteSheet {
+        toml::from_str(r#"
+            frame_width = 20
+            frame_height = 10
+            directions = 4
+            default_hold_type = "pistol"
+
+            [hold_types]
+            unarmed = 0
+            pistol = 1
+
+            [animations.walk]
+            frames = [2, 4]
+            frame_duration = 0.5
+            looping = true
+
+            [animations.draw]
+            frames = [5, 6]
+            frame_duration = 0.5
+        "#).unwrap()
+    }
+
+    #[test]
+    fn test_animation_frame() {
+        let sheet = sheet();
+
+        let walk = sheet.animations["walk"];
+        assert_eq!(walk.frame(0.0), 2);
+        assert_eq!(walk.frame(1.0), 4);
+        assert_eq!(walk.frame(1.5), 2);
+        assert!(!walk.is_finished(10.0));
+
+        let draw = sheet.animations["draw"];
+        assert_eq!(draw.frame(0.6), 6);
+        assert_eq!(draw.frame(10.0), 6);
+        assert!(!draw.is_finished(0.9));
+        assert!(draw.is_finished(1.0));
+    }
+
+    #[test]
+    fn test_frame_rect() {
+        let sheet = sheet();
+        let mut animator = Animator::new("walk");
+
+        animator.advance(0.5);
+        assert_eq!(sheet.frame_rect(&animator, 2), Rect::new(60, 60, 20, 10));
+
+        // Playing the same animation does not restart it.
+        animator.play("walk");
+        assert_eq!(animator.time(), 0.5);
+        animator.play("draw");
+        assert_eq!(animator.time(), 0.0);
+
+        animator.set_hold_type(Some("unarmed"));
+        assert_eq!(sheet.frame_rect(&animator, 1), Rect::new(100, 10, 20, 10));
+
+        animator.play("missing");
+        animator.set_hold_type(Some("missing"));
+        assert_eq!(sheet.frame_rect(&animator, 9), Rect::new(0, 30, 20, 10));
+    }
+}
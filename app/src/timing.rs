@@ -0,0 +1,132 @@
+/// Runs updates at a fixed rate independent of the frame rate.
+///
+/// Every frame adds its duration to an accumulator, which is spent in whole steps. What is left
+/// is the interpolation alpha: how far the frame is between the last update and the next one.
+pub struct FixedTimestep {
+    step: f64,
+    accumulator: f64,
+    /// Most updates run in a single frame. When updates take longer than the step the game
+    /// would fall further behind every frame, instead the missing time is dropped.
+    max_updates: u32,
+}
+
+/// Frame time statistics for `Context`.
+#[derive(Default)]
+pub struct FrameStats {
+    frame_count: u64,
+    frame_time: f64,
+    fps: f64,
+    /// Frames and time since `fps` was last computed.
+    window_frames: u32,
+    window_time: f64,
+}
+
+impl FixedTimestep {
+    pub fn new(updates_per_second: u32, max_updates: u32) -> Self {
+        Self {
+            step: 1.0 / updates_per_second.max(1) as f64,
+            accumulator: 0.0,
+            max_updates: max_updates.max(1),
+        }
+    }
+
+    /// Length of an update in seconds.
+    pub fn step(&self) -> f64 {
+        self.step
+    }
+
+    /// Adds a frame of `frame_time` seconds and returns how many updates to run.
+    pub fn advance(&mut self, frame_time: f64) -> u32 {
+        self.accumulator += frame_time.max(0.0);
+
+        let mut updates = 0;
+        while self.accumulator >= self.step && updates < self.max_updates {
+            self.accumulator -= self.step;
+            updates += 1;
+        }
+        if self.accumulator >= self.step {
+            self.accumulator %= self.step;
+        }
+
+        updates
+    }
+
+    /// Progress towards the next update, from 0 to 1.
+    pub fn alpha(&self) -> f64 {
+        self.accumulator / self.step
+    }
+}
+
+impl FrameStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, frame_time: f64) {
+        self.frame_count += 1;
+        self.frame_time = frame_time;
+        self.window_frames += 1;
+        self.window_time += frame_time;
+
+        if self.window_time >= 1.0 {
+            self.fps = self.window_frames as f64 / self.window_time;
+            self.window_frames = 0;
+            self.window_time = 0.0;
+        }
+    }
+
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// Duration of the last frame in seconds.
+    pub fn frame_time(&self) -> f64 {
+        self.frame_time
+    }
+
+    /// Frames per second, averaged over about a second.
+    pub fn fps(&self) -> f64 {
+        self.fps
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FixedTimestep, FrameStats};
+
+    #[test]
+    fn test_fixed_timestep() {
+        let mut timestep = FixedTimestep::new(50, 5);
+
+        assert_eq!(timestep.advance(0.01), 0, "Short frames do not update");
+        assert!((timestep.alpha() - 0.5).abs() < 1e-9);
+        assert_eq!(timestep.advance(0.02), 1);
+        assert!((timestep.alpha() - 0.5).abs() < 1e-9, "The remainder carries over");
+
+        let mut total = 0;
+        for _ in 0..144 {
+            total += timestep.advance(1.0 / 144.0);
+        }
+        assert_eq!(total, 50, "The update rate does not depend on the frame rate");
+    }
+
+    #[test]
+    fn test_spiral_of_death_cap() {
+        let mut timestep = FixedTimestep::new(50, 5);
+
+        assert_eq!(timestep.advance(1.0), 5, "Long frames run at most max_updates");
+        assert!(timestep.alpha() < 1.0, "The missing time is dropped");
+        assert_eq!(timestep.advance(0.0), 0);
+    }
+
+    #[test]
+    fn test_frame_stats() {
+        let mut stats = FrameStats::new();
+        for _ in 0..31 {
+            stats.record(1.0 / 30.0);
+        }
+
+        assert_eq!(stats.frame_count(), 31);
+        assert!((stats.fps() - 30.0).abs() < 1e-6, "Got {}", stats.fps());
+    }
+}
@@ -0,0 +1,754 @@
+use serde::Deserialize;
+
+use sdl2::{
+    controller::Button as PadButton,
+    keyboard::Keycode,
+    mouse::MouseButton,
+    pixels::Color,
+    rect::Rect,
+    render::{
+        Canvas,
+        Texture,
+    },
+    video::Window,
+};
+
+use crate::{
+    assets::{Assets, Handle, Store},
+    font::BitmapFont,
+};
+
+#[derive(Deserialize)]
+struct NineSliceDescriptor {
+    /// Name of the texture in the asset manifest.
+    texture: String,
+    border: u32,
+}
+
+#[derive(Deserialize)]
+struct ThemeDescriptor {
+    padding: u32,
+    spacing: u32,
+    text_color: [u8; 3],
+    dim_color: [u8; 3],
+    accent_color: [u8; 3],
+    panel: NineSliceDescriptor,
+    button: NineSliceDescriptor,
+    button_focused: NineSliceDescriptor,
+    field: NineSliceDescriptor,
+}
+
+/// Texture drawn at any size by stretching its edges and center, the corners keep their size.
+pub struct NineSlice {
+    texture: Handle<Texture>,
+    border: u32,
+}
+
+/// Textures, colors and spacing of the widgets.
+pub struct Theme {
+    pub panel: NineSlice,
+    pub button: NineSlice,
+    pub button_focused: NineSlice,
+    pub field: NineSlice,
+    /// Space between a widget's edge and its text.
+    pub padding: u32,
+    /// Space between widgets.
+    pub spacing: u32,
+    pub text_color: Color,
+    pub dim_color: Color,
+    pub accent_color: Color,
+}
+
+/// Navigation input from the keyboard or a gamepad.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Nav {
+    Up,
+    Down,
+    Left,
+    Right,
+    Activate,
+    Back,
+}
+
+/// Immediate mode widgets.
+///
+/// Input is queued by the app's event callbacks and used by the next frame. Widgets are
+/// drawn and handled in one call, which returns what the player did with them, so a screen
+/// is a function drawing its widgets in order. Widgets are told apart by that order: the
+/// arrow keys and the d-pad move the focus through them and the mouse focuses the one it is over.
+pub struct Ui {
+    theme: Theme,
+    navs: Vec<Nav>,
+    text: String,
+    /// Backspace presses since the last frame.
+    erase: u32,
+    mouse: (f64, f64),
+    mouse_moved: bool,
+    mouse_down: bool,
+    clicked: Option<(f64, f64)>,
+    /// Index of the focused widget.
+    focus: usize,
+    /// Slider following the mouse while the button is held.
+    dragging: Option<usize>,
+}
+
+/// Widgets of a frame, laid out from the top of an area down. Finishes the frame when dropped.
+pub struct Frame<'a> {
+    ui: &'a mut Ui,
+    canvas: &'a mut Canvas<Window>,
+    font: &'a mut BitmapFont,
+    textures: &'a Store<Texture>,
+    area: Rect,
+    /// Top of the next widget.
+    y: i32,
+    next_id: usize,
+}
+
+impl NineSlice {
+    pub fn new(texture: Handle<Texture>, border: u32) -> Self {
+        Self { texture, border }
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas<Window>, textures: &Store<Texture>, dst: Rect) {
+        let texture = textures.get(&self.texture);
+        let query = texture.query();
+        for (src, dst) in nine_slices(query.width, query.height, self.border, dst) {
+            canvas.copy(texture, src, dst).unwrap();
+        }
+    }
+}
+
+/// Source and destination rectangles of the parts of a `width` x `height` texture drawn
+/// stretched to `dst`. Parts that end up empty are left out.
+fn nine_slices(width: u32, height: u32, border: u32, dst: Rect) -> Vec<(Rect, Rect)> {
+    let border = border.min(width / 2).min(height / 2);
+    // Corners of a destination smaller than them are shrunk.
+    let dst_border = border.min(dst.width() / 2).min(dst.height() / 2);
+
+    let columns = [
+        (0, border, 0, dst_border),
+        (border, width - 2 * border, dst_border, dst.width() - 2 * dst_border),
+        (width - border, border, dst.width() - dst_border, dst_border),
+    ];
+    let rows = [
+        (0, border, 0, dst_border),
+        (border, height - 2 * border, dst_border, dst.height() - 2 * dst_border),
+        (height - border, border, dst.height() - dst_border, dst_border),
+    ];
+
+    let mut slices = Vec::new();
+    for &(src_y, src_height, dst_y, dst_height) in &rows {
+        for &(src_x, src_width, dst_x, dst_width) in &columns {
+            if src_width == 0 || src_height == 0 || dst_width == 0 || dst_height == 0 {
+                continue;
+            }
+            slices.push((
+                Rect::new(src_x as i32, src_y as i32, src_width, src_height),
+                Rect::new(dst.x() + dst_x as i32, dst.y() + dst_y as i32, dst_width, dst_height),
+            ));
+        }
+    }
+
+    slices
+}
+
+impl Default for ThemeDescriptor {
+    /// Same as `static/ui.toml`.
+    fn default() -> Self {
+        let slice = |texture: &str, border| NineSliceDescriptor { texture: texture.into(), border };
+
+        Self {
+            padding: 3,
+            spacing: 1,
+            text_color: [255, 255, 255],
+            dim_color: [160, 160, 160],
+            accent_color: [255, 220, 120],
+            panel: slice("ui_panel", 3),
+            button: slice("ui_button", 2),
+            button_focused: slice("ui_button_focused", 2),
+            field: slice("ui_field", 2),
+        }
+    }
+}
+
+impl Theme {
+    /// Loads a theme from a TOML descriptor naming the textures in `assets`, see `static/ui.toml`.
+    pub fn load(assets: &mut Assets, descriptor_path: &str) -> Result<Self, String> {
+        let descriptor = std::fs::read_to_string(descriptor_path)
+            .map_err(|e| format!("cannot read {}: {}", descriptor_path, e))?;
+        let descriptor: ThemeDescriptor = toml::from_str(&descriptor)
+            .map_err(|e| format!("cannot parse {}: {}", descriptor_path, e))?;
+
+        Ok(Self::from_descriptor(assets, &descriptor))
+    }
+
+    /// The default theme, used when the descriptor cannot be loaded.
+    pub fn placeholder(assets: &mut Assets) -> Self {
+        Self::from_descriptor(assets, &ThemeDescriptor::default())
+    }
+
+    fn from_descriptor(assets: &mut Assets, descriptor: &ThemeDescriptor) -> Self {
+        let mut slice = |descriptor: &NineSliceDescriptor| {
+            NineSlice::new(assets.texture(&descriptor.texture), descriptor.border)
+        };
+        let color = |[r, g, b]: [u8; 3]| Color::RGB(r, g, b);
+
+        Self {
+            panel: slice(&descriptor.panel),
+            button: slice(&descriptor.button),
+            button_focused: slice(&descriptor.button_focused),
+            field: slice(&descriptor.field),
+            padding: descriptor.padding,
+            spacing: descriptor.spacing,
+            text_color: color(descriptor.text_color),
+            dim_color: color(descriptor.dim_color),
+            accent_color: color(descriptor.accent_color),
+        }
+    }
+}
+
+impl Nav {
+    pub fn from_key(keycode: Keycode) -> Option<Self> {
+        match keycode {
+            Keycode::Up => Some(Nav::Up),
+            Keycode::Down => Some(Nav::Down),
+            Keycode::Left => Some(Nav::Left),
+            Keycode::Right => Some(Nav::Right),
+            Keycode::Return | Keycode::KpEnter => Some(Nav::Activate),
+            Keycode::Escape => Some(Nav::Back),
+            _ => None,
+        }
+    }
+
+    pub fn from_pad(button: PadButton) -> Option<Self> {
+        match button {
+            PadButton::DPadUp => Some(Nav::Up),
+            PadButton::DPadDown => Some(Nav::Down),
+            PadButton::DPadLeft => Some(Nav::Left),
+            PadButton::DPadRight => Some(Nav::Right),
+            PadButton::A => Some(Nav::Activate),
+            PadButton::B => Some(Nav::Back),
+            _ => None,
+        }
+    }
+}
+
+/// Value of a slider over `track` with the mouse at `x`, rounded to whole steps.
+fn slider_value(x: f64, track: Rect, min: f64, max: f64, step: f64) -> f64 {
+    let fraction = ((x - track.x() as f64) / track.width().max(1) as f64).clamp(0.0, 1.0);
+    let value = min + fraction * (max - min);
+
+    round_to_step(value, min, max, step)
+}
+
+fn round_to_step(value: f64, min: f64, max: f64, step: f64) -> f64 {
+    let value = if step > 0.0 { min + ((value - min) / step).round() * step } else { value };
+
+    value.max(min).min(max)
+}
+
+impl Ui {
+    pub fn new(theme: Theme) -> Self {
+        Self {
+            theme,
+            navs: Vec::new(),
+            text: String::new(),
+            erase: 0,
+            mouse: (0.0, 0.0),
+            mouse_moved: false,
+            mouse_down: false,
+            clicked: None,
+            focus: 0,
+            dragging: None,
+        }
+    }
+
+    pub fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    /// Returns `false` for keys the widgets do not use.
+    pub fn key_pressed(&mut self, keycode: Keycode) -> bool {
+        if keycode == Keycode::Backspace {
+            self.erase += 1;
+            return true;
+        }
+        match Nav::from_key(keycode) {
+            Some(nav) => {
+                self.navs.push(nav);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Returns `false` for buttons the widgets do not use.
+    pub fn gamepad_pressed(&mut self, button: PadButton) -> bool {
+        match Nav::from_pad(button) {
+            Some(nav) => {
+                self.navs.push(nav);
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn text_input(&mut self, text: &str) {
+        self.text.push_str(text);
+    }
+
+    pub fn mouse_moved(&mut self, x: f64, y: f64) {
+        self.mouse = (x, y);
+        self.mouse_moved = true;
+    }
+
+    pub fn mouse_pressed(&mut self, button: MouseButton, x: f64, y: f64) {
+        if button == MouseButton::Left {
+            self.mouse = (x, y);
+            self.mouse_down = true;
+            self.clicked = Some((x, y));
+        }
+    }
+
+    pub fn mouse_released(&mut self, button: MouseButton, x: f64, y: f64) {
+        if button == MouseButton::Left {
+            self.mouse = (x, y);
+            self.mouse_down = false;
+            self.dragging = None;
+        }
+    }
+
+    /// Moves the focus to the first widget and drops queued input, for a new screen.
+    pub fn reset(&mut self) {
+        self.focus = 0;
+        self.dragging = None;
+        self.navs.clear();
+        self.text.clear();
+        self.erase = 0;
+        self.clicked = None;
+    }
+
+    /// Height of a single line widget.
+    pub fn row_height(&self, font: &BitmapFont) -> u32 {
+        font.line_height() + 2 * self.theme.padding
+    }
+
+    /// Height of a panel holding `rows` single line widgets.
+    pub fn panel_height(&self, font: &BitmapFont, rows: u32) -> u32 {
+        rows * (self.row_height(font) + self.theme.spacing) + 2 * self.theme.padding
+    }
+
+    /// Starts laying out widgets in `area`, the theme's textures are taken from `textures`.
+    pub fn frame<'a>(&'a mut self, canvas: &'a mut Canvas<Window>, font: &'a mut BitmapFont, textures: &'a Store<Texture>, area: Rect)
+        -> Frame<'a>
+    {
+        Frame {
+            y: area.y(),
+            ui: self,
+            canvas,
+            font,
+            textures,
+            area,
+            next_id: 0,
+        }
+    }
+
+    /// Draws a panel over `area` and lays out widgets inside it.
+    pub fn panel<'a>(&'a mut self, canvas: &'a mut Canvas<Window>, font: &'a mut BitmapFont, textures: &'a Store<Texture>, area: Rect)
+        -> Frame<'a>
+    {
+        self.theme.panel.draw(canvas, textures, area);
+
+        let padding = self.theme.padding;
+        let inner = Rect::new(
+            area.x() + padding as i32,
+            area.y() + padding as i32,
+            area.width().saturating_sub(2 * padding).max(1),
+            area.height().saturating_sub(2 * padding).max(1),
+        );
+        self.frame(canvas, font, textures, inner)
+    }
+}
+
+/// A `width` x `height` rectangle in the middle of `outer`.
+pub fn centered(outer: Rect, width: u32, height: u32) -> Rect {
+    Rect::new(
+        outer.x() + (outer.width() as i32 - width as i32) / 2,
+        outer.y() + (outer.height() as i32 - height as i32) / 2,
+        width,
+        height,
+    )
+}
+
+impl<'a> Frame<'a> {
+    pub fn theme(&self) -> &Theme {
+        &self.ui.theme
+    }
+
+    /// Width of the widgets.
+    pub fn width(&self) -> u32 {
+        self.area.width()
+    }
+
+    /// Whether Escape or B was pressed and not used by a widget.
+    pub fn back(&mut self) -> bool {
+        self.take_nav(Nav::Back)
+    }
+
+    /// Leaves `pixels` of empty space.
+    pub fn space(&mut self, pixels: u32) {
+        self.y += pixels as i32;
+    }
+
+    pub fn label(&mut self, text: &str) {
+        let color = self.ui.theme.text_color;
+        self.label_colored(text, color);
+    }
+
+    pub fn heading(&mut self, text: &str) {
+        let color = self.ui.theme.accent_color;
+        self.label_colored(text, color);
+    }
+
+    pub fn label_colored(&mut self, text: &str, color: Color) {
+        let padding = self.ui.theme.padding as i32;
+        let height = self.font.text_size(text).1 + 2 * padding as u32;
+        let row = self.next_row(height);
+        self.font.draw(self.canvas, text, row.x() + padding, row.y() + padding, color);
+    }
+
+    /// Returns `true` when the button was pressed.
+    pub fn button(&mut self, text: &str) -> bool {
+        let (id, row) = self.widget();
+        let pressed = self.activated(id, row);
+
+        self.draw_background(id, row);
+        let color = self.text_color(id);
+        let x = row.x() + (row.width() as i32 - self.font.text_size(text).0 as i32) / 2;
+        self.font.draw(self.canvas, text, x, row.y() + self.ui.theme.padding as i32, color);
+
+        pressed
+    }
+
+    /// Returns `true` when the player toggled `value`.
+    pub fn checkbox(&mut self, text: &str, value: &mut bool) -> bool {
+        let (id, row) = self.widget();
+        let changed = self.activated(id, row);
+        if changed {
+            *value = !*value;
+        }
+
+        self.draw_background(id, row);
+        let padding = self.ui.theme.padding as i32;
+        let size = self.font.line_height();
+        let check = Rect::new(row.right() - padding - size as i32, row.y() + padding, size, size);
+        self.ui.theme.field.draw(self.canvas, self.textures, check);
+        if *value {
+            self.canvas.set_draw_color(self.ui.theme.accent_color);
+            let inner = size.saturating_sub(4).max(1);
+            self.canvas.fill_rect(Rect::new(check.x() + 2, check.y() + 2, inner, inner)).unwrap();
+        }
+        let color = self.text_color(id);
+        self.font.draw(self.canvas, text, row.x() + padding, row.y() + padding, color);
+
+        changed
+    }
+
+    /// Returns `true` when the player changed `value`. Left and right move it by `step`,
+    /// the mouse sets it by clicking or dragging along the widget.
+    pub fn slider(&mut self, text: &str, value: &mut f64, min: f64, max: f64, step: f64) -> bool {
+        let (id, row) = self.widget();
+        let padding = self.ui.theme.padding as i32;
+        let track_width = row.width().saturating_sub(2 * padding as u32).max(1);
+        let track = Rect::new(row.x() + padding, row.bottom() - padding, track_width, 1);
+
+        let before = *value;
+        if self.focused(id) {
+            if self.take_nav(Nav::Left) {
+                *value = round_to_step(*value - step, min, max, step);
+            }
+            if self.take_nav(Nav::Right) {
+                *value = round_to_step(*value + step, min, max, step);
+            }
+        }
+        if self.clicked_in(row) {
+            self.ui.focus = id;
+            self.ui.dragging = Some(id);
+        }
+        if self.ui.dragging == Some(id) && self.ui.mouse_down {
+            *value = slider_value(self.ui.mouse.0, track, min, max, step);
+        }
+
+        self.draw_background(id, row);
+        let color = self.text_color(id);
+        self.font.draw(self.canvas, text, row.x() + padding, row.y() + padding, color);
+        let shown = format_value(*value, step);
+        let x = row.right() - padding - self.font.text_size(&shown).0 as i32;
+        self.font.draw(self.canvas, &shown, x, row.y() + padding, color);
+
+        let fraction = if max > min { (*value - min) / (max - min) } else { 0.0 };
+        let filled = (track.width() as f64 * fraction).round() as u32;
+        self.canvas.set_draw_color(self.ui.theme.dim_color);
+        self.canvas.fill_rect(track).unwrap();
+        if filled > 0 {
+            self.canvas.set_draw_color(self.ui.theme.accent_color);
+            self.canvas.fill_rect(Rect::new(track.x(), track.y(), filled, 1)).unwrap();
+        }
+
+        *value != before
+    }
+
+    /// Returns `true` when the player edited `value`, which takes typed text while focused.
+    pub fn text_field(&mut self, text: &str, value: &mut String, max_length: usize) -> bool {
+        let (id, row) = self.widget();
+        if self.clicked_in(row) {
+            self.ui.focus = id;
+        }
+
+        let mut changed = false;
+        if self.focused(id) {
+            for _ in 0..std::mem::take(&mut self.ui.erase) {
+                changed |= value.pop().is_some();
+            }
+            for c in std::mem::take(&mut self.ui.text).chars().filter(|c| !c.is_control()) {
+                if value.chars().count() >= max_length {
+                    break;
+                }
+                value.push(c);
+                changed = true;
+            }
+        }
+
+        let padding = self.ui.theme.padding as i32;
+        if self.focused(id) {
+            self.ui.theme.button_focused.draw(self.canvas, self.textures, row);
+        } else {
+            self.ui.theme.field.draw(self.canvas, self.textures, row);
+        }
+        let color = self.text_color(id);
+        self.font.draw(self.canvas, text, row.x() + padding, row.y() + padding, color);
+
+        // The end of the text stays visible, the cursor is there.
+        let x = row.x() + padding + self.font.text_size(text).0 as i32 + self.font.text_size(" ").0 as i32;
+        let glyph_width = self.font.text_size(" ").0.max(1) as i32;
+        let columns = ((row.right() - padding - x) / glyph_width).max(1) as usize - 1;
+        let mut shown = value.chars().rev().take(columns).collect::<Vec<_>>();
+        shown.reverse();
+        let mut shown = shown.into_iter().collect::<String>();
+        if self.focused(id) {
+            shown.push('_');
+        }
+        let value_color = self.ui.theme.text_color;
+        self.font.draw(self.canvas, &shown, x, row.y() + padding, value_color);
+
+        changed
+    }
+
+    /// Scrolling list showing `rows` of `items` at a time. Up and down move `selected` while
+    /// the list is focused, leaving it past either end. Returns the item chosen with
+    /// Return, A or a click.
+    pub fn list(&mut self, items: &[String], selected: &mut usize, rows: usize) -> Option<usize> {
+        let rows = rows.max(1);
+        let row_height = self.ui.row_height(self.font);
+        let spacing = self.ui.theme.spacing;
+        let id = self.next_id;
+        self.next_id += 1;
+        let area = self.next_row(rows as u32 * (row_height + spacing) - spacing);
+
+        if items.is_empty() {
+            return None;
+        }
+        *selected = (*selected).min(items.len() - 1);
+
+        let mut chosen = None;
+        if self.focused(id) {
+            if *selected > 0 && self.take_nav(Nav::Up) {
+                *selected -= 1;
+            }
+            if *selected + 1 < items.len() && self.take_nav(Nav::Down) {
+                *selected += 1;
+            }
+            if self.take_nav(Nav::Activate) {
+                chosen = Some(*selected);
+            }
+        }
+
+        let first = selected.saturating_sub(rows - 1);
+        for (i, item) in items.iter().enumerate().skip(first).take(rows) {
+            let row = Rect::new(
+                area.x(),
+                area.y() + ((i - first) as u32 * (row_height + spacing)) as i32,
+                area.width(),
+                row_height,
+            );
+            if self.hovered(row) {
+                self.ui.focus = id;
+                *selected = i;
+            }
+            if self.clicked_in(row) {
+                self.ui.focus = id;
+                *selected = i;
+                chosen = Some(i);
+            }
+
+            let color = if i == *selected {
+                if self.focused(id) {
+                    self.ui.theme.button_focused.draw(self.canvas, self.textures, row);
+                    self.ui.theme.accent_color
+                } else {
+                    self.ui.theme.button.draw(self.canvas, self.textures, row);
+                    self.ui.theme.text_color
+                }
+            } else {
+                self.ui.theme.dim_color
+            };
+            let padding = self.ui.theme.padding as i32;
+            self.font.draw(self.canvas, item, row.x() + padding, row.y() + padding, color);
+        }
+
+        chosen
+    }
+
+    /// Takes the rectangle of the next single line focusable widget.
+    fn widget(&mut self) -> (usize, Rect) {
+        let id = self.next_id;
+        self.next_id += 1;
+        let height = self.ui.row_height(self.font);
+        let row = self.next_row(height);
+        if self.hovered(row) {
+            self.ui.focus = id;
+        }
+
+        (id, row)
+    }
+
+    fn next_row(&mut self, height: u32) -> Rect {
+        let row = Rect::new(self.area.x(), self.y, self.area.width(), height.max(1));
+        self.y += (height + self.ui.theme.spacing) as i32;
+
+        row
+    }
+
+    fn focused(&self, id: usize) -> bool {
+        self.ui.focus == id
+    }
+
+    fn hovered(&self, rect: Rect) -> bool {
+        let (x, y) = self.ui.mouse;
+        self.ui.mouse_moved && rect.contains_point((x as i32, y as i32))
+    }
+
+    /// Takes the click when it landed in `rect`.
+    fn clicked_in(&mut self, rect: Rect) -> bool {
+        match self.ui.clicked {
+            Some((x, y)) if rect.contains_point((x as i32, y as i32)) => {
+                self.ui.clicked = None;
+                true
+            },
+            _ => false,
+        }
+    }
+
+    /// Pressed with Return, A or a click.
+    fn activated(&mut self, id: usize, rect: Rect) -> bool {
+        if self.clicked_in(rect) {
+            self.ui.focus = id;
+            return true;
+        }
+        self.focused(id) && self.take_nav(Nav::Activate)
+    }
+
+    fn take_nav(&mut self, nav: Nav) -> bool {
+        match self.ui.navs.iter().position(|&queued| queued == nav) {
+            Some(index) => {
+                self.ui.navs.remove(index);
+                true
+            },
+            None => false,
+        }
+    }
+
+    fn draw_background(&mut self, id: usize, rect: Rect) {
+        if self.focused(id) {
+            self.ui.theme.button_focused.draw(self.canvas, self.textures, rect);
+        } else {
+            self.ui.theme.button.draw(self.canvas, self.textures, rect);
+        }
+    }
+
+    fn text_color(&self, id: usize) -> Color {
+        if self.focused(id) {
+            self.ui.theme.accent_color
+        } else {
+            self.ui.theme.text_color
+        }
+    }
+}
+
+/// Shows as many decimals as `step` has.
+fn format_value(value: f64, step: f64) -> String {
+    let decimals = if step <= 0.0 || step.fract() == 0.0 {
+        0
+    } else {
+        (-step.fract().log10().floor()) as usize
+    };
+
+    format!("{:.*}", decimals, value)
+}
+
+impl<'a> Drop for Frame<'a> {
+    /// Moves the focus with the navigation the widgets did not use and drops the rest of the input.
+    fn drop(&mut self) {
+        let count = self.next_id;
+        for nav in self.ui.navs.drain(..) {
+            match nav {
+                Nav::Up if count > 0 => self.ui.focus = (self.ui.focus + count - 1) % count,
+                Nav::Down if count > 0 => self.ui.focus = (self.ui.focus + 1) % count,
+                _ => (),
+            }
+        }
+        self.ui.focus = self.ui.focus.min(count.saturating_sub(1));
+
+        self.ui.text.clear();
+        self.ui.erase = 0;
+        self.ui.clicked = None;
+        self.ui.mouse_moved = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_value, nine_slices, slider_value};
+    use sdl2::rect::Rect;
+
+    #[test]
+    fn test_nine_slices() {
+        let slices = nine_slices(9, 9, 3, Rect::new(10, 20, 30, 12));
+
+        assert_eq!(slices.len(), 9);
+        assert_eq!(slices[0], (Rect::new(0, 0, 3, 3), Rect::new(10, 20, 3, 3)), "Corners keep their size");
+        assert_eq!(slices[4], (Rect::new(3, 3, 3, 3), Rect::new(13, 23, 24, 6)), "The center stretches");
+        assert_eq!(slices[8], (Rect::new(6, 6, 3, 3), Rect::new(37, 29, 3, 3)));
+
+        let slices = nine_slices(9, 9, 3, Rect::new(0, 0, 6, 4));
+        assert!(slices.iter().all(|(_, dst)| dst.right() <= 6 && dst.bottom() <= 4), "Small areas shrink the corners");
+    }
+
+    #[test]
+    fn test_slider_value() {
+        let track = Rect::new(10, 0, 100, 1);
+
+        assert_eq!(slider_value(10.0, track, 0.0, 1.0, 0.1), 0.0);
+        assert_eq!(slider_value(60.0, track, 0.0, 10.0, 1.0), 5.0);
+        assert_eq!(slider_value(64.0, track, 0.0, 10.0, 1.0), 5.0, "Values round to whole steps");
+        assert_eq!(slider_value(500.0, track, 0.0, 10.0, 1.0), 10.0);
+        assert_eq!(slider_value(-5.0, track, 2.0, 10.0, 1.0), 2.0);
+    }
+
+    #[test]
+    fn test_format_value() {
+        assert_eq!(format_value(5.0, 1.0), "5");
+        assert_eq!(format_value(0.25, 0.05), "0.25");
+        assert_eq!(format_value(0.3, 0.1), "0.3");
+    }
+}
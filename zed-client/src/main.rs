@@ -1,3 +1,4 @@
+pub mod settings;
 pub mod util;
 
 mod zed;
@@ -7,8 +8,7 @@ use std::time::{Instant, Duration};
 use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
 use std::thread::sleep;
 use std::net::SocketAddr;
-
-const ADDR: &str = "127.0.0.1:10995";
+use tracing::{info, warn};
 
 fn socket_poller(should_run: Arc<AtomicBool>, mut socket: Socket) -> Result<(), laminar::ErrorKind> {
     while should_run.load(Ordering::Relaxed) {
@@ -21,15 +21,36 @@ fn socket_poller(should_run: Arc<AtomicBool>, mut socket: Socket) -> Result<(),
 
 fn main() -> Result<(), laminar::ErrorKind> {
     use zed::app::{Main, Net};
+    use zed::scenes::{Connecting, MainMenu};
+    use app::scene::{Scene, SceneStack};
     use zed_shared::protocol::SimpleProtocol;
     use zed_shared::protocol::register_messages;
+    use settings::{Settings, SETTINGS_PATH};
+
+    let (settings, settings_error) = match Settings::load(SETTINGS_PATH) {
+        Ok(settings) => (settings, None),
+        Err(e) => (Settings::default(), Some(e)),
+    };
 
-    let addr: String;
-    let local_addr: Option<String>;
+    let _log_guard = match zed_shared::logging::init(&settings.log) {
+        Ok(guard) => Some(guard),
+        Err(e) => {
+            eprintln!("Logging disabled, {}", e);
+            None
+        }
+    };
+    if let Some(e) = settings_error {
+        warn!("Using default settings, {}", e);
+    }
 
-    match std::env::args().skip(1).nth(0) {
-        Some(a) => addr = a.clone(),
-        None => addr = ADDR.to_string()
+    // A server on the command line is joined right away, otherwise the main menu opens.
+    let first: Box<dyn Scene<Main>> = match std::env::args().nth(1).map(|addr| addr.parse::<SocketAddr>()) {
+        Some(Ok(addr)) => Connecting::scene(addr),
+        Some(Err(e)) => {
+            warn!(error = %e, "Invalid server address");
+            MainMenu::scene()
+        },
+        None => MainMenu::scene(),
     };
 
     let mut socket = match std::env::args().skip(2).nth(0) {
@@ -37,14 +58,13 @@ fn main() -> Result<(), laminar::ErrorKind> {
         None => Socket::bind_any()
     }?;
 
-    println!("Client on on {}", socket.local_addr().unwrap());
+    info!(addr = %socket.local_addr().unwrap(), "Client socket bound");
     let (sender, receiver) = (socket.get_packet_sender(), socket.get_event_receiver());
 
     let mut protocol = SimpleProtocol::new();
     register_messages(&mut protocol);
 
-    println!("Server address: {}", addr);
-    let net = Net::new(&mut socket, addr.parse().unwrap(), protocol);
+    let net = Net::new(&mut socket, protocol);
 
 
     let should_run = Arc::new(AtomicBool::new(true));
@@ -54,9 +74,19 @@ fn main() -> Result<(), laminar::ErrorKind> {
     };
 
 
+    let window = app::app::WindowSettings {
+        width: settings.window_width,
+        height: settings.window_height,
+        scale: settings.scale,
+        pixel_perfect: settings.pixel_perfect,
+        fullscreen: settings.fullscreen,
+        ..Default::default()
+    };
+
     app::run(move |ctx| {
-            zed::app::Main::new(ctx, net)
+            SceneStack::new(Main::new(ctx, net, settings), first)
         },
+        window,
         should_run.clone()
     );
 
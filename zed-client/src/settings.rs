@@ -0,0 +1,397 @@
+use serde::{Serialize, Deserialize};
+use app::controller::{Controller, Input, DEFAULT_DEADZONE};
+use rand::random;
+use tracing::warn;
+use zed_shared::logging::LogConfig;
+use zed_shared::message::MAX_PLAYER_NAME_LENGTH;
+
+use std::path::Path;
+
+pub const SETTINGS_PATH: &str = "settings.toml";
+/// Servers remembered in `Settings::servers`.
+pub const MAX_SERVERS: usize = 10;
+/// Smallest window width and height, the window is created with the sizes from the settings.
+pub const MIN_WINDOW_SIZE: u32 = 160;
+/// Largest window width and height, SDL cannot create larger windows.
+pub const MAX_WINDOW_SIZE: u32 = 16384;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub player_name: String,
+    /// Identifies this player to servers across sessions, generated on first launch.
+    pub identity_token: String,
+    pub color: Option<[u8; 3]>,
+    pub last_server: Option<String>,
+    /// Recently joined servers, the latest first.
+    pub servers: Vec<String>,
+    pub window_width: u32,
+    pub window_height: u32,
+    pub fullscreen: bool,
+    /// Window pixels per game pixel.
+    pub scale: f64,
+    /// Only scale by whole numbers, leaving bars around the picture.
+    pub pixel_perfect: bool,
+    pub volume: u8,
+    /// Part of the gamepad sticks' range around the center that is ignored, from 0 to 0.9.
+    pub gamepad_deadzone: f64,
+    pub keys: KeyBindings,
+    pub log: LogConfig,
+}
+
+/// Inputs of every action, see `Input::parse` for the names.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub up: Binding,
+    pub down: Binding,
+    pub left: Binding,
+    pub right: Binding,
+    pub fire: Binding,
+    pub holster: Binding,
+    pub change_color: Binding,
+    pub chat: Binding,
+    pub scoreboard: Binding,
+    pub menu: Binding,
+}
+
+/// Names of the inputs bound to an action. A single name is read as well as a list.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "OneOrMany")]
+pub struct Binding(pub Vec<String>);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    Up,
+    Down,
+    Left,
+    Right,
+    Fire,
+    Holster,
+    ChangeColor,
+    Chat,
+    Scoreboard,
+    Menu,
+}
+
+pub const ACTIONS: [Action; 10] = [
+    Action::Up,
+    Action::Down,
+    Action::Left,
+    Action::Right,
+    Action::Fire,
+    Action::Holster,
+    Action::ChangeColor,
+    Action::Chat,
+    Action::Scoreboard,
+    Action::Menu,
+];
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            player_name: "Player".into(),
+            identity_token: generate_token(),
+            color: None,
+            last_server: None,
+            servers: Vec::new(),
+            window_width: 1000,
+            window_height: 1000,
+            fullscreen: false,
+            scale: 6.0,
+            pixel_perfect: true,
+            volume: 80,
+            gamepad_deadzone: DEFAULT_DEADZONE,
+            keys: KeyBindings::default(),
+            log: LogConfig::default(),
+        }
+    }
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            up: Binding::new(&["W", "Pad:dpup"]),
+            down: Binding::new(&["S", "Pad:dpdown"]),
+            left: Binding::new(&["A", "Pad:dpleft"]),
+            right: Binding::new(&["D", "Pad:dpright"]),
+            fire: Binding::new(&["Mouse:Left", "Pad:righttrigger"]),
+            holster: Binding::new(&["H", "Pad:y"]),
+            change_color: Binding::new(&["C", "Pad:x"]),
+            chat: Binding::new(&["Return"]),
+            scoreboard: Binding::new(&["Tab", "Pad:back"]),
+            menu: Binding::new(&["F1"]),
+        }
+    }
+}
+
+impl Settings {
+    /// Reads settings from `path`. A missing file is not an error, defaults are used instead.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        let mut settings: Settings = toml::from_str(&contents)
+            .map_err(|e| format!("cannot parse {}: {}", path.display(), e))?;
+        settings.sanitize();
+
+        Ok(settings)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), String> {
+        let path = path.as_ref();
+        let contents = toml::to_string_pretty(self)
+            .map_err(|e| format!("cannot serialize settings: {}", e))?;
+
+        std::fs::write(path, contents)
+            .map_err(|e| format!("cannot write {}: {}", path.display(), e))
+    }
+
+    /// Controller with the key bindings and the gamepad deadzone.
+    pub fn controller(&self) -> Controller<Action> {
+        let mut controller = self.keys.controller();
+        controller.deadzone = self.gamepad_deadzone;
+
+        controller
+    }
+
+    /// Makes `addr` the last server and moves it to the front of the recent servers.
+    pub fn remember_server(&mut self, addr: &str) {
+        self.last_server = Some(addr.to_string());
+        self.servers.retain(|server| server != addr);
+        self.servers.insert(0, addr.to_string());
+        self.servers.truncate(MAX_SERVERS);
+    }
+
+    fn sanitize(&mut self) {
+        self.player_name = self.player_name.trim().chars().take(MAX_PLAYER_NAME_LENGTH).collect();
+        if self.player_name.is_empty() {
+            self.player_name = Self::default().player_name;
+        }
+        if !(1.0..=16.0).contains(&self.scale) {
+            self.scale = Self::default().scale;
+        }
+        self.volume = self.volume.min(100);
+        self.window_width = self.window_width.max(MIN_WINDOW_SIZE).min(MAX_WINDOW_SIZE);
+        self.window_height = self.window_height.max(MIN_WINDOW_SIZE).min(MAX_WINDOW_SIZE);
+        if !(0.0..=0.9).contains(&self.gamepad_deadzone) {
+            self.gamepad_deadzone = Self::default().gamepad_deadzone;
+        }
+        if self.identity_token.trim().is_empty() {
+            self.identity_token = generate_token();
+        }
+        self.servers.truncate(MAX_SERVERS);
+    }
+}
+
+fn generate_token() -> String {
+    format!("{:032x}", random::<u128>())
+}
+
+impl KeyBindings {
+    pub fn get(&self, action: Action) -> &Binding {
+        match action {
+            Action::Up => &self.up,
+            Action::Down => &self.down,
+            Action::Left => &self.left,
+            Action::Right => &self.right,
+            Action::Fire => &self.fire,
+            Action::Holster => &self.holster,
+            Action::ChangeColor => &self.change_color,
+            Action::Chat => &self.chat,
+            Action::Scoreboard => &self.scoreboard,
+            Action::Menu => &self.menu,
+        }
+    }
+
+    pub fn get_mut(&mut self, action: Action) -> &mut Binding {
+        match action {
+            Action::Up => &mut self.up,
+            Action::Down => &mut self.down,
+            Action::Left => &mut self.left,
+            Action::Right => &mut self.right,
+            Action::Fire => &mut self.fire,
+            Action::Holster => &mut self.holster,
+            Action::ChangeColor => &mut self.change_color,
+            Action::Chat => &mut self.chat,
+            Action::Scoreboard => &mut self.scoreboard,
+            Action::Menu => &mut self.menu,
+        }
+    }
+
+    /// Binds all actions, names that are not inputs are skipped.
+    pub fn controller(&self) -> Controller<Action> {
+        let mut controller = Controller::new();
+        for &action in &ACTIONS {
+            for name in &self.get(action).0 {
+                match Input::parse(name) {
+                    Some(input) => controller.bind(input, action),
+                    None => warn!(%name, ?action, "Unknown input in key bindings"),
+                }
+            }
+        }
+
+        controller
+    }
+
+    /// Binds `input` to `action`, taking it away from other actions. It replaces the inputs
+    /// of the same device, so rebinding a key keeps the gamepad binding and the other way around.
+    pub fn rebind(&mut self, action: Action, input: Input) {
+        let name = input.to_string();
+        for &other in &ACTIONS {
+            self.get_mut(other).0.retain(|bound| Input::parse(bound) != Some(input));
+        }
+
+        let binding = self.get_mut(action);
+        binding.0.retain(|bound| match Input::parse(bound) {
+            Some(bound) => is_gamepad(bound) != is_gamepad(input),
+            None => false,
+        });
+        binding.0.push(name);
+    }
+}
+
+fn is_gamepad(input: Input) -> bool {
+    matches!(input, Input::Pad(_) | Input::Trigger(_))
+}
+
+impl Binding {
+    pub fn new(names: &[&str]) -> Self {
+        Binding(names.iter().map(|&name| name.to_string()).collect())
+    }
+}
+
+impl From<OneOrMany> for Binding {
+    fn from(names: OneOrMany) -> Self {
+        match names {
+            OneOrMany::One(name) => Binding(vec![name]),
+            OneOrMany::Many(names) => Binding(names),
+        }
+    }
+}
+
+impl std::fmt::Display for Binding {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.0.is_empty() {
+            write!(f, "-")
+        } else {
+            write!(f, "{}", self.0.join(", "))
+        }
+    }
+}
+
+impl Action {
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Up => "Up",
+            Action::Down => "Down",
+            Action::Left => "Left",
+            Action::Right => "Right",
+            Action::Fire => "Fire",
+            Action::Holster => "Holster",
+            Action::ChangeColor => "Color",
+            Action::Chat => "Chat",
+            Action::Scoreboard => "Scores",
+            Action::Menu => "Menu",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Settings, Action, Binding, MAX_PLAYER_NAME_LENGTH, MAX_SERVERS, MAX_WINDOW_SIZE, MIN_WINDOW_SIZE};
+    use app::controller::Input;
+    use sdl2::{keyboard::Keycode, mouse::MouseButton, controller::Button as PadButton};
+
+    #[test]
+    fn test_settings_roundtrip() {
+        let mut settings = Settings::default();
+        settings.player_name = "Zed".into();
+        settings.color = Some([10, 20, 30]);
+        settings.last_server = Some("127.0.0.1:10995".into());
+
+        let serialized = toml::to_string_pretty(&settings).unwrap();
+        let deserialized: Settings = toml::from_str(&serialized).unwrap();
+
+        assert_eq!(settings, deserialized);
+    }
+
+    #[test]
+    fn test_sanitize() {
+        let mut settings: Settings = toml::from_str(r#"
+            player_name = "   "
+            scale = 0.0
+            volume = 250
+            gamepad_deadzone = 1.5
+            window_width = 0
+            window_height = 4000000000
+        "#).unwrap();
+        settings.sanitize();
+
+        assert_eq!(settings.player_name, Settings::default().player_name);
+        assert_eq!(settings.scale, Settings::default().scale);
+        assert_eq!(settings.volume, 100);
+        assert_eq!((settings.window_width, settings.window_height), (MIN_WINDOW_SIZE, MAX_WINDOW_SIZE));
+        assert_eq!(settings.gamepad_deadzone, Settings::default().gamepad_deadzone);
+        assert_eq!(settings.identity_token.len(), 32, "Identity token is generated when missing");
+
+        settings.player_name = "A very long player name indeed".into();
+        settings.sanitize();
+        assert_eq!(settings.player_name.chars().count(), MAX_PLAYER_NAME_LENGTH);
+    }
+
+    #[test]
+    fn test_remember_server() {
+        let mut settings = Settings::default();
+        for port in 0..MAX_SERVERS + 2 {
+            settings.remember_server(&format!("127.0.0.1:{}", port));
+        }
+        settings.remember_server("127.0.0.1:5");
+
+        assert_eq!(settings.last_server.as_deref(), Some("127.0.0.1:5"));
+        assert_eq!(settings.servers.len(), MAX_SERVERS);
+        assert_eq!(settings.servers[0], "127.0.0.1:5", "Joining again moves the server to the front");
+        assert_eq!(settings.servers.iter().filter(|server| *server == "127.0.0.1:5").count(), 1);
+        assert_eq!(settings.servers[1], format!("127.0.0.1:{}", MAX_SERVERS + 1));
+    }
+
+    #[test]
+    fn test_key_bindings() {
+        let settings: Settings = toml::from_str(r#"
+            [keys]
+            holster = "space"
+            fire = ["Mouse:Left", "Left Shift"]
+        "#).unwrap();
+        let controller = settings.keys.controller();
+
+        assert_eq!(controller.action(Input::Key(Keycode::W)), Some(Action::Up));
+        assert_eq!(controller.action(Input::Key(Keycode::Space)), Some(Action::Holster), "A single name is read");
+        assert_eq!(controller.action(Input::Key(Keycode::H)), None);
+        assert_eq!(controller.action(Input::Key(Keycode::LShift)), Some(Action::Fire));
+        assert_eq!(controller.action(Input::Mouse(MouseButton::Left)), Some(Action::Fire));
+    }
+
+    #[test]
+    fn test_rebind() {
+        let mut settings = Settings::default();
+        settings.keys.up = Binding::new(&["W", "Up", "Pad:dpup"]);
+
+        settings.keys.rebind(Action::Up, Input::Key(Keycode::H));
+        assert_eq!(settings.keys.up, Binding::new(&["Pad:dpup", "H"]), "Gamepad bindings are kept");
+        assert_eq!(settings.keys.holster, Binding::new(&["Pad:y"]), "The input is taken from other actions");
+
+        settings.keys.rebind(Action::Up, Input::Pad(PadButton::Y));
+        assert_eq!(settings.keys.up, Binding::new(&["H", "Pad:y"]));
+    }
+}
@@ -4,7 +4,7 @@ pub fn calculate_frame_from_angle(angle: f64, discrete_directions: usize) -> (us
     let angle = (angle % (2.0*PI) + 2.0*PI) % (2.0*PI);
 
     let rad_per_direction = FRAC_PI_2 / (discrete_directions as f64);
-    let frame = (angle / rad_per_direction).round() % 4.0;
+    let frame = (angle / rad_per_direction).round() % discrete_directions as f64;
     let angle = ((angle + rad_per_direction/2.0) / FRAC_PI_2).trunc() * FRAC_PI_2 % (2.0*PI);
 
     (frame as usize , angle)
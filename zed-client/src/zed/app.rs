@@ -1,11 +1,19 @@
 use app::{
     app::{run, App, Context},
-    controller
+    assets::{Assets, Handle, DEFAULT_MANIFEST},
+    camera::Camera,
+    controller::{Controller, Input},
+    font::{Align, BitmapFont, DrawText, TextStyle},
+    sprite::{Animator, SpriteSheet},
+    ui::{self, Frame, Theme, Ui},
 };
 use bottles::{Dispatcher, Queue};
 
 use zed_shared::protocol::{SimpleProtocol, Protocol};
 use zed_shared::message;
+use zed_shared::components::{Direction, Model, MovementInput, Player, Position, Velocity};
+use zed_shared::map::{MapSource, TileMap, DEFAULT_MAPS_DIR};
+use zed_shared::simulation::{self, PredictionHistory, PLAYER_HITBOX};
 
 use laminar::{Socket, Packet, SocketEvent};
 use legion::entity::Entity;
@@ -14,8 +22,9 @@ use legion::filter::filter_fns::tag;
 use legion::borrow::RefMut;
 
 use sdl2::{
-    image::LoadTexture,
+    controller::{Axis, Button as PadButton},
     keyboard::Keycode,
+    mouse::MouseButton,
     pixels::Color,
     rect::Rect,
     rect::Point,
@@ -32,87 +41,112 @@ use std::collections::hash_map::Entry;
 use std::sync::{Arc, Mutex};
 use std::rc::Rc;
 use rand::random;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 
 use crossbeam_channel::{Sender, Receiver};
 use std::net::SocketAddr;
 
 use serde::{Serialize, de::DeserializeOwned};
+use tracing::{debug, info, trace, warn};
 
+use crate::settings::{Settings, Action, SETTINGS_PATH};
+use super::chat::ChatBox;
+use super::map::MapRenderer;
 
-#[derive(Clone, Copy, PartialEq)]
-struct Position {
-    x: f64,
-    y: f64
-}
-
-#[derive(Clone, Copy, PartialEq)]
-struct Direction {
-    x: f64,
-    y: f64
-}
-
-impl Direction {
-    pub fn angle(&self) -> f64 {
-        self.y.atan2(self.x)
-    }
-    
-    pub fn set_angle(&mut self, angle: f64) {
-        self.x = angle.cos();
-        self.y = angle.sin();
-    }
-}
 
-enum HitBox {
-    Circle { radius: f64 },
-    Box { width: f64, height: f64 }
-}
+/// Half size of the area the local player moves in without moving the camera.
+const CAMERA_DEADZONE: (f64, f64) = (12.0, 8.0);
+const CAMERA_SMOOTHING: f64 = 0.15;
+/// Left stick deflection along an axis that counts as moving that way,
+/// splitting the stick's range into eight directions.
+const STICK_THRESHOLD: f64 = 0.38;
+/// Widest a menu panel gets, narrower screens make it narrower.
+const MENU_WIDTH: u32 = 160;
+/// Seconds between checks for changed asset files in development builds.
+const ASSET_RELOAD_INTERVAL: f64 = 1.0;
 
+/// How a `Model` is drawn, only exists on the client. Frames are picked by the entity's `Animator`.
 #[derive(Clone, PartialEq)]
-struct Model {
-    texture_static: String,
-    texture_blend: String,
-    color: Color,
-    frame_width: usize,
-    frame_height: usize,
-    frame: (usize, usize)
+struct Sprite {
+    sheet: Handle<SpriteSheet>,
+    texture_static: Handle<Texture>,
+    texture_blend: Handle<Texture>,
 }
 
-#[derive(Clone, Copy, PartialEq)]
-struct Player {
-    holster: bool,
-    id: Option<usize>,
+impl Sprite {
+    fn for_model(model: &Model, assets: &mut Assets) -> Self {
+        Self {
+            sheet: assets.sprite(&model.name),
+            texture_static: assets.texture(&format!("{}_static", model.name)),
+            texture_blend: assets.texture(&format!("{}_blend", model.name)),
+        }
+    }
 }
 
 #[derive(Clone, Copy, PartialEq)]
 struct LocalPlayer {}
 
+/// Position before the last update, drawing blends it with the current one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct PreviousPosition(Position);
+
+impl PreviousPosition {
+    fn lerp(&self, current: &Position, alpha: f64) -> (f64, f64) {
+        (
+            self.0.x + (current.x - self.0.x) * alpha,
+            self.0.y + (current.y - self.0.y) * alpha,
+        )
+    }
+}
+
 pub struct Net {
     sender: Sender<Packet>,
     receiver: Receiver<SocketEvent>,
-    addr: SocketAddr,
+    /// Server the client talks to, packets from anywhere else are dropped.
+    addr: Cell<Option<SocketAddr>>,
     protocol: RefCell<SimpleProtocol>,
     queue: RefCell<Queue<Main>>,
 }
 
 pub struct Main {
-    controller: controller::Controller,
+    controller: Controller<Action>,
+    /// The player aims with the mouse until the right stick is used, and back when the mouse moves.
+    mouse_aim: bool,
     counter: f64,
-    textures: HashMap<String, Texture>,
+    assets: Assets,
+    /// Time of the last check for changed asset files.
+    assets_checked: f64,
+    font: Handle<BitmapFont>,
+    ui: Ui,
     ecs: legion::world::World,
+    /// Local player positions sent to the server, compared with its answers.
+    predictions: PredictionHistory,
+    camera: Camera,
+    map: TileMap,
+    map_renderer: MapRenderer,
+    /// Name and hash of the server's map while it is being downloaded.
+    awaited_map: Option<(String, u64)>,
+
+    settings: Settings,
+    chat: ChatBox,
 
     local_player_id: Option<usize>,
+    roster: HashMap<usize, String>,
+    announced_name: String,
+    /// Reason of the last disconnect, set until the player connects again.
+    /// Scenes show the disconnected screen when it appears.
+    disconnected: Option<String>,
 
     net: Rc<Net>,
     others: Arc<Mutex<HashMap<usize, Entity>>>
 }
 
 impl Net {
-    pub fn new(socket: &mut Socket, addr: SocketAddr, protocol: SimpleProtocol) -> Self {
+    pub fn new(socket: &mut Socket, protocol: SimpleProtocol) -> Self {
         Self {
             sender: socket.get_packet_sender(),
             receiver: socket.get_event_receiver(),
-            addr,
+            addr: Cell::new(None),
             protocol: RefCell::new(protocol),
             queue: RefCell::new(Queue::new()),
         }
@@ -130,27 +164,49 @@ impl Net {
         self.queue.borrow_mut().subscribe(self.protocol.borrow_mut().dispatcher_mut(), f);
     }
 
+    /// Talks to the server at `addr` from now on, `None` to stop talking to any.
+    pub fn set_addr(&self, addr: Option<SocketAddr>) {
+        self.addr.set(addr);
+    }
+
+    pub fn addr(&self) -> Option<SocketAddr> {
+        self.addr.get()
+    }
+
     pub fn send_reliable_unordered<T: 'static + Serialize>(&self, message: T) {
-        self.protocol.borrow_mut().send_reliable_unordered(&self.sender, self.addr, message);
+        match self.addr.get() {
+            Some(addr) => self.protocol.borrow_mut().send_reliable_unordered(&self.sender, addr, message),
+            None => trace!("Not connected, dropping a message"),
+        }
     }
 
     pub fn poll(main: &mut Main) {
-        while let Ok(event) = main.net.receiver.try_recv() {
+        let net = Rc::clone(&main.net);
+
+        while let Ok(event) = net.receiver.try_recv() {
             match event {
                 SocketEvent::Connect(addr) => {
-                    println!("Connected to {}", addr);
+                    info!(%addr, "Connected");
                 },
                 SocketEvent::Timeout(addr) => {
-                    println!("Timeout of {}", addr);
+                    warn!(%addr, "Connection timed out");
+                    if Some(addr) == net.addr.get() && main.disconnected.is_none() {
+                        main.disconnect("Connection to the server timed out.".into());
+                    }
                 },
                 SocketEvent::Packet(packet) => {
-                    main.net.protocol.borrow_mut().receive(packet.payload());
+                    if Some(packet.addr()) != net.addr.get() {
+                        trace!(addr = %packet.addr(), "Dropping a packet from another server");
+                        continue;
+                    }
+                    if let Err(e) = net.protocol.borrow_mut().receive(packet.payload()) {
+                        warn!(error = %e, "Dropping undecodable packet");
+                    }
                 },
                 _ => ()
             }
         }
 
-        let net = Rc::clone(&main.net);
         let mut queue = net.queue.borrow_mut();
 
         queue.poll(main);
@@ -158,133 +214,191 @@ impl Net {
 }
 
 impl Main {
-    pub fn new(ctx: &mut Context, net: Net) -> Self {
-        let mut images = HashMap::new();
-        images.insert("guy_static".into(), ctx.texture_creator.load_texture("static/guy_static.png").unwrap());
-        images.insert("guy_blend".into(), ctx.texture_creator.load_texture("static/guy_blend.png").unwrap());
+    pub fn new(ctx: &mut Context, net: Net, mut settings: Settings) -> Self {
+        let mut assets = Assets::load(ctx.texture_creator, DEFAULT_MANIFEST);
+        let font = assets.font("default");
+        let theme = Theme::load(&mut assets, "static/ui.toml").unwrap_or_else(|e| {
+            warn!(error = %e, "Cannot load the UI theme");
+            Theme::placeholder(&mut assets)
+        });
+
+        let [r, g, b] = *settings.color.get_or_insert_with(|| [random(), random(), random()]);
+
+        let model = Model {
+            name: "guy".into(),
+            color: [r, g, b],
+        };
+        // Shown until the server says which map it runs.
+        let map = TileMap::load(DEFAULT_MAPS_DIR, "default").unwrap_or_else(|e| {
+            warn!(error = %e, "Cannot load the default map");
+            TileMap::empty("default", 20, 20, 8)
+        });
+        let map_renderer = MapRenderer::new(&mut assets, &map, DEFAULT_MAPS_DIR);
 
         let mut ecs = legion::world::World::new();
         ecs.insert((LocalPlayer {},),
 [(
             Position { x: 16.0, y: 16.0 },
+            PreviousPosition(Position { x: 16.0, y: 16.0 }),
             Direction { x: 0.0, y: 0.0 },
-            Model {
-                texture_static: "guy_static".into(),
-                texture_blend: "guy_blend".into(),
-                color: Color::RGB(random(), random(), random()),
-                frame_width: 20,
-                frame_height: 20,
-                frame: (1, 1)
-            },
+            Velocity::default(),
+            MovementInput::default(),
+            PLAYER_HITBOX,
+            Sprite::for_model(&model, &mut assets),
+            Animator::new("aim"),
+            model,
             Player {
                 id: None,
                 holster: false,
             }
         )].iter().cloned());
 
+        assets.load_pending(ctx.texture_creator);
+
+        let mut camera = Camera::new(map.pixel_width(), map.pixel_height());
+        camera.deadzone = CAMERA_DEADZONE;
+        camera.smoothing = CAMERA_SMOOTHING;
+
         Self {
             counter: 0.0,
-            controller: controller::Controller::new(),
-            textures: images,
+            controller: settings.controller(),
+            mouse_aim: true,
+            assets,
+            assets_checked: 0.0,
+            font,
+            ui: Ui::new(theme),
             ecs: ecs,
+            predictions: PredictionHistory::new(),
+            camera,
+            map,
+            map_renderer,
+            awaited_map: None,
+            settings,
+            chat: ChatBox::new(),
             net: Rc::new(net),
             others: Arc::new(Mutex::new(HashMap::new())),
             
             local_player_id: None,
+            roster: HashMap::new(),
+            announced_name: String::new(),
+            disconnected: None,
         }
     }
 
     fn control_player(&mut self, ctx: &mut Context) {
-        let dt = 1.0/60.0;
-        let spd = 50.0;
-
-        for (mut position) in <(Write<Position>)>::query().filter(tag::<LocalPlayer>()).iter(&mut self.ecs) {
-            let mut dx: f64 = 0.0;
-            let mut dy: f64 = 0.0;
-
-            if self.controller.left.pressed() {
-                dx -= 1.0;
-            }
-            if self.controller.right.pressed() {
-                dx += 1.0;
-            }
-            if self.controller.up.pressed() {
-                dy -= 1.0;
-            }
-            if self.controller.down.pressed() {
-                dy += 1.0;
-            }
+        let dt = ctx.dt();
 
-            let len = (dx.powi(2) + dy.powi(2)).sqrt();
+        // Sticks are not buttons and do not get released when the chat opens.
+        let playing = !self.chat.is_open();
+        let (stick_x, stick_y) = if playing { self.controller.direction() } else { (0.0, 0.0) };
+        for (mut input) in <(Write<MovementInput>)>::query().filter(tag::<LocalPlayer>()).iter(&mut self.ecs) {
+            *input = MovementInput {
+                up: self.controller.pressed(Action::Up) || stick_y < -STICK_THRESHOLD,
+                down: self.controller.pressed(Action::Down) || stick_y > STICK_THRESHOLD,
+                left: self.controller.pressed(Action::Left) || stick_x < -STICK_THRESHOLD,
+                right: self.controller.pressed(Action::Right) || stick_x > STICK_THRESHOLD,
+            };
+        }
 
-            if len > 0.0 {
-                dx /= len;
-                dy /= len;
+        simulation::movement(&mut self.ecs, dt);
+        simulation::collision(&mut self.ecs, &self.map);
 
-                position.x += dx * dt * spd;
-                position.y += dy * dt * spd;
-            }
+        let aim = self.controller.aim().filter(|_| playing);
+        if aim.is_some() {
+            self.mouse_aim = false;
         }
-
         let (mx, my) = ctx.get_mouse_pos();
+        let (mx, my) = self.camera.screen_to_world(mx, my);
         for (mut direction, position) in <(Write<Direction>, Read<Position>)>::query().filter(tag::<LocalPlayer>())
             .iter(&mut self.ecs)
         {
-            direction.x = mx - position.x;
-            direction.y = my - position.y;
+            match aim {
+                Some((x, y)) => {
+                    direction.x = x;
+                    direction.y = y;
+                },
+                // A released stick keeps the last direction.
+                None if !self.mouse_aim => (),
+                None => {
+                    direction.x = mx - position.x;
+                    direction.y = my - position.y;
+                },
+            }
+        }
+    }
+
+    /// Picks the animation of every player and moves the animations on.
+    fn animate(&mut self, dt: f64) {
+        for (mut animator, player) in <(Write<Animator>, Read<Player>)>::query().iter(&mut self.ecs) {
+            animator.play(if player.holster { "holster" } else { "aim" });
+        }
+        for mut animator in Write::<Animator>::query().iter(&mut self.ecs) {
+            animator.advance(dt);
+        }
+    }
+
+    /// Remembers where everything was before the update moves it.
+    fn store_previous_positions(&mut self) {
+        for (position, mut previous) in <(Read<Position>, Write<PreviousPosition>)>::query().iter(&mut self.ecs) {
+            previous.0 = *position;
         }
+    }
 
+    /// Follows the local player, keeping the view inside the map. Runs every frame on the
+    /// interpolated position so the view moves smoothly at any frame rate.
+    fn update_camera(&mut self, ctx: &mut Context, alpha: f64) {
+        let (width, height) = ctx.screen_size();
+        self.camera.set_viewport(width as f64, height as f64);
+        self.camera.set_bounds(Some((0.0, 0.0, self.map.pixel_width(), self.map.pixel_height())));
 
-        for (mut model, player) in <(Write<Model>, Read<Player>)>::query().iter(&mut self.ecs) {
-            model.frame.0 = if player.holster { 1 } else { 0 };
+        let query = <(Read<Position>, Read<PreviousPosition>)>::query().filter(tag::<LocalPlayer>());
+        for (position, previous) in query.iter(&mut self.ecs) {
+            let (x, y) = previous.lerp(&position, alpha);
+            self.camera.follow(x, y, ctx.frame_time());
         }
     }
 
-    fn draw_models(&mut self, ctx: &mut Context, canvas: &mut Canvas<Window>) {
+    fn draw_models(&mut self, ctx: &mut Context, canvas: &mut Canvas<Window>, alpha: f64) {
         use std::f64::consts::PI;
 
 
-        for (position, direction, model) in <(Read<Position>, Read<Direction>, Read<Model>)>::query().iter(&mut self.ecs) {
+        let query = <(Read<Position>, Read<PreviousPosition>, Read<Direction>, Read<Model>, Read<Sprite>, Read<Animator>)>::query();
+        for (position, previous, direction, model, sprite, animator) in query.iter(&mut self.ecs) {
             let angle = direction.angle();
+            let sheet = self.assets.sprites.get(&sprite.sheet);
+            let (frame_width, frame_height) = (sheet.frame_width, sheet.frame_height);
 
-            let frame = crate::util::calculate_frame_from_angle(angle, 4);
+            let frame = crate::util::calculate_frame_from_angle(angle, sheet.directions);
             let angle_deg = frame.1 * 180.0 / PI;
 
-            let (frame_x, frame_y) = model.frame;
-            let frame_y = frame_y*4 + frame.0;
+            let (x, y) = previous.lerp(&position, alpha);
+            let (x, y) = self.camera.world_to_screen(x, y);
 
-            let x = position.x;
-            let y = position.y;
-
-            let qs = Rect::new(
-                (model.frame_width * frame_x) as i32,
-                (model.frame_height * (frame_y)) as i32,
-                model.frame_width as u32,
-                model.frame_height as u32
-            );
+            let qs = sheet.frame_rect(&animator, frame.0);
             let qd = Rect::new(
-                x as i32 - model.frame_width as i32 / 2,
-                y as i32 - model.frame_height as i32 / 2,
-                model.frame_width as u32,
-                model.frame_height as u32
+                x as i32 - frame_width as i32 / 2,
+                y as i32 - frame_height as i32 / 2,
+                frame_width,
+                frame_height
             );
 
-            let img_static = self.textures.get(&model.texture_static).unwrap();
+            let img_static = self.assets.textures.get(&sprite.texture_static);
             canvas.set_draw_color(Color::RGB(255, 255, 255));
             canvas.copy_ex(
                 img_static,
                 qs,
                 qd,
                 angle_deg,
-                Point::new(model.frame_width as i32 / 2, model.frame_height as i32 / 2),
+                Point::new(frame_width as i32 / 2, frame_height as i32 / 2),
                 false,
                 false)
                 .unwrap();
 
-            let img_blend = self.textures.get_mut(&model.texture_blend).unwrap();
-            img_blend.set_color_mod(model.color.r, model.color.g, model.color.b);
+            let img_blend = self.assets.textures.get_mut(&sprite.texture_blend);
+            let [r, g, b] = model.color;
+            img_blend.set_color_mod(r, g, b);
             canvas.copy_ex(img_blend, qs, qd, angle_deg,
-                           Point::new(model.frame_width as i32 / 2, model.frame_height as i32 / 2),
+                           Point::new(frame_width as i32 / 2, frame_height as i32 / 2),
                            false, false)
                 .unwrap();
 
@@ -297,6 +411,14 @@ impl Main {
         }
     }
 
+    fn apply_settings(&mut self) {
+        if let Some([r, g, b]) = self.settings.color {
+            for mut model in Write::<Model>::query().filter(tag::<LocalPlayer>()).iter(&mut self.ecs) {
+                model.color = [r, g, b];
+            }
+        }
+    }
+
     fn send_player_pos(&mut self) {
         let local_player_id = match self.local_player_id {
             None => return,
@@ -305,79 +427,471 @@ impl Main {
 
         use zed_shared::message::both::PlayerStatus;
         
-        let q = <(Read<Position>, Read<Direction>, Read<Model>, Read<Player>)>::query().filter(tag::<LocalPlayer>());
-        for (pos, dir, model, player) in q.iter(&mut self.ecs) {
+        let q = <(Read<Position>, Read<Direction>, Read<Model>, Read<Player>, Read<MovementInput>)>::query()
+            .filter(tag::<LocalPlayer>());
+        for (pos, dir, model, player, input) in q.iter(&mut self.ecs) {
+            let [r, g, b] = model.color;
             let status = PlayerStatus {
+                sequence: self.predictions.record(*pos),
                 x: pos.x,
                 y: pos.y,
                 angle: dir.angle(),
                 player_id: local_player_id,
-                r: model.color.r,
-                g: model.color.g,
-                b: model.color.b,
-                holster: player.holster, 
+                r,
+                g,
+                b,
+                holster: player.holster,
+                input: *input,
             };
             
             self.net.send_reliable_unordered(status);
         }
     }
 
-    fn receive_greeting(&mut self, greeting: Rc<message::from_client::Greeting>) {
-        println!("Received greeting from {}", greeting.name);
+    fn draw_name_tags(&mut self, canvas: &mut Canvas<Window>, alpha: f64) {
+        let font = self.assets.fonts.get_mut(&self.font);
+        let query = <(Read<Position>, Read<PreviousPosition>, Read<Sprite>, Read<Player>)>::query();
+        for (position, previous, sprite, player) in query.iter(&mut self.ecs) {
+            let name = match player.id.and_then(|id| self.roster.get(&id)) {
+                Some(name) => name,
+                None => continue,
+            };
+
+            let (x, y) = previous.lerp(&position, alpha);
+            let (x, y) = self.camera.world_to_screen(x, y);
+            let frame_height = self.assets.sprites.get(&sprite.sheet).frame_height;
+            let y = y as i32 - frame_height as i32 / 2 - font.line_height() as i32 - 2;
+
+            let style = TextStyle {
+                align: Align::Center,
+                outline: Some(Color::RGB(0, 0, 0)),
+                ..TextStyle::new(Color::RGB(255, 255, 255))
+            };
+            canvas.draw_text(font, name, x as i32, y, &style);
+        }
+    }
+
+    /// Tells the server about a new player name.
+    pub fn announce_name(&mut self) {
+        if self.settings.player_name == self.announced_name {
+            return;
+        }
+
+        self.announced_name = self.settings.player_name.clone();
+        self.net.send_reliable_unordered(message::from_client::ChangeName {
+            name: self.announced_name.clone(),
+        });
+    }
+
+    fn send_greeting(&mut self) {
+        debug!("Sending greeting");
+        self.net.send_reliable_unordered(
+            message::from_client::Greeting {
+                name: self.settings.player_name.clone(),
+                token: self.settings.identity_token.clone(),
+            }
+        );
+        self.announced_name = self.settings.player_name.clone();
+    }
+
+    pub fn ui(&mut self) -> &mut Ui {
+        &mut self.ui
+    }
+
+    /// Lays out `rows` rows of widgets in a panel in the middle of the screen.
+    pub fn ui_panel<'a>(&'a mut self, canvas: &'a mut Canvas<Window>, rows: u32) -> Frame<'a> {
+        let viewport = canvas.viewport();
+        let width = viewport.width().saturating_sub(8).min(MENU_WIDTH);
+        let font = self.assets.fonts.get_mut(&self.font);
+        let height = self.ui.panel_height(font, rows);
+        let area = ui::centered(Rect::new(0, 0, viewport.width(), viewport.height()), width, height);
+
+        self.ui.panel(canvas, font, &self.assets.textures, area)
+    }
+
+    /// Characters fitting on a line of `ui_panel`.
+    pub fn ui_columns(&self, canvas: &Canvas<Window>) -> usize {
+        let width = canvas.viewport().width().saturating_sub(8).min(MENU_WIDTH);
+        let padding = self.ui.theme().padding;
+
+        (width.saturating_sub(4 * padding) / self.assets.fonts.get(&self.font).text_size(" ").0.max(1)).max(1) as usize
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    pub fn settings_mut(&mut self) -> &mut Settings {
+        &mut self.settings
+    }
+
+    /// Puts the settings changed with `settings_mut` into effect.
+    pub fn settings_changed(&mut self, ctx: &mut Context) {
+        self.apply_settings();
+        self.controller = self.settings.controller();
+        ctx.set_scale(self.settings.scale);
+        ctx.set_pixel_perfect(self.settings.pixel_perfect);
+    }
+
+    /// The action `input` is bound to.
+    pub fn action(&self, input: Input) -> Option<Action> {
+        self.controller.action(input)
+    }
+
+    /// The press of a gamepad trigger crossing the threshold, handled like a button press.
+    pub fn trigger_pulled(&mut self, id: u32, axis: Axis, value: f64) -> Option<Input> {
+        match self.controller.axis_motion(id, axis, value) {
+            Some((input, true)) => Some(input),
+            _ => None,
+        }
+    }
+
+    /// Address of the server joined last, kept after a disconnect to join it again.
+    pub fn server_addr(&self) -> Option<SocketAddr> {
+        self.net.addr()
+    }
+
+    /// The server accepted the greeting.
+    pub fn is_joined(&self) -> bool {
+        self.local_player_id.is_some()
+    }
+
+    pub fn disconnect_reason(&self) -> Option<&str> {
+        self.disconnected.as_deref()
+    }
+
+    /// Leaves the current server, if any, and greets the one at `addr`.
+    ///
+    /// Greeting the server the client talks to already is enough, the server takes it as joining again.
+    pub fn connect(&mut self, addr: SocketAddr) {
+        info!(%addr, "Connecting");
+        if self.net.addr() == Some(addr) {
+            self.reset_session();
+            self.disconnected = None;
+        } else {
+            self.leave();
+        }
+        self.net.set_addr(Some(addr));
+        self.settings.remember_server(&addr.to_string());
+        self.send_greeting();
+    }
+
+    /// Tells the server the player leaves, stops talking to it and forgets everything learned from it.
+    pub fn leave(&mut self) {
+        self.net.send_reliable_unordered(message::from_client::Leave {});
+        self.reset_session();
+        self.disconnected = None;
+        self.net.set_addr(None);
+    }
+
+    /// Forgets everything learned from the server and keeps `reason` until the player connects again.
+    pub fn disconnect(&mut self, reason: String) {
+        warn!(%reason, "Disconnected");
+        self.chat.push(format!("Disconnected: {}", reason), Color::RGB(255, 120, 120));
+        self.reset_session();
+        self.disconnected = Some(reason);
+    }
+
+    fn reset_session(&mut self) {
+        let others = self.others.lock().unwrap().drain().map(|(_, entity)| entity).collect::<Vec<_>>();
+        for entity in others {
+            self.ecs.delete(entity);
+        }
+        self.local_player_id = None;
+        self.roster.clear();
+        self.awaited_map = None;
+        self.predictions.clear();
+        self.controller.release_all();
+    }
+
+    /// The part of an update that only runs while the game scene is on top.
+    pub fn play(&mut self, ctx: &mut Context) {
+        self.perform_actions();
+        self.control_player(ctx);
+        self.send_player_pos();
+    }
+
+    pub fn is_chat_open(&self) -> bool {
+        self.chat.is_open()
+    }
+
+    /// Lets go of everything held, for when the game stops getting input.
+    pub fn release_input(&mut self) {
+        self.controller.release_all();
+    }
+
+    /// Names of everyone on the server while the scoreboard action is held.
+    fn draw_scoreboard(&mut self, canvas: &mut Canvas<Window>) {
+        if !self.controller.pressed(Action::Scoreboard) || self.roster.is_empty() {
+            return;
+        }
+
+        let mut players = self.roster.iter().collect::<Vec<_>>();
+        players.sort_by_key(|&(&id, _)| id);
+
+        let font = self.assets.fonts.get_mut(&self.font);
+        let viewport = canvas.viewport();
+        let line_height = font.line_height() as i32 + 2;
+        let width = players.iter().map(|(_, name)| font.text_size(name).0).max().unwrap_or(0) + 8;
+        let height = (players.len() as i32 + 1) * line_height + 6;
+        let (x, y) = ((viewport.width() as i32 - width as i32) / 2, 4);
+
+        canvas.set_draw_color(Color::RGB(24, 28, 36));
+        canvas.fill_rect(Rect::new(x, y, width, height as u32)).unwrap();
+        font.draw(canvas, "PLAYERS", x + 4, y + 4, Color::RGB(255, 220, 120));
+        for (i, (&id, name)) in players.into_iter().enumerate() {
+            let color = if Some(id) == self.local_player_id {
+                Color::RGB(255, 255, 255)
+            } else {
+                Color::RGB(180, 180, 180)
+            };
+            font.draw(canvas, name, x + 4, y + 4 + (i as i32 + 1) * line_height, color);
+        }
+    }
+
+    fn remove_player(&mut self, player_id: usize) {
+        let entity = self.others.lock().unwrap().remove(&player_id);
+        if let Some(entity) = entity {
+            self.ecs.delete(entity);
+        }
+    }
+
+    fn receive_chat_message(&mut self, message: Rc<message::from_server::ChatMessage>) {
+        use message::ChatChannel;
+
+        let sender = match message.player_id {
+            None => {
+                self.chat.push(message.text.clone(), Color::RGB(255, 220, 120));
+                return;
+            },
+            Some(id) => self.roster.get(&id).cloned().unwrap_or_else(|| format!("Player {}", id)),
+        };
+
+        match message.channel {
+            ChatChannel::All => self.chat.push(format!("{}: {}", sender, message.text), Color::RGB(255, 255, 255)),
+            ChatChannel::Team => self.chat.push(format!("[team] {}: {}", sender, message.text), Color::RGB(140, 230, 140)),
+        }
+    }
+
+    fn receive_roster(&mut self, roster: Rc<message::from_server::Roster>) {
+        self.roster = roster.players.iter()
+            .map(|entry| (entry.player_id, entry.name.clone()))
+            .collect();
+    }
+
+    fn receive_player_joined(&mut self, message: Rc<message::from_server::PlayerJoined>) {
+        info!(player_id = message.player_id, name = %message.name, "Player joined");
+        self.roster.insert(message.player_id, message.name.clone());
+    }
+
+    fn receive_player_left(&mut self, message: Rc<message::from_server::PlayerLeft>) {
+        if let Some(name) = self.roster.remove(&message.player_id) {
+            info!(player_id = message.player_id, %name, "Player left");
+        }
+        self.remove_player(message.player_id);
+    }
+
+    fn receive_player_renamed(&mut self, message: Rc<message::from_server::PlayerRenamed>) {
+        if let Some(name) = self.roster.insert(message.player_id, message.name.clone()) {
+            info!(player_id = message.player_id, old_name = %name, name = %message.name, "Player renamed");
+        }
     }
 
     fn receive_greeting_response(&mut self, response: Rc<message::from_server::GreetingResponse>) {
-        println!("Connected to server with player_id {}", response.player_id);
+        info!(server = %response.server_name, player_id = response.player_id, map = %response.map, "Joined server");
+        if !response.motd.is_empty() {
+            self.chat.push(response.motd.clone(), Color::RGB(255, 220, 120));
+        }
         self.local_player_id = Some(response.player_id);
+        self.chat.set_max_length(response.max_chat_length);
+        self.use_map(&response.map, response.map_hash);
     }
 
-    fn receive_player_status(&mut self, message: Rc<message::both::PlayerStatus>) {
-        let mut others = self.others.lock().unwrap();
+    fn receive_kicked(&mut self, message: Rc<message::from_server::Kicked>) {
+        self.disconnect(message.reason.clone());
+    }
+
+    fn receive_server_shutdown(&mut self, message: Rc<message::from_server::ServerShutdown>) {
+        self.disconnect(message.reason.clone());
+    }
+
+    fn receive_map_change(&mut self, message: Rc<message::from_server::MapChange>) {
+        info!(map = %message.name, "Map changed");
+        self.chat.push(format!("Map changed to {}", message.name), Color::RGB(255, 220, 120));
+        self.use_map(&message.name, message.hash);
+    }
+
+    /// Switches to the server's map, downloading it when the local copy is missing or differs.
+    fn use_map(&mut self, name: &str, hash: u64) {
+        match TileMap::load(DEFAULT_MAPS_DIR, name) {
+            Ok(map) if map.hash() == hash => {
+                self.set_map(map);
+                return;
+            },
+            Ok(_) => info!(map = %name, "Local map differs from the server's, downloading it"),
+            Err(e) => info!(map = %name, error = %e, "Downloading map"),
+        }
+
+        self.awaited_map = Some((name.into(), hash));
+        self.net.send_reliable_unordered(message::from_client::MapRequest { name: name.into() });
+    }
 
+    fn set_map(&mut self, map: TileMap) {
+        debug!(map = %map.name, "Using map");
+        self.map_renderer = MapRenderer::new(&mut self.assets, &map, DEFAULT_MAPS_DIR);
+        self.map = map;
+        self.awaited_map = None;
+    }
+
+    fn receive_map_data(&mut self, message: Rc<message::from_server::MapData>) {
+        let hash = match &self.awaited_map {
+            Some((name, hash)) if *name == message.name => *hash,
+            _ => return,
+        };
+
+        let source = MapSource {
+            file_name: message.file_name.clone(),
+            contents: message.contents.clone(),
+        };
+        match TileMap::parse(&message.name, source) {
+            Ok(map) if map.hash() == hash => self.set_map(map),
+            Ok(_) => self.disconnect(format!("Downloaded map {} does not match the server's.", message.name)),
+            Err(e) => self.disconnect(format!("Cannot load map {}: {}", message.name, e)),
+        }
+    }
+
+    fn receive_rcon_response(&mut self, message: Rc<message::from_server::RconResponse>) {
+        for line in message.output.lines() {
+            self.chat.push(line.into(), Color::RGB(150, 200, 255));
+        }
+    }
+
+    /// Sends a chat message, or a remote console request for input starting with `/rcon`.
+    fn send_chat(&mut self, message: message::from_client::ChatSend) {
+        let text = message.text.trim();
+        if !text.starts_with("/rcon") {
+            self.net.send_reliable_unordered(message);
+            return;
+        }
+
+        let args = text["/rcon".len()..].trim();
+        if args.starts_with("login ") {
+            self.net.send_reliable_unordered(message::from_client::RconAuth {
+                password: args["login ".len()..].trim().into(),
+            });
+        } else if !args.is_empty() {
+            self.chat.push(format!("rcon> {}", args), Color::RGB(150, 200, 255));
+            self.net.send_reliable_unordered(message::from_client::RconCommand {
+                command: args.into(),
+            });
+        } else {
+            self.chat.push("Usage: /rcon login <password>, /rcon <command>".into(), Color::RGB(150, 200, 255));
+        }
+    }
+
+    /// Handles a key, mouse or gamepad button press that is not meant for the chat.
+    fn input_pressed(&mut self, input: Input) {
+        // Opening the chat decides where the following key presses go, so it cannot wait for the next tick.
+        if self.controller.action(input) == Some(Action::Chat) {
+            self.chat.open();
+            self.controller.release_all();
+            return;
+        }
+        self.controller.input(input, true);
+    }
+
+    /// Actions that happen once per press, read from the controller's snapshot of this tick.
+    fn perform_actions(&mut self) {
+        if self.controller.rising(Action::Holster) {
+            for mut player in Write::<Player>::query().filter(tag::<LocalPlayer>()).iter(&mut self.ecs) {
+                player.holster = !player.holster;
+            }
+        }
+        if self.controller.rising(Action::ChangeColor) {
+            self.settings.color = Some([random(), random(), random()]);
+            self.apply_settings();
+        }
+    }
+
+    /// Moves the local player by how far its prediction was off from the server's simulation
+    /// of the status the server answers, when the server does not accept it.
+    fn correct_prediction(&mut self, status: &message::both::PlayerStatus) {
+        let server_position = Position { x: status.x, y: status.y };
+        let (dx, dy) = match self.predictions.reconcile(status.sequence, server_position) {
+            Some(offset) => offset,
+            None => return,
+        };
+
+        debug!(dx, dy, "Correcting the local player position");
+        let query = <(Write<Position>, Write<PreviousPosition>)>::query().filter(tag::<LocalPlayer>());
+        for (mut position, mut previous) in query.iter(&mut self.ecs) {
+            position.x += dx;
+            position.y += dy;
+            // Jump instead of sliding across the map.
+            *previous = PreviousPosition(*position);
+        }
+    }
+
+    fn receive_player_status(&mut self, message: Rc<message::both::PlayerStatus>) {
         match self.local_player_id {
-            Some(id) if message.player_id == id => return,
+            Some(id) if message.player_id == id => {
+                self.correct_prediction(&message);
+                return;
+            },
             _ => ()
         };
+
+        let mut others = self.others.lock().unwrap();
          
         match others.get(&message.player_id) {
             None => {
+                let model = Model {
+                    name: "guy".into(),
+                    color: [message.r, message.g, message.b],
+                };
                 let entities = self.ecs.insert((), [(
                         Position { x: message.x, y: message.y },
-                        Direction { x: 0.0, y: 0.0 },
-                        Model {
-                            texture_static: "guy_static".into(),
-                            texture_blend: "guy_blend".into(),
-                            color: Color::RGB(message.r, message.g, message.b),
-                            frame_width: 20,
-                            frame_height: 20,
-                            frame: (1, 1)
-                        },
+                        PreviousPosition(Position { x: message.x, y: message.y }),
+                        Direction::from_angle(message.angle),
+                        // Like on the server, so contacts with the local player are split the same way.
+                        Velocity::default(),
+                        PLAYER_HITBOX,
+                        Sprite::for_model(&model, &mut self.assets),
+                        Animator::new("aim"),
+                        model,
                         Player {
                             id: Some(message.player_id),
                             holster: message.holster,
                         }
                     )].iter().cloned());
                     
-                println!("Creating networked player {}", message.player_id);
+                debug!(player_id = message.player_id, "Creating networked player");
                 others.insert(message.player_id, entities[0]);
             },
             Some(&entity) => {
-                println!("Updating networked player {}", message.player_id);
+                trace!(
+                    player_id = message.player_id,
+                    x = message.x,
+                    y = message.y,
+                    angle = message.angle,
+                    "Updating networked player"
+                );
                 {
-                    dbg!(message.x, message.y);
                     let mut pos = self.ecs.get_component_mut::<Position>(entity).unwrap();
                     pos.x = message.x;
                     pos.y = message.y;
                 }
                 {
-                    dbg!(message.angle);
                     let mut dir = self.ecs.get_component_mut::<Direction>(entity).unwrap();
                     dir.set_angle(message.angle);
                 }
                 {
-                    dbg!(message.r, message.g, message.b);
                     let mut model = self.ecs.get_component_mut::<Model>(entity).unwrap();
-                    model.color = Color::RGB(message.r, message.g, message.b);
+                    model.color = [message.r, message.g, message.b];
+                }
+                {
+                    let mut player = self.ecs.get_component_mut::<Player>(entity).unwrap();
+                    player.holster = message.holster;
                 }
             }
         };
@@ -389,62 +903,151 @@ impl App for Main {
         self.net.register::<message::from_server::GreetingResponse>();
         self.net.subscribe(Self::receive_greeting_response);
 
-        self.net.register::<message::from_client::Greeting>();
-        self.net.subscribe(Self::receive_greeting);
+        self.net.register::<message::from_server::Roster>();
+        self.net.subscribe(Self::receive_roster);
+
+        self.net.register::<message::from_server::PlayerJoined>();
+        self.net.subscribe(Self::receive_player_joined);
+
+        self.net.register::<message::from_server::PlayerLeft>();
+        self.net.subscribe(Self::receive_player_left);
+
+        self.net.register::<message::from_server::PlayerRenamed>();
+        self.net.subscribe(Self::receive_player_renamed);
+
+        self.net.register::<message::from_server::ChatMessage>();
+        self.net.subscribe(Self::receive_chat_message);
+
+        self.net.register::<message::from_server::Kicked>();
+        self.net.subscribe(Self::receive_kicked);
+
+        self.net.register::<message::from_server::ServerShutdown>();
+        self.net.subscribe(Self::receive_server_shutdown);
+
+        self.net.register::<message::from_server::MapChange>();
+        self.net.subscribe(Self::receive_map_change);
+
+        self.net.register::<message::from_server::MapData>();
+        self.net.subscribe(Self::receive_map_data);
+
+        self.net.register::<message::from_server::RconResponse>();
+        self.net.subscribe(Self::receive_rcon_response);
 
         self.net.register::<message::both::PlayerStatus>();
         self.net.subscribe(Self::receive_player_status);
-
-        println!("Sending greeting.");
-        self.net.send_reliable_unordered(
-            message::from_client::Greeting {
-                name: "Marcin Szymczak".into(),
-            }
-        );
     }
 
     fn update(&mut self, ctx: &mut Context) {
-        self.counter += 1.0/60.0;
-        self.control_player(ctx);
-        self.send_player_pos();
-        
-        Net::poll(self);
-    }
+        self.counter += ctx.dt();
+        self.controller.update();
+        self.store_previous_positions();
+        self.animate(ctx.dt());
 
-    fn key_pressed(&mut self, ctx: &mut Context, keycode: Keycode) {
-        match keycode {
-            Keycode::A => self.controller.left.update(true),
-            Keycode::D => self.controller.right.update(true),
-            Keycode::S => self.controller.down.update(true),
-            Keycode::W => self.controller.up.update(true),
+        Net::poll(self);
 
-            Keycode::H => {
-                for mut player in Write::<Player>::query().filter(tag::<LocalPlayer>()).iter(&mut self.ecs) {
-                    player.holster = !player.holster;
-                }
-            },
+        self.assets.load_pending(ctx.texture_creator);
+        if self.counter - self.assets_checked >= ASSET_RELOAD_INTERVAL {
+            self.assets_checked = self.counter;
+            if cfg!(debug_assertions) {
+                self.assets.reload_changed(ctx.texture_creator);
+            }
+            let unloaded = self.assets.collect();
+            if unloaded > 0 {
+                debug!(count = unloaded, "Unloaded unused assets");
+            }
+        }
+    }
 
-            Keycode::C => {
-                use rand::random;
-                for (mut model) in <(Write<Model>)>::query().filter(tag::<LocalPlayer>()).iter(&mut self.ecs) {
-                    model.color = Color::RGB(random(), random(), random());
-                }
+    fn key_pressed(&mut self, ctx: &mut Context, keycode: Keycode, repeat: bool) {
+        if self.chat.is_open() {
+            if let Some(message) = self.chat.key_pressed(keycode) {
+                self.send_chat(message);
             }
-            _ => ()
+            return;
         }
+        if repeat {
+            return;
+        }
+
+        self.input_pressed(Input::Key(keycode));
     }
 
     fn key_released(&mut self, ctx: &mut Context, keycode: Keycode) {
-        match keycode {
-            Keycode::A => self.controller.left.update(false),
-            Keycode::D => self.controller.right.update(false),
-            Keycode::S => self.controller.down.update(false),
-            Keycode::W => self.controller.up.update(false),
-            _ => ()
+        self.controller.input(Input::Key(keycode), false);
+    }
+
+    fn mouse_pressed(&mut self, ctx: &mut Context, button: MouseButton, _x: f64, _y: f64) {
+        if !self.chat.is_open() {
+            self.input_pressed(Input::Mouse(button));
+        }
+    }
+
+    fn mouse_released(&mut self, ctx: &mut Context, button: MouseButton, _x: f64, _y: f64) {
+        self.controller.input(Input::Mouse(button), false);
+    }
+
+    fn mouse_moved(&mut self, ctx: &mut Context, _x: f64, _y: f64) {
+        self.mouse_aim = true;
+    }
+
+    fn gamepad_pressed(&mut self, ctx: &mut Context, id: u32, button: PadButton) {
+        self.controller.activate_gamepad(id);
+        if self.chat.is_open() {
+            return;
+        }
+
+        self.input_pressed(Input::Pad(button));
+    }
+
+    fn gamepad_released(&mut self, ctx: &mut Context, id: u32, button: PadButton) {
+        if self.controller.is_active_gamepad(id) {
+            self.controller.input(Input::Pad(button), false);
         }
     }
 
-    fn draw(&mut self, ctx: &mut Context, canvas: &mut Canvas<Window>) {
-        self.draw_models(ctx, canvas);
+    fn gamepad_axis(&mut self, ctx: &mut Context, id: u32, axis: Axis, value: f64) {
+        match self.controller.axis_motion(id, axis, value) {
+            Some((input, true)) if !self.chat.is_open() => self.input_pressed(input),
+            Some((input, _)) => {
+                self.controller.input(input, false);
+            },
+            None => (),
+        }
+    }
+
+    fn gamepad_removed(&mut self, ctx: &mut Context, id: u32) {
+        self.controller.remove_gamepad(id);
+    }
+
+    fn text_input(&mut self, ctx: &mut Context, text: &str) {
+        if self.chat.is_open() {
+            self.chat.text_input(text);
+        }
+    }
+
+    fn focus_lost(&mut self, ctx: &mut Context) {
+        // Keys let go of in another window would stay held.
+        self.controller.release_all();
+    }
+
+    fn draw(&mut self, ctx: &mut Context, canvas: &mut Canvas<Window>, alpha: f64) {
+        self.update_camera(ctx, alpha);
+        self.map_renderer.draw(canvas, &self.assets.textures, &self.map, &self.camera);
+        self.draw_models(ctx, canvas, alpha);
+        self.draw_name_tags(canvas, alpha);
+        self.chat.draw(canvas, self.assets.fonts.get_mut(&self.font));
+        self.draw_scoreboard(canvas);
+    }
+
+    fn quit(&mut self, ctx: &mut Context) {
+        self.settings.fullscreen = ctx.is_fullscreen();
+        if !ctx.is_fullscreen() {
+            let (width, height) = ctx.window_size();
+            self.settings.window_width = width;
+            self.settings.window_height = height;
+        }
+        if let Err(e) = self.settings.save(SETTINGS_PATH) {
+            warn!("Could not save settings: {}", e);
+        }
     }
 }
@@ -0,0 +1,258 @@
+use app::font::BitmapFont;
+use zed_shared::message::{from_client::ChatSend, ChatChannel};
+
+use sdl2::{
+    keyboard::Keycode,
+    pixels::Color,
+    rect::Rect,
+    render::Canvas,
+    video::Window,
+};
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+const MAX_LINES: usize = 100;
+const VISIBLE_LINES: usize = 6;
+/// Longest input until the server tells its limit, the default of the server configuration.
+const DEFAULT_MAX_INPUT_LENGTH: usize = 120;
+const FADE_DELAY: Duration = Duration::from_secs(8);
+const FADE_DURATION: Duration = Duration::from_secs(2);
+
+struct ChatLine {
+    text: String,
+    color: Color,
+    received: Instant,
+}
+
+/// Chat scrollback with an input line, shown in the bottom left corner.
+///
+/// While closed only recent lines are shown and they fade out after a while.
+pub struct ChatBox {
+    open: bool,
+    channel: ChatChannel,
+    input: String,
+    /// Longest message the server relays, in characters.
+    max_length: usize,
+    lines: VecDeque<ChatLine>,
+    scroll: usize,
+}
+
+impl ChatBox {
+    pub fn new() -> Self {
+        Self {
+            open: false,
+            channel: ChatChannel::All,
+            input: String::new(),
+            max_length: DEFAULT_MAX_INPUT_LENGTH,
+            lines: VecDeque::new(),
+            scroll: 0,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn open(&mut self) {
+        self.open = true;
+        self.scroll = 0;
+    }
+
+    /// Limits the input to the longest message the server relays.
+    pub fn set_max_length(&mut self, max_length: usize) {
+        self.max_length = max_length;
+        self.input = self.input.chars().take(max_length).collect();
+    }
+
+    pub fn push(&mut self, text: String, color: Color) {
+        if self.lines.len() == MAX_LINES {
+            self.lines.pop_front();
+        }
+
+        self.lines.push_back(ChatLine {
+            text,
+            color,
+            received: Instant::now(),
+        });
+    }
+
+    /// Handles a key while the chat is open. Returns a message to send when the input was confirmed.
+    pub fn key_pressed(&mut self, keycode: Keycode) -> Option<ChatSend> {
+        match keycode {
+            Keycode::Return | Keycode::KpEnter => {
+                self.open = false;
+                let text = std::mem::replace(&mut self.input, String::new());
+
+                if text.trim().is_empty() {
+                    None
+                } else {
+                    Some(ChatSend { channel: self.channel, text })
+                }
+            },
+            Keycode::Escape => {
+                self.open = false;
+                self.input.clear();
+                None
+            },
+            Keycode::Tab => {
+                self.channel = match self.channel {
+                    ChatChannel::All => ChatChannel::Team,
+                    ChatChannel::Team => ChatChannel::All,
+                };
+                None
+            },
+            Keycode::Backspace => {
+                self.input.pop();
+                None
+            },
+            Keycode::PageUp => {
+                self.scroll = (self.scroll + VISIBLE_LINES / 2).min(self.lines.len().saturating_sub(1));
+                None
+            },
+            Keycode::PageDown => {
+                self.scroll = self.scroll.saturating_sub(VISIBLE_LINES / 2);
+                None
+            },
+            _ => None
+        }
+    }
+
+    pub fn text_input(&mut self, text: &str) {
+        for c in text.chars().filter(|c| !c.is_control()) {
+            if self.input.chars().count() >= self.max_length {
+                break;
+            }
+            self.input.push(c);
+        }
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas<Window>, font: &mut BitmapFont) {
+        let viewport = canvas.viewport();
+        let (glyph_width, line_height) = (font.text_size(" ").0.max(1), font.line_height() as i32);
+        let columns = (viewport.width().saturating_sub(4) / glyph_width).max(1) as usize;
+        let now = Instant::now();
+
+        let mut rows = Vec::new();
+        for line in self.lines.iter().rev().skip(self.scroll) {
+            let alpha = if self.open {
+                255
+            } else {
+                match fade_alpha(now.duration_since(line.received)) {
+                    0 => break,
+                    alpha => alpha,
+                }
+            };
+
+            for text in wrap(&line.text, columns).into_iter().rev() {
+                rows.push((text, Color::RGBA(line.color.r, line.color.g, line.color.b, alpha)));
+            }
+            if rows.len() >= VISIBLE_LINES {
+                break;
+            }
+        }
+        rows.truncate(VISIBLE_LINES);
+
+        let mut y = viewport.height() as i32 - 2 - line_height;
+        if self.open {
+            let visible = VISIBLE_LINES as i32 + 1;
+            canvas.set_draw_color(Color::RGB(24, 28, 36));
+            canvas.fill_rect(Rect::new(0, y - (visible - 1) * line_height - 2, viewport.width(), (visible * line_height + 4) as u32)).unwrap();
+
+            let prefix = match self.channel {
+                ChatChannel::All => "all>",
+                ChatChannel::Team => "team>",
+            };
+            let input = format!("{}{}_", prefix, self.input);
+            let skip = input.chars().count().saturating_sub(columns);
+            font.draw(canvas, &input.chars().skip(skip).collect::<String>(), 2, y, Color::RGB(255, 255, 255));
+        }
+        y -= line_height;
+
+        for (text, color) in rows {
+            font.draw(canvas, &text, 2, y, color);
+            y -= line_height;
+        }
+    }
+}
+
+fn fade_alpha(age: Duration) -> u8 {
+    if age <= FADE_DELAY {
+        255
+    } else if age >= FADE_DELAY + FADE_DURATION {
+        0
+    } else {
+        let faded = (age - FADE_DELAY).as_secs_f64() / FADE_DURATION.as_secs_f64();
+        (255.0 * (1.0 - faded)) as u8
+    }
+}
+
+/// Splits `text` into lines of at most `columns` characters, breaking on spaces when possible.
+pub fn wrap(text: &str, columns: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+
+    for word in text.split(' ') {
+        let word_length = word.chars().count();
+        let line_length = line.chars().count();
+
+        if line_length > 0 && line_length + 1 + word_length > columns {
+            lines.push(std::mem::replace(&mut line, String::new()));
+        } else if line_length > 0 {
+            line.push(' ');
+        }
+
+        let mut chars = word.chars().peekable();
+        while chars.peek().is_some() {
+            let free = columns - line.chars().count();
+            line.extend(chars.by_ref().take(free));
+
+            if chars.peek().is_some() {
+                lines.push(std::mem::replace(&mut line, String::new()));
+            }
+        }
+    }
+    if !line.is_empty() || lines.is_empty() {
+        lines.push(line);
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{fade_alpha, wrap, ChatBox, FADE_DELAY, FADE_DURATION};
+    use std::time::Duration;
+
+    #[test]
+    fn test_max_length() {
+        let mut chat = ChatBox::new();
+        chat.text_input("hello world");
+        chat.set_max_length(5);
+        assert_eq!(chat.input, "hello");
+
+        chat.text_input("!\u{7}");
+        assert_eq!(chat.input, "hello", "Input stops at the server's limit");
+        chat.set_max_length(7);
+        chat.text_input("\u{7}!!!");
+        assert_eq!(chat.input, "hello!!");
+    }
+
+    #[test]
+    fn test_wrap() {
+        assert_eq!(wrap("hello world", 20), vec!["hello world"]);
+        assert_eq!(wrap("hello world", 8), vec!["hello", "world"]);
+        assert_eq!(wrap("hello world", 11), vec!["hello world"]);
+        assert_eq!(wrap("abcdefghij", 4), vec!["abcd", "efgh", "ij"]);
+        assert_eq!(wrap("a abcdefgh", 4), vec!["a", "abcd", "efgh"]);
+        assert_eq!(wrap("", 4), vec![""]);
+    }
+
+    #[test]
+    fn test_fade_alpha() {
+        assert_eq!(fade_alpha(Duration::from_secs(0)), 255);
+        assert_eq!(fade_alpha(FADE_DELAY), 255);
+        assert_eq!(fade_alpha(FADE_DELAY + FADE_DURATION / 2), 127);
+        assert_eq!(fade_alpha(FADE_DELAY + FADE_DURATION), 0);
+    }
+}
@@ -0,0 +1,74 @@
+use app::{
+    assets::{Assets, Handle, Store},
+    camera::Camera,
+};
+use sdl2::{
+    pixels::Color,
+    rect::Rect,
+    render::{Canvas, Texture},
+    video::Window,
+};
+
+use zed_shared::map::TileMap;
+
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Drawn instead of the tiles of a tileset whose image is not loaded.
+const PLACEHOLDER_COLOR: Color = Color::RGB(255, 0, 255);
+
+/// Tileset textures of the current map.
+pub struct MapRenderer {
+    /// By the first global tile id of their tileset.
+    textures: HashMap<u32, Handle<Texture>>,
+}
+
+impl MapRenderer {
+    /// Asks `assets` for the tileset images of `map`, their paths are relative to `dir`.
+    pub fn new(assets: &mut Assets, map: &TileMap, dir: &str) -> Self {
+        let textures = map.tilesets.iter()
+            .map(|tileset| {
+                let path = Path::new(dir).join(&tileset.image);
+                (tileset.first_gid, assets.textures.file(&path.to_string_lossy()))
+            })
+            .collect();
+
+        Self { textures }
+    }
+
+    /// Draws the visible layers of `map` in order, the first layer at the bottom.
+    pub fn draw(&self, canvas: &mut Canvas<Window>, textures: &Store<Texture>, map: &TileMap, camera: &Camera) {
+        let (tile_width, tile_height) = (map.tile_width as i32, map.tile_height as i32);
+        let (origin_x, origin_y) = camera.origin();
+        let (origin_x, origin_y) = (origin_x as i32, origin_y as i32);
+        let viewport = canvas.viewport();
+
+        for layer in map.layers.iter().filter(|layer| layer.visible) {
+            for (i, &gid) in layer.tiles.iter().enumerate() {
+                let (tileset, source_x, source_y) = match map.tile_source(gid) {
+                    Some(source) => source,
+                    None => continue,
+                };
+
+                // Tiles larger than the map grid stick out upwards, like in Tiled.
+                let x = (i % map.width) as i32 * tile_width - origin_x;
+                let y = (i / map.width) as i32 * tile_height + tile_height - tileset.tile_height as i32 - origin_y;
+                let destination = Rect::new(x, y, tileset.tile_width, tileset.tile_height);
+                if !destination.has_intersection(viewport) {
+                    continue;
+                }
+
+                match self.textures.get(&tileset.first_gid).filter(|&texture| textures.is_loaded(texture)) {
+                    Some(texture) => {
+                        let source = Rect::new(source_x as i32, source_y as i32, tileset.tile_width, tileset.tile_height);
+                        canvas.copy(textures.get(texture), source, destination).unwrap();
+                    },
+                    None => {
+                        canvas.set_draw_color(PLACEHOLDER_COLOR);
+                        canvas.draw_rect(destination).unwrap();
+                    },
+                }
+            }
+        }
+    }
+}
@@ -1,2 +1,5 @@
 pub mod app;
+pub mod chat;
 pub mod client;
+pub mod map;
+pub mod scenes;
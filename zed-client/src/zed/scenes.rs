@@ -0,0 +1,770 @@
+use app::{
+    app::{App, Context},
+    controller::Input,
+    scene::{Scene, Transition},
+};
+use sdl2::{
+    controller::{Axis, Button as PadButton},
+    keyboard::Keycode,
+    mouse::MouseButton,
+    pixels::Color,
+    render::Canvas,
+    video::Window,
+};
+use tracing::warn;
+
+use std::net::{SocketAddr, ToSocketAddrs};
+
+use super::app::Main;
+use super::chat;
+use crate::settings::{Action, ACTIONS};
+use zed_shared::message::MAX_PLAYER_NAME_LENGTH;
+
+/// Offered in the server browser when no server was joined before.
+pub const DEFAULT_SERVER: &str = "127.0.0.1:10995";
+/// Seconds to wait for the server to answer the greeting.
+const CONNECT_TIMEOUT: f64 = 10.0;
+const MAX_ADDRESS_LENGTH: usize = 64;
+/// Servers the browser shows at once.
+const SERVER_ROWS: usize = 5;
+/// Actions the controls screen shows at once.
+const ACTION_ROWS: usize = 4;
+
+const ERROR_COLOR: Color = Color::RGB(255, 120, 120);
+
+/// Looks up a server address, host names are allowed.
+fn resolve(addr: &str) -> Result<SocketAddr, String> {
+    let addr = addr.trim();
+    addr.to_socket_addrs()
+        .map_err(|e| format!("Cannot resolve {}: {}", addr, e))?
+        .next()
+        .ok_or_else(|| format!("No address found for {}", addr))
+}
+
+/// A scene made of UI widgets only, run by `Menu`.
+trait Screen {
+    fn name(&self) -> &str;
+
+    fn enter(&mut self, _ctx: &mut Context, _main: &mut Main) {}
+    fn is_overlay(&self) -> bool {
+        false
+    }
+    fn update(&mut self, _ctx: &mut Context, _main: &mut Main) -> Transition<Main> {
+        Transition::None
+    }
+    /// Draws the widgets and returns the transition the player chose with them.
+    fn ui(&mut self, ctx: &mut Context, main: &mut Main, canvas: &mut Canvas<Window>) -> Transition<Main>;
+    /// Buttons the widgets do not use.
+    fn gamepad_pressed(&mut self, _main: &mut Main, _button: PadButton) -> Transition<Main> {
+        Transition::None
+    }
+    /// Key, mouse and gamepad button presses before the widgets get them,
+    /// returns `true` when the screen took the press.
+    fn input_pressed(&mut self, _ctx: &mut Context, _main: &mut Main, _input: Input) -> bool {
+        false
+    }
+}
+
+/// Feeds the input to `Main`'s UI and applies what the player chose on the next update,
+/// widgets are only handled while they are drawn.
+struct Menu<T> {
+    screen: T,
+    chosen: Option<Transition<Main>>,
+}
+
+fn menu<T: Screen + 'static>(screen: T) -> Box<dyn Scene<Main>> {
+    Box::new(Menu { screen, chosen: None })
+}
+
+impl<T: Screen> Scene<Main> for Menu<T> {
+    fn name(&self) -> &str {
+        self.screen.name()
+    }
+
+    fn enter(&mut self, ctx: &mut Context, main: &mut Main) {
+        main.ui().reset();
+        self.screen.enter(ctx, main);
+    }
+
+    fn uncovered(&mut self, _ctx: &mut Context, main: &mut Main) {
+        main.ui().reset();
+    }
+
+    fn is_overlay(&self) -> bool {
+        self.screen.is_overlay()
+    }
+
+    fn update(&mut self, ctx: &mut Context, main: &mut Main) -> Transition<Main> {
+        match self.chosen.take() {
+            Some(transition) => transition,
+            None => self.screen.update(ctx, main),
+        }
+    }
+
+    fn draw(&mut self, ctx: &mut Context, main: &mut Main, canvas: &mut Canvas<Window>, _alpha: f64) {
+        let transition = self.screen.ui(ctx, main, canvas);
+        if self.chosen.is_none() && !transition.is_none() {
+            self.chosen = Some(transition);
+        }
+    }
+
+    fn key_pressed(&mut self, ctx: &mut Context, main: &mut Main, keycode: Keycode, repeat: bool) -> Transition<Main> {
+        if !repeat && self.screen.input_pressed(ctx, main, Input::Key(keycode)) {
+            return Transition::None;
+        }
+        // Holding Escape would close the menu it just opened.
+        if !repeat || keycode != Keycode::Escape {
+            main.ui().key_pressed(keycode);
+        }
+        Transition::None
+    }
+
+    fn mouse_pressed(&mut self, ctx: &mut Context, main: &mut Main, button: MouseButton, x: f64, y: f64)
+        -> Transition<Main>
+    {
+        if !self.screen.input_pressed(ctx, main, Input::Mouse(button)) {
+            main.ui().mouse_pressed(button, x, y);
+        }
+        Transition::None
+    }
+
+    fn mouse_released(&mut self, _ctx: &mut Context, main: &mut Main, button: MouseButton, x: f64, y: f64)
+        -> Transition<Main>
+    {
+        main.ui().mouse_released(button, x, y);
+        Transition::None
+    }
+
+    fn mouse_moved(&mut self, _ctx: &mut Context, main: &mut Main, x: f64, y: f64) -> Transition<Main> {
+        main.ui().mouse_moved(x, y);
+        Transition::None
+    }
+
+    fn text_input(&mut self, _ctx: &mut Context, main: &mut Main, text: &str) -> Transition<Main> {
+        main.ui().text_input(text);
+        Transition::None
+    }
+
+    fn gamepad_pressed(&mut self, ctx: &mut Context, main: &mut Main, _id: u32, button: PadButton) -> Transition<Main> {
+        if self.screen.input_pressed(ctx, main, Input::Pad(button)) {
+            return Transition::None;
+        }
+        let transition = self.screen.gamepad_pressed(main, button);
+        if transition.is_none() {
+            main.ui().gamepad_pressed(button);
+        }
+
+        transition
+    }
+
+    fn gamepad_axis(&mut self, ctx: &mut Context, main: &mut Main, id: u32, axis: Axis, value: f64)
+        -> Transition<Main>
+    {
+        if let Some(input) = main.trigger_pulled(id, axis, value) {
+            self.screen.input_pressed(ctx, main, input);
+        }
+        Transition::None
+    }
+}
+
+/// First screen without a server on the command line.
+pub struct MainMenu {
+    error: Option<String>,
+}
+
+impl MainMenu {
+    pub fn scene() -> Box<dyn Scene<Main>> {
+        menu(Self { error: None })
+    }
+}
+
+impl Screen for MainMenu {
+    fn name(&self) -> &str {
+        "main menu"
+    }
+
+    fn ui(&mut self, _ctx: &mut Context, main: &mut Main, canvas: &mut Canvas<Window>) -> Transition<Main> {
+        let last_server = main.settings().last_server.clone();
+        let rows = 4 + last_server.is_some() as u32 + self.error.is_some() as u32;
+        let mut frame = main.ui_panel(canvas, rows);
+        let mut transition = Transition::None;
+
+        frame.heading("ZED");
+        if let Some(addr) = &last_server {
+            if frame.button(&format!("Join {}", addr)) {
+                match resolve(addr) {
+                    Ok(addr) => transition = Transition::Reset(Connecting::scene(addr)),
+                    Err(e) => self.error = Some(e),
+                }
+            }
+        }
+        if frame.button("Servers") {
+            transition = Transition::Push(menu(ServerBrowser::new()));
+        }
+        if frame.button("Settings") {
+            transition = Transition::Push(SettingsScene::scene());
+        }
+        if frame.button("Quit") {
+            transition = Transition::Quit;
+        }
+        if let Some(error) = &self.error {
+            frame.label_colored(error, ERROR_COLOR);
+        }
+
+        transition
+    }
+}
+
+/// Recently joined servers and a field to type a new address.
+struct ServerBrowser {
+    selected: usize,
+    address: String,
+    error: Option<String>,
+}
+
+impl ServerBrowser {
+    fn new() -> Self {
+        Self {
+            selected: 0,
+            address: String::new(),
+            error: None,
+        }
+    }
+
+    fn join(&mut self, addr: &str) -> Transition<Main> {
+        match resolve(addr) {
+            Ok(addr) => Transition::Reset(Connecting::scene(addr)),
+            Err(e) => {
+                warn!(error = %e, "Cannot join");
+                self.error = Some(e);
+                Transition::None
+            },
+        }
+    }
+}
+
+impl Screen for ServerBrowser {
+    fn name(&self) -> &str {
+        "server browser"
+    }
+
+    fn enter(&mut self, _ctx: &mut Context, main: &mut Main) {
+        if main.settings().servers.is_empty() {
+            self.address = DEFAULT_SERVER.to_string();
+        }
+    }
+
+    fn ui(&mut self, _ctx: &mut Context, main: &mut Main, canvas: &mut Canvas<Window>) -> Transition<Main> {
+        let servers = main.settings().servers.clone();
+        let list_rows = servers.len().min(SERVER_ROWS).max(1);
+        let rows = 4 + list_rows as u32 + !servers.is_empty() as u32 + self.error.is_some() as u32;
+
+        let mut join = None;
+        let mut forget = false;
+        let back;
+        {
+            let mut frame = main.ui_panel(canvas, rows);
+
+            frame.heading("SERVERS");
+            if servers.is_empty() {
+                let color = frame.theme().dim_color;
+                frame.label_colored("None joined yet", color);
+            } else if let Some(index) = frame.list(&servers, &mut self.selected, list_rows) {
+                join = Some(servers[index].clone());
+            }
+            frame.text_field("Address", &mut self.address, MAX_ADDRESS_LENGTH);
+            if frame.button("Join address") {
+                join = Some(self.address.clone());
+            }
+            if !servers.is_empty() && frame.button("Forget server") {
+                forget = true;
+            }
+            back = frame.button("Back") || frame.back();
+            if let Some(error) = &self.error {
+                frame.label_colored(error, ERROR_COLOR);
+            }
+        }
+
+        if forget && self.selected < servers.len() {
+            main.settings_mut().servers.remove(self.selected);
+        }
+        match join {
+            Some(addr) => self.join(&addr),
+            None if back => Transition::Pop,
+            None => Transition::None,
+        }
+    }
+}
+
+/// Waits for the server to answer the greeting.
+pub struct Connecting {
+    addr: SocketAddr,
+    elapsed: f64,
+}
+
+impl Connecting {
+    pub fn scene(addr: SocketAddr) -> Box<dyn Scene<Main>> {
+        menu(Self { addr, elapsed: 0.0 })
+    }
+}
+
+impl Screen for Connecting {
+    fn name(&self) -> &str {
+        "connecting"
+    }
+
+    fn enter(&mut self, _ctx: &mut Context, main: &mut Main) {
+        main.connect(self.addr);
+    }
+
+    fn update(&mut self, ctx: &mut Context, main: &mut Main) -> Transition<Main> {
+        self.elapsed += ctx.dt();
+
+        if main.is_joined() {
+            return Transition::Replace(Box::new(InGame));
+        }
+        if main.disconnect_reason().is_none() && self.elapsed > CONNECT_TIMEOUT {
+            main.disconnect("The server did not answer.".into());
+        }
+        if main.disconnect_reason().is_some() {
+            return Transition::Replace(menu(Disconnected));
+        }
+
+        Transition::None
+    }
+
+    fn ui(&mut self, _ctx: &mut Context, main: &mut Main, canvas: &mut Canvas<Window>) -> Transition<Main> {
+        let dots = ".".repeat(self.elapsed as usize % 4);
+        let cancel = {
+            let mut frame = main.ui_panel(canvas, 3);
+            frame.heading("Connecting");
+            frame.label(&format!("{}{}", self.addr, dots));
+            frame.button("Cancel") || frame.back()
+        };
+
+        if cancel {
+            main.leave();
+            Transition::Replace(MainMenu::scene())
+        } else {
+            Transition::None
+        }
+    }
+}
+
+/// The game itself, everything is handled by `Main`.
+pub struct InGame;
+
+impl InGame {
+    /// Escape and Start pause the game unless the chat takes them.
+    fn pauses(main: &Main) -> bool {
+        !main.is_chat_open()
+    }
+
+    /// Whether `input` opens the settings, the chat takes it like any other key while open.
+    fn opens_settings(main: &Main, input: Input) -> bool {
+        !main.is_chat_open() && main.action(input) == Some(Action::Menu)
+    }
+}
+
+impl Scene<Main> for InGame {
+    fn name(&self) -> &str {
+        "in game"
+    }
+
+    fn covered(&mut self, _ctx: &mut Context, main: &mut Main) {
+        main.release_input();
+    }
+
+    fn update(&mut self, ctx: &mut Context, main: &mut Main) -> Transition<Main> {
+        if main.disconnect_reason().is_some() {
+            return Transition::Replace(menu(Disconnected));
+        }
+        main.play(ctx);
+
+        Transition::None
+    }
+
+    fn draw(&mut self, ctx: &mut Context, main: &mut Main, canvas: &mut Canvas<Window>, alpha: f64) {
+        main.draw(ctx, canvas, alpha);
+    }
+
+    fn key_pressed(&mut self, ctx: &mut Context, main: &mut Main, keycode: Keycode, repeat: bool) -> Transition<Main> {
+        if keycode == Keycode::Escape && Self::pauses(main) {
+            if repeat {
+                return Transition::None;
+            }
+            return Transition::Push(menu(Pause));
+        }
+        if !repeat && Self::opens_settings(main, Input::Key(keycode)) {
+            return Transition::Push(SettingsScene::scene());
+        }
+
+        main.key_pressed(ctx, keycode, repeat);
+        Transition::None
+    }
+
+    fn key_released(&mut self, ctx: &mut Context, main: &mut Main, keycode: Keycode) -> Transition<Main> {
+        main.key_released(ctx, keycode);
+        Transition::None
+    }
+
+    fn mouse_pressed(&mut self, ctx: &mut Context, main: &mut Main, button: MouseButton, x: f64, y: f64)
+        -> Transition<Main>
+    {
+        if Self::opens_settings(main, Input::Mouse(button)) {
+            return Transition::Push(SettingsScene::scene());
+        }
+
+        main.mouse_pressed(ctx, button, x, y);
+        Transition::None
+    }
+
+    fn mouse_released(&mut self, ctx: &mut Context, main: &mut Main, button: MouseButton, x: f64, y: f64)
+        -> Transition<Main>
+    {
+        main.mouse_released(ctx, button, x, y);
+        Transition::None
+    }
+
+    fn mouse_moved(&mut self, ctx: &mut Context, main: &mut Main, x: f64, y: f64) -> Transition<Main> {
+        main.mouse_moved(ctx, x, y);
+        Transition::None
+    }
+
+    fn text_input(&mut self, ctx: &mut Context, main: &mut Main, text: &str) -> Transition<Main> {
+        main.text_input(ctx, text);
+        Transition::None
+    }
+
+    fn gamepad_pressed(&mut self, ctx: &mut Context, main: &mut Main, id: u32, button: PadButton) -> Transition<Main> {
+        if button == PadButton::Start && Self::pauses(main) {
+            return Transition::Push(menu(Pause));
+        }
+        if Self::opens_settings(main, Input::Pad(button)) {
+            return Transition::Push(SettingsScene::scene());
+        }
+
+        main.gamepad_pressed(ctx, id, button);
+        Transition::None
+    }
+
+    fn gamepad_released(&mut self, ctx: &mut Context, main: &mut Main, id: u32, button: PadButton)
+        -> Transition<Main>
+    {
+        main.gamepad_released(ctx, id, button);
+        Transition::None
+    }
+
+    fn gamepad_axis(&mut self, ctx: &mut Context, main: &mut Main, id: u32, axis: Axis, value: f64)
+        -> Transition<Main>
+    {
+        main.gamepad_axis(ctx, id, axis, value);
+        Transition::None
+    }
+}
+
+
+/// Drawn over the game, which keeps running on the server.
+struct Pause;
+
+impl Screen for Pause {
+    fn name(&self) -> &str {
+        "pause"
+    }
+
+    fn is_overlay(&self) -> bool {
+        true
+    }
+
+    fn update(&mut self, _ctx: &mut Context, main: &mut Main) -> Transition<Main> {
+        check_disconnected(main)
+    }
+
+    fn ui(&mut self, _ctx: &mut Context, main: &mut Main, canvas: &mut Canvas<Window>) -> Transition<Main> {
+        let (resume, settings, disconnect, quit) = {
+            let mut frame = main.ui_panel(canvas, 5);
+            frame.heading("PAUSED");
+            let resume = frame.button("Resume");
+            let settings = frame.button("Settings");
+            let disconnect = frame.button("Disconnect");
+            let quit = frame.button("Quit");
+            (resume || frame.back(), settings, disconnect, quit)
+        };
+
+        if settings {
+            return Transition::Push(SettingsScene::scene());
+        }
+        if disconnect {
+            main.leave();
+            return Transition::Reset(MainMenu::scene());
+        }
+        if quit {
+            Transition::Quit
+        } else if resume {
+            Transition::Pop
+        } else {
+            Transition::None
+        }
+    }
+
+    fn gamepad_pressed(&mut self, _main: &mut Main, button: PadButton) -> Transition<Main> {
+        match button {
+            PadButton::Start => Transition::Pop,
+            _ => Transition::None,
+        }
+    }
+}
+
+/// The disconnected screen, for menus opened from the game, which stops updating under them.
+fn check_disconnected(main: &Main) -> Transition<Main> {
+    match main.disconnect_reason() {
+        Some(_) => Transition::Reset(menu(Disconnected)),
+        None => Transition::None,
+    }
+}
+
+/// Player name, color and volume, the display and the controls have screens of their own.
+pub struct SettingsScene {
+    /// The menu action was pressed again.
+    closed: bool,
+}
+
+impl SettingsScene {
+    pub fn scene() -> Box<dyn Scene<Main>> {
+        menu(Self { closed: false })
+    }
+
+    /// Tells the server about a new name once the player is done typing it.
+    fn close(main: &mut Main) -> Transition<Main> {
+        main.announce_name();
+        Transition::Pop
+    }
+}
+
+impl Screen for SettingsScene {
+    fn name(&self) -> &str {
+        "settings"
+    }
+
+    fn update(&mut self, _ctx: &mut Context, main: &mut Main) -> Transition<Main> {
+        if self.closed {
+            return Self::close(main);
+        }
+        check_disconnected(main)
+    }
+
+    fn ui(&mut self, ctx: &mut Context, main: &mut Main, canvas: &mut Canvas<Window>) -> Transition<Main> {
+        let mut name = main.settings().player_name.clone();
+        let [r, g, b] = main.settings().color.unwrap_or([255, 255, 255]);
+        let mut color = [r as f64, g as f64, b as f64];
+        let mut volume = main.settings().volume as f64;
+
+        let (name_changed, color_changed, volume_changed, display, controls, back) = {
+            let mut frame = main.ui_panel(canvas, 9);
+            frame.heading("SETTINGS");
+            let name_changed = frame.text_field("Name", &mut name, MAX_PLAYER_NAME_LENGTH);
+            let mut color_changed = false;
+            for (text, channel) in ["Red", "Green", "Blue"].iter().zip(color.iter_mut()) {
+                color_changed |= frame.slider(text, channel, 0.0, 255.0, 16.0);
+            }
+            let volume_changed = frame.slider("Volume", &mut volume, 0.0, 100.0, 10.0);
+            let display = frame.button("Display");
+            let controls = frame.button("Controls");
+            let back = frame.button("Back") || frame.back();
+            (name_changed, color_changed, volume_changed, display, controls, back)
+        };
+
+        let settings = main.settings_mut();
+        if name_changed {
+            settings.player_name = name;
+        }
+        // Left unset until the player picks one, the server chooses the color then.
+        if color_changed {
+            let [r, g, b] = color;
+            settings.color = Some([r as u8, g as u8, b as u8]);
+        }
+        if volume_changed {
+            settings.volume = volume as u8;
+        }
+        if color_changed || volume_changed {
+            main.settings_changed(ctx);
+        }
+
+        if display {
+            Transition::Push(menu(DisplaySettings))
+        } else if controls {
+            Transition::Push(menu(ControlsSettings { selected: 0, capturing: false }))
+        } else if back {
+            Self::close(main)
+        } else {
+            Transition::None
+        }
+    }
+
+    fn input_pressed(&mut self, _ctx: &mut Context, main: &mut Main, input: Input) -> bool {
+        self.closed = main.action(input) == Some(Action::Menu);
+        self.closed
+    }
+}
+
+/// Size of the picture and the window.
+struct DisplaySettings;
+
+impl Screen for DisplaySettings {
+    fn name(&self) -> &str {
+        "display settings"
+    }
+
+    fn update(&mut self, _ctx: &mut Context, main: &mut Main) -> Transition<Main> {
+        check_disconnected(main)
+    }
+
+    fn ui(&mut self, ctx: &mut Context, main: &mut Main, canvas: &mut Canvas<Window>) -> Transition<Main> {
+        let mut scale = main.settings().scale;
+        let mut pixel_perfect = main.settings().pixel_perfect;
+        let mut fullscreen = ctx.is_fullscreen();
+
+        let (changed, fullscreen_changed, back) = {
+            let mut frame = main.ui_panel(canvas, 5);
+            frame.heading("DISPLAY");
+            let mut changed = frame.slider("Scale", &mut scale, 1.0, 16.0, 1.0);
+            changed |= frame.checkbox("Pixel perfect", &mut pixel_perfect);
+            let fullscreen_changed = frame.checkbox("Fullscreen", &mut fullscreen);
+            let back = frame.button("Back") || frame.back();
+            (changed, fullscreen_changed, back)
+        };
+
+        if changed {
+            let settings = main.settings_mut();
+            settings.scale = scale;
+            settings.pixel_perfect = pixel_perfect;
+            main.settings_changed(ctx);
+        }
+        // Saved with the window size when the game quits, F11 toggles it too.
+        if fullscreen_changed {
+            ctx.set_fullscreen(fullscreen);
+        }
+
+        if back {
+            Transition::Pop
+        } else {
+            Transition::None
+        }
+    }
+}
+
+/// Key bindings and the gamepad deadzone.
+struct ControlsSettings {
+    selected: usize,
+    /// Waiting for the input to bind to the selected action.
+    capturing: bool,
+}
+
+impl Screen for ControlsSettings {
+    fn name(&self) -> &str {
+        "controls"
+    }
+
+    fn update(&mut self, _ctx: &mut Context, main: &mut Main) -> Transition<Main> {
+        check_disconnected(main)
+    }
+
+    fn ui(&mut self, ctx: &mut Context, main: &mut Main, canvas: &mut Canvas<Window>) -> Transition<Main> {
+        let columns = main.ui_columns(canvas);
+        let bindings = ACTIONS.iter().enumerate()
+            .map(|(i, &action)| {
+                let binding = if self.capturing && i == self.selected {
+                    "press...".to_string()
+                } else {
+                    main.settings().keys.get(action).to_string()
+                };
+                format!("{:7}{}", action.name(), binding).chars().take(columns).collect::<String>()
+            })
+            .collect::<Vec<String>>();
+        let mut deadzone = main.settings().gamepad_deadzone;
+        let rows = 4 + ACTION_ROWS as u32 + self.capturing as u32;
+
+        let (chosen, deadzone_changed, clear, back) = {
+            let mut frame = main.ui_panel(canvas, rows);
+            frame.heading("CONTROLS");
+            let chosen = frame.list(&bindings, &mut self.selected, ACTION_ROWS).is_some();
+            let deadzone_changed = frame.slider("Deadzone", &mut deadzone, 0.0, 0.9, 0.1);
+            let clear = frame.button("Clear binding");
+            let back = frame.button("Back") || frame.back();
+            if self.capturing {
+                let color = frame.theme().dim_color;
+                frame.label_colored("Esc cancels", color);
+            }
+            (chosen, deadzone_changed, clear, back)
+        };
+
+        if chosen {
+            self.capturing = true;
+        }
+        if clear {
+            main.settings_mut().keys.get_mut(ACTIONS[self.selected]).0.clear();
+        }
+        if deadzone_changed {
+            main.settings_mut().gamepad_deadzone = deadzone;
+        }
+        if clear || deadzone_changed {
+            main.settings_changed(ctx);
+        }
+
+        if back {
+            Transition::Pop
+        } else {
+            Transition::None
+        }
+    }
+
+    /// Binds the next press to the selected action, Escape cancels and stays with the menus.
+    fn input_pressed(&mut self, ctx: &mut Context, main: &mut Main, input: Input) -> bool {
+        if !self.capturing {
+            return false;
+        }
+
+        self.capturing = false;
+        if input != Input::Key(Keycode::Escape) {
+            main.settings_mut().keys.rebind(ACTIONS[self.selected], input);
+            main.settings_changed(ctx);
+        }
+        true
+    }
+}
+
+
+/// Shows why the connection ended, until the player joins again or goes back to the menu.
+struct Disconnected;
+
+impl Screen for Disconnected {
+    fn name(&self) -> &str {
+        "disconnected"
+    }
+
+    fn ui(&mut self, _ctx: &mut Context, main: &mut Main, canvas: &mut Canvas<Window>) -> Transition<Main> {
+        let reason = chat::wrap(main.disconnect_reason().unwrap_or(""), main.ui_columns(canvas));
+        let addr = main.server_addr();
+        let rows = 2 + reason.len() as u32 + addr.is_some() as u32;
+
+        let (reconnect, main_menu) = {
+            let mut frame = main.ui_panel(canvas, rows);
+            frame.label_colored("Disconnected", ERROR_COLOR);
+            for line in &reason {
+                frame.label(line);
+            }
+            let reconnect = addr.is_some() && frame.button("Reconnect");
+            let main_menu = frame.button("Main menu") || frame.back();
+            (reconnect, main_menu)
+        };
+
+        match addr {
+            Some(addr) if reconnect => Transition::Reset(Connecting::scene(addr)),
+            _ if main_menu => {
+                main.leave();
+                Transition::Reset(MainMenu::scene())
+            },
+            _ => Transition::None,
+        }
+    }
+}
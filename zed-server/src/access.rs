@@ -0,0 +1,390 @@
+use serde::{Serialize, Deserialize};
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Address range written as `address/prefix_length`, e.g. `10.0.0.0/8`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix: u8,
+}
+
+/// What an access list entry applies to.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Target {
+    Ip(IpAddr),
+    Range(Cidr),
+    /// Identity token a client sends along with its greeting.
+    Token(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AccessEntry {
+    pub target: Target,
+    #[serde(default)]
+    pub reason: String,
+    /// Unix timestamp in seconds after which the entry stops applying, permanent if missing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub until: Option<u64>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct AccessFile {
+    #[serde(default, rename = "ban", skip_serializing_if = "Vec::is_empty")]
+    bans: Vec<AccessEntry>,
+    #[serde(default, rename = "allow", skip_serializing_if = "Vec::is_empty")]
+    allows: Vec<AccessEntry>,
+}
+
+/// Ban and allow lists persisted in a TOML file.
+///
+/// When the allow list is not empty only clients matching one of its entries can join.
+pub struct AccessList {
+    path: PathBuf,
+    file: AccessFile,
+    modified: Option<SystemTime>,
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::max_value().checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            },
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::max_value().checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            },
+            _ => false,
+        }
+    }
+}
+
+impl std::str::FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, '/');
+        let network: IpAddr = parts.next().unwrap_or("").parse()
+            .map_err(|_| format!("invalid address in range \"{}\"", s))?;
+        let prefix: u8 = parts.next().unwrap_or("").parse()
+            .map_err(|_| format!("invalid prefix length in range \"{}\"", s))?;
+
+        let max_prefix = if network.is_ipv4() { 32 } else { 128 };
+        if prefix > max_prefix {
+            return Err(format!("prefix length of \"{}\" is larger than {}", s, max_prefix));
+        }
+
+        Ok(Self { network, prefix })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+impl Target {
+    fn matches(&self, ip: IpAddr, token: Option<&str>) -> bool {
+        match self {
+            Target::Ip(target) => *target == ip,
+            Target::Range(range) => range.contains(ip),
+            Target::Token(target) => token == Some(target.as_str()),
+        }
+    }
+}
+
+impl TryFrom<String> for Target {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let s = s.trim();
+
+        if s.starts_with("token:") {
+            let token = s["token:".len()..].trim();
+            if token.is_empty() {
+                return Err("empty identity token".into());
+            }
+            Ok(Target::Token(token.into()))
+        } else if s.contains('/') {
+            s.parse().map(Target::Range)
+        } else {
+            s.parse().map(Target::Ip)
+                .map_err(|_| format!("\"{}\" is neither an address, a range nor a token:<identity>", s))
+        }
+    }
+}
+
+impl From<Target> for String {
+    fn from(target: Target) -> Self {
+        target.to_string()
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Target::Ip(ip) => write!(f, "{}", ip),
+            Target::Range(range) => write!(f, "{}", range),
+            Target::Token(token) => write!(f, "token:{}", token),
+        }
+    }
+}
+
+impl AccessEntry {
+    fn is_active(&self, now: u64) -> bool {
+        self.until.map_or(true, |until| now < until)
+    }
+}
+
+impl AccessList {
+    /// Loads the lists from `path`, a missing file results in empty lists.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let mut list = Self {
+            path: path.as_ref().to_owned(),
+            file: AccessFile::default(),
+            modified: None,
+        };
+        list.reload()?;
+
+        Ok(list)
+    }
+
+    fn reload(&mut self) -> Result<(), String> {
+        let path = &self.path;
+        self.modified = modified_time(path);
+        if !path.exists() {
+            self.file = AccessFile::default();
+            return Ok(());
+        }
+
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        self.file = toml::from_str(&contents)
+            .map_err(|e| format!("cannot parse {}: {}", path.display(), e))?;
+
+        Ok(())
+    }
+
+    /// Reloads the lists if the file was modified since it was last read or written.
+    /// Returns `true` when the lists were reloaded.
+    pub fn reload_if_changed(&mut self) -> Result<bool, String> {
+        if modified_time(&self.path) == self.modified {
+            return Ok(false);
+        }
+
+        self.reload().map(|_| true)
+    }
+
+    pub fn save(&mut self) -> Result<(), String> {
+        let contents = toml::to_string_pretty(&self.file)
+            .map_err(|e| format!("cannot serialize access lists: {}", e))?;
+        std::fs::write(&self.path, contents)
+            .map_err(|e| format!("cannot write {}: {}", self.path.display(), e))?;
+        self.modified = modified_time(&self.path);
+
+        Ok(())
+    }
+
+    /// Checks whether a client can join, returning the reason presented to it if it cannot.
+    pub fn check(&self, ip: IpAddr, token: Option<&str>, now: SystemTime) -> Result<(), String> {
+        self.check_entries(ip, token, false, now)
+    }
+
+    /// Checks a client whose identity token is not known yet, only bans and allows of
+    /// addresses can refuse it. Token entries are left for `check` once the greeting arrives.
+    pub fn check_address(&self, ip: IpAddr, now: SystemTime) -> Result<(), String> {
+        self.check_entries(ip, None, true, now)
+    }
+
+    fn check_entries(&self, ip: IpAddr, token: Option<&str>, token_pending: bool, now: SystemTime) -> Result<(), String> {
+        let now = unix_time(now);
+
+        let ban = self.file.bans.iter()
+            .find(|entry| entry.is_active(now) && entry.target.matches(ip, token));
+        if let Some(ban) = ban {
+            let mut reason = "You are banned from this server".to_string();
+            if let Some(until) = ban.until {
+                let minutes = (until - now + 59) / 60;
+                reason += &format!(" for {} more minute{}", minutes, if minutes == 1 { "" } else { "s" });
+            }
+            if !ban.reason.is_empty() {
+                reason += &format!(": {}", ban.reason);
+            }
+            return Err(reason + ".");
+        }
+
+        let active_allows = self.file.allows.iter()
+            .filter(|entry| entry.is_active(now))
+            .collect::<Vec<_>>();
+        let allowed = active_allows.is_empty() || active_allows.iter()
+            .any(|entry| entry.target.matches(ip, token) || (token_pending && matches!(entry.target, Target::Token(_))));
+        if !allowed {
+            return Err("This server only accepts invited players.".into());
+        }
+
+        Ok(())
+    }
+
+    pub fn ban(&mut self, target: Target, reason: String, until: Option<SystemTime>) {
+        self.file.bans.retain(|entry| entry.target != target);
+        self.file.bans.push(AccessEntry {
+            target,
+            reason,
+            until: until.map(unix_time),
+        });
+    }
+
+    /// Removes all bans of `target`, returns the number of removed entries.
+    pub fn unban(&mut self, target: &Target) -> usize {
+        let before = self.file.bans.len();
+        self.file.bans.retain(|entry| entry.target != *target);
+
+        before - self.file.bans.len()
+    }
+
+    /// Drops bans and allows which have expired.
+    pub fn prune(&mut self, now: SystemTime) -> usize {
+        let now = unix_time(now);
+        let before = self.file.bans.len() + self.file.allows.len();
+        self.file.bans.retain(|entry| entry.is_active(now));
+        self.file.allows.retain(|entry| entry.is_active(now));
+
+        before - self.file.bans.len() - self.file.allows.len()
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AccessFile, AccessList, Cidr, Target};
+    use std::convert::TryFrom;
+    use std::path::PathBuf;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn list(contents: &str) -> AccessList {
+        AccessList {
+            path: PathBuf::new(),
+            file: toml::from_str::<AccessFile>(contents).unwrap(),
+            modified: None,
+        }
+    }
+
+    #[test]
+    fn test_cidr() {
+        let range: Cidr = "10.1.0.0/16".parse().unwrap();
+        assert!(range.contains("10.1.2.3".parse().unwrap()));
+        assert!(!range.contains("10.2.0.1".parse().unwrap()));
+        assert!(!range.contains("::1".parse().unwrap()));
+
+        let everything: Cidr = "0.0.0.0/0".parse().unwrap();
+        assert!(everything.contains("192.168.1.1".parse().unwrap()));
+
+        let single: Cidr = "fd00::1/128".parse().unwrap();
+        assert!(single.contains("fd00::1".parse().unwrap()));
+        assert!(!single.contains("fd00::2".parse().unwrap()));
+
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+        assert!("10.0.0/8".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn test_parse_target() {
+        assert_eq!(Target::try_from("127.0.0.1".to_string()), Ok(Target::Ip("127.0.0.1".parse().unwrap())));
+        assert_eq!(Target::try_from("token:abc".to_string()), Ok(Target::Token("abc".into())));
+        assert!(matches!(Target::try_from("10.0.0.0/8".to_string()), Ok(Target::Range(_))));
+        assert!(Target::try_from("token:".to_string()).is_err());
+        assert!(Target::try_from("somebody".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_bans() {
+        let list = list(r#"
+            [[ban]]
+            target = "10.0.0.0/8"
+            reason = "cheating"
+
+            [[ban]]
+            target = "token:griefer"
+
+            [[ban]]
+            target = "192.168.1.10"
+            until = 1000
+        "#);
+        let ip = |ip: &str| ip.parse().unwrap();
+
+        let error = list.check(ip("10.20.30.40"), None, UNIX_EPOCH).unwrap_err();
+        assert!(error.contains("cheating"), "Reason is presented to the client: {}", error);
+
+        assert!(list.check(ip("127.0.0.1"), None, UNIX_EPOCH).is_ok());
+        assert!(list.check(ip("127.0.0.1"), Some("griefer"), UNIX_EPOCH).is_err());
+
+        let error = list.check(ip("192.168.1.10"), None, UNIX_EPOCH + Duration::from_secs(930)).unwrap_err();
+        assert!(error.contains("2 more minutes"), "Remaining time of a timed ban is presented: {}", error);
+        assert!(list.check(ip("192.168.1.10"), None, UNIX_EPOCH + Duration::from_secs(1000)).is_ok(), "Timed ban has expired");
+    }
+
+    #[test]
+    fn test_allow_list() {
+        let list = list(r#"
+            [[allow]]
+            target = "192.168.0.0/16"
+
+            [[allow]]
+            target = "token:friend"
+        "#);
+        let ip = |ip: &str| ip.parse().unwrap();
+
+        assert!(list.check(ip("192.168.5.5"), None, UNIX_EPOCH).is_ok());
+        assert!(list.check(ip("8.8.8.8"), Some("friend"), UNIX_EPOCH).is_ok());
+        assert!(list.check(ip("8.8.8.8"), Some("stranger"), UNIX_EPOCH).is_err());
+    }
+
+    #[test]
+    fn test_token_only_allow_list() {
+        let list = list(r#"
+            [[allow]]
+            target = "token:friend"
+
+            [[ban]]
+            target = "10.0.0.1"
+        "#);
+        let ip = |ip: &str| ip.parse().unwrap();
+
+        assert!(list.check_address(ip("8.8.8.8"), UNIX_EPOCH).is_ok(), "Token is checked once the greeting arrives");
+        assert!(list.check_address(ip("10.0.0.1"), UNIX_EPOCH).is_err(), "Bans of addresses apply before the greeting");
+        assert!(list.check(ip("8.8.8.8"), Some("friend"), UNIX_EPOCH).is_ok());
+        assert!(list.check(ip("8.8.8.8"), Some("stranger"), UNIX_EPOCH).is_err());
+    }
+
+    #[test]
+    fn test_ban_unban_roundtrip() {
+        let mut list = list("");
+        let target = Target::Token("abc".into());
+
+        list.ban(target.clone(), "spam".into(), Some(UNIX_EPOCH + Duration::from_secs(60)));
+        list.ban(target.clone(), "more spam".into(), None);
+        assert_eq!(list.file.bans.len(), 1, "Banning a target again replaces the previous ban");
+
+        let serialized = toml::to_string_pretty(&list.file).unwrap();
+        let deserialized: AccessFile = toml::from_str(&serialized).unwrap();
+        assert_eq!(deserialized.bans, list.file.bans);
+
+        assert_eq!(list.unban(&target), 1);
+        assert!(list.check("1.1.1.1".parse().unwrap(), Some("abc"), UNIX_EPOCH).is_ok());
+    }
+}
@@ -0,0 +1,153 @@
+use std::time::Instant;
+
+/// Hook applied to every chat message before it is relayed to other players.
+pub trait ChatFilter {
+    /// Returns the text to relay, or `None` to drop the message altogether.
+    fn filter(&self, text: &str) -> Option<String>;
+}
+
+/// Masks configured words with asterisks, case insensitive.
+pub struct WordFilter {
+    words: Vec<Vec<char>>,
+}
+
+/// Token bucket limiting how often a single player can send chat messages.
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl WordFilter {
+    pub fn new(words: &[String]) -> Self {
+        Self {
+            words: words.iter()
+                .map(|word| lowercase(word.trim()).0)
+                .filter(|word| !word.is_empty())
+                .collect(),
+        }
+    }
+}
+
+impl ChatFilter for WordFilter {
+    fn filter(&self, text: &str) -> Option<String> {
+        let mut chars = text.chars().collect::<Vec<_>>();
+        let (lowercase, origins) = lowercase(text);
+
+        for word in &self.words {
+            let mut start = 0;
+
+            while start + word.len() <= lowercase.len() {
+                if lowercase[start..start + word.len()] == word[..] {
+                    for c in &mut chars[origins[start]..=origins[start + word.len() - 1]] {
+                        *c = '*';
+                    }
+                    start += word.len();
+                } else {
+                    start += 1;
+                }
+            }
+        }
+
+        Some(chars.into_iter().collect())
+    }
+}
+
+impl RateLimiter {
+    pub fn new(rate: f64, burst: u32, now: Instant) -> Self {
+        Self {
+            rate,
+            burst: burst as f64,
+            tokens: burst as f64,
+            last: now,
+        }
+    }
+
+    /// Takes a token if one is available.
+    pub fn try_acquire(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.last = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Lowercase characters of `text` and the index of the character of `text` each comes from.
+/// Some characters have more than one lowercase character, like 'İ'.
+fn lowercase(text: &str) -> (Vec<char>, Vec<usize>) {
+    text.chars()
+        .enumerate()
+        .flat_map(|(i, c)| c.to_lowercase().map(move |lower| (lower, i)))
+        .unzip()
+}
+
+/// Strips control characters and surrounding whitespace and cuts the text to `max_length`
+/// characters. Returns `None` when nothing is left to send.
+pub fn sanitize_chat_text(text: &str, max_length: usize) -> Option<String> {
+    let text = text.chars()
+        .filter(|c| !c.is_control())
+        .collect::<String>();
+    let text = text.trim()
+        .chars()
+        .take(max_length)
+        .collect::<String>();
+
+    if text.is_empty() {
+        None
+    } else {
+        Some(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChatFilter, WordFilter, RateLimiter, sanitize_chat_text};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_sanitize_chat_text() {
+        assert_eq!(sanitize_chat_text("  hello \n", 10), Some("hello".into()));
+        assert_eq!(sanitize_chat_text("hello world", 5), Some("hello".into()));
+        assert_eq!(sanitize_chat_text(" \t\u{7} ", 10), None);
+    }
+
+    #[test]
+    fn test_word_filter() {
+        let filter = WordFilter::new(&["darn".into(), " ".into()]);
+
+        assert_eq!(filter.filter("well DARN it"), Some("well **** it".into()));
+        assert_eq!(filter.filter("darndarn"), Some("********".into()));
+        assert_eq!(filter.filter("nothing here"), Some("nothing here".into()));
+
+        let filter = WordFilter::new(&["İx".into()]);
+        assert_eq!(filter.filter("aİXb"), Some("a**b".into()), "Lowercase characters that expand match");
+        assert_eq!(filter.filter("a\u{130}xb İ"), Some("a**b İ".into()));
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(1.0, 3, start);
+
+        assert!(limiter.try_acquire(start));
+        assert!(limiter.try_acquire(start));
+        assert!(limiter.try_acquire(start));
+        assert!(!limiter.try_acquire(start), "Burst is exhausted");
+
+        assert!(!limiter.try_acquire(start + Duration::from_millis(500)));
+        assert!(limiter.try_acquire(start + Duration::from_millis(1000)), "A token is refilled after a second");
+
+        let later = start + Duration::from_secs(60);
+        for _ in 0..3 {
+            assert!(limiter.try_acquire(later));
+        }
+        assert!(!limiter.try_acquire(later), "Refill is capped at burst size");
+    }
+}
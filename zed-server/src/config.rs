@@ -0,0 +1,346 @@
+use serde::Deserialize;
+use structopt::StructOpt;
+use zed_shared::logging::LogConfig;
+use zed_shared::map::DEFAULT_MAPS_DIR;
+
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const DEFAULT_CONFIG_PATH: &str = "server.toml";
+
+#[derive(StructOpt, Debug, Default)]
+#[structopt(name = "zed-server", about = "Zed game server")]
+pub struct Opt {
+    /// Path to the server configuration file
+    #[structopt(short, long, parse(from_os_str))]
+    pub config: Option<PathBuf>,
+
+    /// Address to bind the server socket to, e.g. 127.0.0.1:10995
+    pub address: Option<SocketAddr>,
+
+    /// Number of server ticks per second
+    #[structopt(short, long)]
+    pub tick_rate: Option<u32>,
+
+    /// Maximum number of connected players
+    #[structopt(short, long)]
+    pub max_players: Option<usize>,
+
+    /// Server name presented to connecting players
+    #[structopt(short, long)]
+    pub name: Option<String>,
+
+    /// Message of the day presented to connecting players
+    #[structopt(long)]
+    pub motd: Option<String>,
+
+    /// Password for the remote console, the remote console is disabled without it
+    #[structopt(long)]
+    pub rcon_password: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub address: SocketAddr,
+    pub tick_rate: u32,
+    pub max_players: usize,
+    pub name: String,
+    pub motd: String,
+    pub map: String,
+    /// Directory with the map files, `map` is loaded from `<maps_dir>/<map>.json` or `.tmx`.
+    pub maps_dir: PathBuf,
+    /// Remote console is disabled when no password is set.
+    pub rcon_password: Option<String>,
+    /// File with ban and allow lists, reloaded whenever it changes.
+    pub access_list: PathBuf,
+    /// Address of the HTTP endpoint serving Prometheus metrics, disabled when not set.
+    pub metrics_address: Option<SocketAddr>,
+    pub network: NetworkConfig,
+    pub chat: ChatConfig,
+    pub log: LogConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkConfig {
+    /// Interval of keep-alive packets sent to idle clients, 0 disables heartbeats.
+    pub heartbeat_interval_ms: u64,
+    /// Time without any packet after which a client is considered disconnected.
+    pub idle_timeout_ms: u64,
+    pub max_packet_size: usize,
+    pub max_packets_in_flight: u16,
+    /// How long the server keeps delivering packets to clients after announcing a shutdown.
+    pub shutdown_grace_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChatConfig {
+    /// Longer messages are cut, in characters.
+    pub max_length: usize,
+    /// Sustained number of messages a player can send per second.
+    pub messages_per_second: f64,
+    /// Number of messages a player can send in a quick succession.
+    pub burst: u32,
+    /// Words masked out by the default chat filter.
+    pub filtered_words: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Invalid(String),
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            address: "127.0.0.1:10995".parse().unwrap(),
+            tick_rate: 60,
+            max_players: 16,
+            name: "Zed server".into(),
+            motd: String::new(),
+            map: "default".into(),
+            maps_dir: DEFAULT_MAPS_DIR.into(),
+            rcon_password: None,
+            access_list: "access.toml".into(),
+            metrics_address: None,
+            network: NetworkConfig::default(),
+            chat: ChatConfig::default(),
+            log: LogConfig::default(),
+        }
+    }
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            heartbeat_interval_ms: 1000,
+            idle_timeout_ms: 5000,
+            max_packet_size: 16 * 1024,
+            max_packets_in_flight: 512,
+            shutdown_grace_ms: 500,
+        }
+    }
+}
+
+impl Default for ChatConfig {
+    fn default() -> Self {
+        Self {
+            max_length: 120,
+            messages_per_second: 1.0,
+            burst: 5,
+            filtered_words: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the configuration file pointed to by `opt` (or `server.toml` if it exists),
+    /// applies command line overrides and validates the result.
+    pub fn load(opt: &Opt) -> Result<Self, ConfigError> {
+        let mut config = match &opt.config {
+            Some(path) => Self::from_file(path)?,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => Self::from_file(DEFAULT_CONFIG_PATH)?,
+            None => Self::default(),
+        };
+
+        config.apply(opt);
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| ConfigError::Io(path.to_owned(), e))?;
+
+        toml::from_str(&contents)
+            .map_err(|e| ConfigError::Parse(path.to_owned(), e))
+    }
+
+    pub fn apply(&mut self, opt: &Opt) {
+        if let Some(address) = opt.address {
+            self.address = address;
+        }
+        if let Some(tick_rate) = opt.tick_rate {
+            self.tick_rate = tick_rate;
+        }
+        if let Some(max_players) = opt.max_players {
+            self.max_players = max_players;
+        }
+        if let Some(name) = &opt.name {
+            self.name = name.clone();
+        }
+        if let Some(motd) = &opt.motd {
+            self.motd = motd.clone();
+        }
+        if let Some(password) = &opt.rcon_password {
+            self.rcon_password = Some(password.clone());
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.tick_rate == 0 || self.tick_rate > 1000 {
+            return Err(ConfigError::Invalid(
+                format!("tick_rate must be between 1 and 1000, got {}", self.tick_rate)
+            ));
+        }
+        if self.max_players == 0 {
+            return Err(ConfigError::Invalid("max_players must be at least 1".into()));
+        }
+        if self.name.trim().is_empty() {
+            return Err(ConfigError::Invalid("name must not be empty".into()));
+        }
+        if self.map.trim().is_empty() {
+            return Err(ConfigError::Invalid("map must not be empty".into()));
+        }
+        if self.rcon_password.as_ref().map_or(false, |password| password.is_empty()) {
+            return Err(ConfigError::Invalid("rcon_password must not be empty, remove it to disable the remote console".into()));
+        }
+
+        let network = &self.network;
+        if network.idle_timeout_ms == 0 {
+            return Err(ConfigError::Invalid("network.idle_timeout_ms must be greater than 0".into()));
+        }
+        if network.heartbeat_interval_ms >= network.idle_timeout_ms {
+            return Err(ConfigError::Invalid(format!(
+                "network.heartbeat_interval_ms ({}) must be lower than network.idle_timeout_ms ({})",
+                network.heartbeat_interval_ms, network.idle_timeout_ms
+            )));
+        }
+        if network.max_packets_in_flight == 0 {
+            return Err(ConfigError::Invalid("network.max_packets_in_flight must be at least 1".into()));
+        }
+
+        let chat = &self.chat;
+        if chat.max_length == 0 || chat.max_length > 500 {
+            return Err(ConfigError::Invalid(
+                format!("chat.max_length must be between 1 and 500, got {}", chat.max_length)
+            ));
+        }
+        if !(chat.messages_per_second > 0.0) {
+            return Err(ConfigError::Invalid("chat.messages_per_second must be greater than 0".into()));
+        }
+        if chat.burst == 0 {
+            return Err(ConfigError::Invalid("chat.burst must be at least 1".into()));
+        }
+
+        Ok(())
+    }
+
+    pub fn shutdown_grace(&self) -> Duration {
+        Duration::from_millis(self.network.shutdown_grace_ms)
+    }
+
+    pub fn tick_duration(&self) -> Duration {
+        Duration::from_secs(1) / self.tick_rate
+    }
+
+    pub fn laminar(&self) -> laminar::Config {
+        let network = &self.network;
+        let heartbeat_interval = match network.heartbeat_interval_ms {
+            0 => None,
+            ms => Some(Duration::from_millis(ms)),
+        };
+
+        laminar::Config {
+            heartbeat_interval,
+            idle_connection_timeout: Duration::from_millis(network.idle_timeout_ms),
+            max_packet_size: network.max_packet_size,
+            max_packets_in_flight: network.max_packets_in_flight,
+            ..laminar::Config::default()
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "cannot read {}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "cannot parse {}: {}", path.display(), e),
+            ConfigError::Invalid(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, Opt};
+
+    #[test]
+    fn test_defaults_are_valid() {
+        assert!(Config::default().validate().is_ok());
+    }
+
+    #[test]
+    fn test_parse_partial_file() {
+        let config: Config = toml::from_str(r#"
+            address = "0.0.0.0:2000"
+            max_players = 4
+
+            [network]
+            idle_timeout_ms = 10000
+        "#).unwrap();
+
+        assert_eq!(config.address, "0.0.0.0:2000".parse().unwrap());
+        assert_eq!(config.max_players, 4);
+        assert_eq!(config.tick_rate, Config::default().tick_rate, "Missing keys fall back to defaults");
+        assert_eq!(config.network.idle_timeout_ms, 10000);
+        assert_eq!(config.network.heartbeat_interval_ms, 1000);
+    }
+
+    #[test]
+    fn test_unknown_keys_are_rejected() {
+        assert!(toml::from_str::<Config>("tickrate = 30").is_err());
+    }
+
+    #[test]
+    fn test_cli_overrides_file() {
+        let mut config: Config = toml::from_str(r#"
+            name = "From file"
+            tick_rate = 30
+        "#).unwrap();
+
+        config.apply(&Opt {
+            tick_rate: Some(20),
+            motd: Some("Hello".into()),
+            ..Opt::default()
+        });
+
+        assert_eq!(config.name, "From file");
+        assert_eq!(config.tick_rate, 20);
+        assert_eq!(config.motd, "Hello");
+    }
+
+    #[test]
+    fn test_validation() {
+        let mut config = Config::default();
+        config.tick_rate = 0;
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.max_players = 0;
+        assert!(config.validate().is_err());
+
+        let mut config = Config::default();
+        config.network.heartbeat_interval_ms = 5000;
+        config.network.idle_timeout_ms = 5000;
+        assert!(config.validate().is_err(), "Heartbeat must be more frequent than the idle timeout");
+
+        let mut config = Config::default();
+        config.network.heartbeat_interval_ms = 0;
+        assert!(config.validate().is_ok(), "Heartbeats can be disabled");
+
+        let mut config = Config::default();
+        config.chat.messages_per_second = 0.0;
+        assert!(config.validate().is_err());
+    }
+}
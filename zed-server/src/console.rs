@@ -0,0 +1,169 @@
+use crossbeam_channel::{unbounded, Receiver};
+
+use std::convert::TryFrom;
+use std::io::BufRead;
+use std::time::Duration;
+
+use crate::access::Target;
+
+pub const HELP: &str = "\
+status                   list connected players
+kick <player_id> [reason]
+ban <player_id> [reason] kick and refuse further connections from the player's address and identity
+tempban <player_id> <minutes> [reason]
+unban <target>           remove bans of an address, a range or token:<identity>
+say <text>               send a chat message to everyone
+map <name>               change the current map
+set tickrate <n>         change the number of server ticks per second
+shutdown [reason]        disconnect all players and stop the server
+help                     show this message";
+
+pub const DEFAULT_SHUTDOWN_REASON: &str = "Server is shutting down.";
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Status,
+    Kick { player_id: usize, reason: String },
+    Ban { player_id: usize, duration: Option<Duration>, reason: String },
+    Unban(Target),
+    Say(String),
+    Map(String),
+    SetTickRate(u32),
+    Shutdown(String),
+    Help,
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let line = line.trim();
+        let (name, args) = match line.find(char::is_whitespace) {
+            Some(i) => (&line[..i], line[i..].trim()),
+            None => (line, ""),
+        };
+
+        match name {
+            "status" => Ok(Command::Status),
+            "kick" => {
+                let (player_id, reason) = parse_player_and_reason(args, "kick")?;
+                Ok(Command::Kick { player_id, reason })
+            },
+            "ban" => {
+                let (player_id, reason) = parse_player_and_reason(args, "ban")?;
+                Ok(Command::Ban { player_id, duration: None, reason })
+            },
+            "tempban" => {
+                let usage = || "usage: tempban <player_id> <minutes> [reason]".to_string();
+                let (player_id, rest) = parse_player_and_reason(args, "tempban")?;
+                let (minutes, reason) = match rest.find(char::is_whitespace) {
+                    Some(i) => (&rest[..i], rest[i..].trim()),
+                    None => (rest.as_str(), ""),
+                };
+                let minutes: u64 = minutes.parse().map_err(|_| usage())?;
+                let seconds = match minutes.checked_mul(60) {
+                    Some(seconds) if seconds > 0 => seconds,
+                    _ => return Err(usage()),
+                };
+
+                Ok(Command::Ban {
+                    player_id,
+                    duration: Some(Duration::from_secs(seconds)),
+                    reason: reason.into(),
+                })
+            },
+            "unban" if !args.is_empty() => Target::try_from(args.to_string()).map(Command::Unban),
+            "unban" => Err("usage: unban <address | range | token:identity>".into()),
+            "say" if !args.is_empty() => Ok(Command::Say(args.into())),
+            "say" => Err("usage: say <text>".into()),
+            "map" if !args.is_empty() => Ok(Command::Map(args.into())),
+            "map" => Err("usage: map <name>".into()),
+            "set" => {
+                let mut args = args.split_whitespace();
+                match (args.next(), args.next()) {
+                    (Some("tickrate"), Some(value)) => value.parse()
+                        .map(Command::SetTickRate)
+                        .map_err(|_| format!("invalid tick rate \"{}\"", value)),
+                    _ => Err("usage: set tickrate <n>".into()),
+                }
+            },
+            "shutdown" | "quit" | "exit" if args.is_empty() => Ok(Command::Shutdown(DEFAULT_SHUTDOWN_REASON.into())),
+            "shutdown" | "quit" | "exit" => Ok(Command::Shutdown(args.into())),
+            "help" | "?" => Ok(Command::Help),
+            "" => Err("empty command".into()),
+            _ => Err(format!("unknown command \"{}\", type \"help\" for the list of commands", name)),
+        }
+    }
+}
+
+fn parse_player_and_reason(args: &str, command: &str) -> Result<(usize, String), String> {
+    let mut parts = args.splitn(2, char::is_whitespace);
+    let player_id = parts.next()
+        .and_then(|id| id.parse().ok())
+        .ok_or_else(|| format!("usage: {} <player_id> [reason]", command))?;
+    let reason = parts.next().map(str::trim).unwrap_or("").to_string();
+
+    Ok((player_id, reason))
+}
+
+/// Reads lines from stdin on a background thread.
+///
+/// The thread is never joined, it stays blocked on stdin until the process exits.
+pub fn spawn_stdin_reader() -> Receiver<String> {
+    let (sender, receiver) = unbounded();
+
+    std::thread::spawn(move || {
+        let stdin = std::io::stdin();
+        for line in stdin.lock().lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Command, DEFAULT_SHUTDOWN_REASON};
+    use crate::access::Target;
+    use std::time::Duration;
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(Command::parse("status"), Ok(Command::Status));
+        assert_eq!(Command::parse("  shutdown  "), Ok(Command::Shutdown(DEFAULT_SHUTDOWN_REASON.into())));
+        assert_eq!(Command::parse("shutdown back in 5"), Ok(Command::Shutdown("back in 5".into())));
+        assert_eq!(Command::parse("say hello  there"), Ok(Command::Say("hello  there".into())));
+        assert_eq!(Command::parse("map arena"), Ok(Command::Map("arena".into())));
+        assert_eq!(Command::parse("set tickrate 30"), Ok(Command::SetTickRate(30)));
+        assert_eq!(Command::parse("kick 3"), Ok(Command::Kick { player_id: 3, reason: String::new() }));
+        assert_eq!(
+            Command::parse("ban 4 being rude"),
+            Ok(Command::Ban { player_id: 4, duration: None, reason: "being rude".into() })
+        );
+        assert_eq!(
+            Command::parse("tempban 4 30 cool down"),
+            Ok(Command::Ban { player_id: 4, duration: Some(Duration::from_secs(1800)), reason: "cool down".into() })
+        );
+        assert_eq!(Command::parse("unban 10.0.0.1"), Ok(Command::Unban(Target::Ip("10.0.0.1".parse().unwrap()))));
+    }
+
+    #[test]
+    fn test_parse_invalid_commands() {
+        assert!(Command::parse("").is_err());
+        assert!(Command::parse("say").is_err());
+        assert!(Command::parse("kick").is_err());
+        assert!(Command::parse("kick bob").is_err());
+        assert!(Command::parse("set tickrate fast").is_err());
+        assert!(Command::parse("set gravity 2").is_err());
+        assert!(Command::parse("dance").is_err());
+        assert!(Command::parse("tempban 4").is_err());
+        assert!(Command::parse("tempban 4 0").is_err());
+        assert!(Command::parse("tempban 1 18446744073709551615").is_err(), "Minutes overflowing seconds are refused");
+        assert!(Command::parse("unban someone").is_err());
+    }
+}
@@ -1,83 +1,158 @@
 use std::time::{Instant, Duration};
 use std::sync::{Arc, atomic::AtomicBool, atomic::Ordering};
-use laminar::{ErrorKind, SocketEvent, Socket, Packet};
+use laminar::{ErrorKind, SocketEvent, Socket};
 use simple_signal::{self, Signal};
-use crossbeam_channel::{Sender};
+use structopt::StructOpt;
+use tracing::{error, info, trace_span};
+use zed_shared::logging;
+use zed_shared::map::TileMap;
 
-use zed_shared::protocol::{Protocol, SimpleProtocol, register_messages};
-use zed_shared::message::{both, from_client, from_server};
-use std::collections::HashMap;
+mod access;
+mod chat;
+mod config;
+mod console;
+mod metrics;
+mod server;
+mod world;
 
-const ADDR: &str = "127.0.0.1:10995";
+use access::AccessList;
+use config::{Config, Opt};
+use console::Command;
+use metrics::Metrics;
+use server::Server;
 
 fn main() -> Result<(), ErrorKind> {
-    let addr: String;
+    let config = match Config::load(&Opt::from_args()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Invalid server configuration: {}", e);
+            std::process::exit(2);
+        }
+    };
 
-    match std::env::args().skip(1).nth(0) {
-        Some(a) => addr = a.clone(),
-        None => addr = ADDR.to_string()
+    let log_guard = match logging::init(&config.log) {
+        Ok(guard) => guard,
+        Err(e) => {
+            eprintln!("Invalid logging configuration: {}", e);
+            std::process::exit(2);
+        }
     };
 
-    let mut clients = Vec::new();
+    let access = match AccessList::load(&config.access_list) {
+        Ok(access) => access,
+        Err(e) => {
+            error!("Invalid access list: {}", e);
+            drop(log_guard);
+            std::process::exit(2);
+        }
+    };
+
+    let map = match TileMap::load(&config.maps_dir, &config.map) {
+        Ok(map) => map,
+        Err(e) => {
+            error!("Cannot load map {}: {}", config.map, e);
+            drop(log_guard);
+            std::process::exit(2);
+        }
+    };
 
-    let mut socket = Socket::bind(addr)?;
-    println!("Server on {}", socket.local_addr().unwrap());
+    let mut socket = Socket::bind_with_config(config.address, config.laminar())?;
+    info!(name = %config.name, addr = %socket.local_addr().unwrap(), "Server started");
     let (sender, receiver) = (socket.get_packet_sender(), socket.get_event_receiver());
 
-    let mut protocol = SimpleProtocol::new();
-    register_messages(&mut protocol);
+    let mut metrics = Metrics::new();
+    if let Some(addr) = config.metrics_address {
+        if let Err(e) = metrics.spawn_endpoint(addr) {
+            error!("Cannot serve metrics on {}: {}", addr, e);
+            drop(log_guard);
+            std::process::exit(2);
+        }
+    }
+
+    let mut server = Server::new(config, sender, access, map);
+    metrics.update_snapshot(&server);
+    let console = console::spawn_stdin_reader();
 
+    // `should_run` is cleared by signals, `polling` keeps the socket alive until the
+    // shutdown notice had a chance to reach the clients.
     let should_run = Arc::new(AtomicBool::new(true));
+    let polling = Arc::new(AtomicBool::new(true));
     let thread;
     {
         let should_run = should_run.clone();
-        simple_signal::set_handler(&[Signal::Int], move |_signals| {
+        simple_signal::set_handler(&[Signal::Int, Signal::Term], move |_signals| {
             should_run.store(false, Ordering::Relaxed);
         });
     }
     {
-        let should_run = should_run.clone();
+        let polling = polling.clone();
         thread = std::thread::spawn(move || {
-            while should_run.load(Ordering::Relaxed) {
+            while polling.load(Ordering::Relaxed) {
                 socket.manual_poll(Instant::now());
                 std::thread::sleep(Duration::from_micros(100));
             }
         });
     }
 
-    while should_run.load(Ordering::Relaxed) {
-        if let Ok(event) = receiver.recv() {
+    let mut last_housekeeping = Instant::now();
+    while should_run.load(Ordering::Relaxed) && server.is_running() {
+        let tick_start = Instant::now();
+        let tick = trace_span!("tick").entered();
+
+        if last_housekeeping.elapsed() >= Duration::from_secs(1) {
+            server.reload_access_list();
+            metrics.update_snapshot(&server);
+            last_housekeeping = tick_start;
+        }
+
+        while let Ok(line) = console.try_recv() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match Command::parse(&line) {
+                Ok(command) => println!("{}", server.execute(command)),
+                Err(e) => println!("{}", e),
+            }
+        }
+
+        while let Ok(event) = receiver.try_recv() {
             match event {
-                SocketEvent::Connect(addr) => {
-                    if clients.iter().find(|&&a| a == addr).is_some() {
-                        break;
-                    }
-
-                    println!("Client connected from {}", addr);
-                    let client_id = clients.len();
-                    clients.push(addr);
-
-                    protocol.send_reliable_unordered(&sender, addr, from_server::GreetingResponse {
-                        player_id: client_id
-                    });
-                },
-                SocketEvent::Timeout(addr) => {
-                    println!("Client timeout {}", addr);
-                }
-                SocketEvent::Packet(packet) => {
-                    println!("Received a packet from {}", packet.addr());
-                    for c in &clients {
-                        sender.try_send(Packet::reliable_unordered(
-                            *c,
-                            packet.payload().to_vec()
-                        )).unwrap();
-                    }
-                },
+                SocketEvent::Connect(addr) => server.connect(addr),
+                SocketEvent::Timeout(addr) => server.disconnect(addr),
+                SocketEvent::Packet(packet) => server.receive(packet),
                 _ => ()
             }
         }
+
+        server.update();
+        drop(tick);
+        metrics.observe_tick(tick_start.elapsed());
+
+        if let Some(remaining) = server.tick_duration().checked_sub(tick_start.elapsed()) {
+            std::thread::sleep(remaining);
+        }
+    }
+
+    if server.is_running() {
+        info!("Shutting down");
+        server.shutdown(console::DEFAULT_SHUTDOWN_REASON);
+    }
+
+    // Keep polling so the shutdown notice is sent and resent until acknowledged,
+    // anything the clients send in the meantime is dropped.
+    let grace_end = Instant::now() + server.shutdown_grace();
+    while Instant::now() < grace_end {
+        while receiver.try_recv().is_ok() {}
+        std::thread::sleep(server.tick_duration().min(grace_end.saturating_duration_since(Instant::now())));
+    }
+    polling.store(false, Ordering::Relaxed);
+
+    if thread.join().is_err() {
+        error!("Network thread panicked");
+        drop(log_guard);
+        std::process::exit(1);
     }
 
-    thread.join();
+    info!("Server stopped");
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,249 @@
+use std::fmt::Write as _;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tracing::{debug, info, warn};
+
+use crate::server::Server;
+
+/// Upper bounds of the tick duration histogram buckets, in seconds.
+const TICK_BUCKETS: &[f64] = &[0.0005, 0.001, 0.0025, 0.005, 0.01, 0.0166, 0.025, 0.05, 0.1];
+/// Requests answered at the same time, further connections are closed right away.
+const MAX_CONNECTIONS: usize = 8;
+/// Time a client gets to send its request, and again to read the answer.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Cumulative histogram in the Prometheus sense, every bucket counts observations below its bound.
+pub struct Histogram {
+    bounds: &'static [f64],
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+/// Server statistics not tracked by `Server` itself, rendered in the Prometheus text format.
+pub struct Metrics {
+    tick_duration: Histogram,
+    /// Last rendered snapshot, shared with the HTTP thread.
+    snapshot: Arc<Mutex<String>>,
+}
+
+impl Histogram {
+    pub fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            counts: vec![0; bounds.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    pub fn observe(&mut self, value: f64) {
+        for (bound, count) in self.bounds.iter().zip(self.counts.iter_mut()) {
+            if value <= *bound {
+                *count += 1;
+            }
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        writeln!(out, "# HELP {} {}", name, help).unwrap();
+        writeln!(out, "# TYPE {} histogram", name).unwrap();
+        for (bound, count) in self.bounds.iter().zip(&self.counts) {
+            writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, count).unwrap();
+        }
+        writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, self.count).unwrap();
+        writeln!(out, "{}_sum {}", name, self.sum).unwrap();
+        writeln!(out, "{}_count {}", name, self.count).unwrap();
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self {
+            tick_duration: Histogram::new(TICK_BUCKETS),
+            snapshot: Arc::new(Mutex::new(String::new())),
+        }
+    }
+
+    pub fn observe_tick(&mut self, duration: Duration) {
+        self.tick_duration.observe(duration.as_secs_f64());
+    }
+
+    /// Renders the current state for the HTTP endpoint. The server is not `Send`,
+    /// so the endpoint serves the latest snapshot instead of asking the server directly.
+    pub fn update_snapshot(&self, server: &Server) {
+        let text = self.render(server);
+        *self.snapshot.lock().unwrap() = text;
+    }
+
+    pub fn render(&self, server: &Server) -> String {
+        let mut out = String::new();
+        let (in_game, pending) = server.player_counts();
+
+        gauge(&mut out, "zed_players_connected", "Players in game.", in_game as u64);
+        gauge(&mut out, "zed_clients_pending", "Connected clients that have not sent their greeting yet.", pending as u64);
+        self.tick_duration.render(&mut out, "zed_tick_duration_seconds", "Time spent processing a server tick.");
+
+        let protocol = server.protocol();
+        let stats = protocol.message_stats().collect::<Vec<_>>();
+        let per_message: &[(&str, &str, fn(&zed_shared::protocol::MessageStats) -> u64)] = &[
+            ("zed_packets_sent_total", "Packets sent per message type.", |s| s.sent_packets),
+            ("zed_bytes_sent_total", "Payload bytes sent per message type.", |s| s.sent_bytes),
+            ("zed_packets_received_total", "Packets received per message type.", |s| s.received_packets),
+            ("zed_bytes_received_total", "Payload bytes received per message type.", |s| s.received_bytes),
+        ];
+        for (name, help, value) in per_message {
+            writeln!(out, "# HELP {} {}", name, help).unwrap();
+            writeln!(out, "# TYPE {} counter", name).unwrap();
+            for (message, stats) in &stats {
+                writeln!(out, "{}{{message=\"{}\"}} {}", name, message, value(stats)).unwrap();
+            }
+        }
+
+        counter(&mut out, "zed_decode_errors_total", "Received packets that could not be decoded.", protocol.decode_errors());
+        counter(&mut out, "zed_dropped_packets_total", "Received packets that were not processed.", server.dropped_packets());
+
+        out
+    }
+
+    /// Serves the latest snapshot on `addr` from a background thread and returns the address
+    /// it listens on. Every connection is answered on a thread of its own, so a slow client
+    /// does not hold up the others.
+    ///
+    /// Like the console reader the threads are never joined, they end with the process.
+    pub fn spawn_endpoint(&self, addr: SocketAddr) -> std::io::Result<SocketAddr> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let snapshot = self.snapshot.clone();
+        let connections = Arc::new(AtomicUsize::new(0));
+        info!(%addr, "Serving metrics");
+
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        warn!(error = %e, "Metrics connection failed");
+                        continue;
+                    }
+                };
+                if connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                    connections.fetch_sub(1, Ordering::SeqCst);
+                    debug!("Too many metrics connections, closing one");
+                    continue;
+                }
+
+                let (snapshot, connections) = (snapshot.clone(), connections.clone());
+                std::thread::spawn(move || {
+                    if let Err(e) = respond(stream, &snapshot) {
+                        warn!(error = %e, "Metrics request failed");
+                    }
+                    connections.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        });
+
+        Ok(addr)
+    }
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: u64) {
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} gauge", name).unwrap();
+    writeln!(out, "{} {}", name, value).unwrap();
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: u64) {
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} counter", name).unwrap();
+    writeln!(out, "{} {}", name, value).unwrap();
+}
+
+/// Answers a single HTTP request, only `GET /metrics` is supported.
+fn respond(mut stream: TcpStream, snapshot: &Mutex<String>) -> std::io::Result<()> {
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+
+    // The timeout is for the whole request, not for every read.
+    let deadline = Instant::now() + REQUEST_TIMEOUT;
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") && request.len() < 8 * 1024 {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining == Duration::from_secs(0) {
+            return Err(std::io::ErrorKind::TimedOut.into());
+        }
+        stream.set_read_timeout(Some(remaining))?;
+
+        let read = stream.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buffer[..read]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let mut parts = request.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", snapshot.lock().unwrap().clone()),
+        _ => ("404 Not Found", "Not found, try /metrics\n".to_string()),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, body.len(), body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Histogram, Metrics, REQUEST_TIMEOUT};
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::time::Instant;
+
+    #[test]
+    fn test_idle_client_does_not_block_endpoint() {
+        let metrics = Metrics::new();
+        *metrics.snapshot.lock().unwrap() = "zed_players_connected 1\n".into();
+        let addr = metrics.spawn_endpoint("127.0.0.1:0".parse().unwrap()).unwrap();
+
+        let _idle = TcpStream::connect(addr).unwrap();
+        let start = Instant::now();
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"GET /metrics HTTP/1.1\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        assert!(response.ends_with("\r\n\r\nzed_players_connected 1\n"), "{}", response);
+        assert!(start.elapsed() < REQUEST_TIMEOUT / 2, "Answered while another client is idle");
+    }
+
+    #[test]
+    fn test_histogram() {
+        let mut histogram = Histogram::new(&[0.1, 1.0]);
+        histogram.observe(0.05);
+        histogram.observe(0.5);
+        histogram.observe(2.0);
+
+        let mut out = String::new();
+        histogram.render(&mut out, "test", "Test histogram.");
+
+        assert_eq!(out, "\
+# HELP test Test histogram.
+# TYPE test histogram
+test_bucket{le=\"0.1\"} 1
+test_bucket{le=\"1\"} 2
+test_bucket{le=\"+Inf\"} 3
+test_sum 2.55
+test_count 3
+");
+    }
+}
@@ -0,0 +1,768 @@
+use bottles::Queue;
+use crossbeam_channel::Sender;
+use laminar::Packet;
+use serde::{Serialize, de::DeserializeOwned};
+use tracing::{debug, info, warn};
+
+use zed_shared::protocol::{Protocol, SimpleProtocol, register_messages};
+use zed_shared::message::{both, from_client, from_server, ChatChannel, MAX_PLAYER_NAME_LENGTH};
+use zed_shared::map::TileMap;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::net::{IpAddr, SocketAddr};
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::access::{AccessList, Target};
+use crate::chat::{self, ChatFilter, RateLimiter, WordFilter};
+use crate::config::Config;
+use crate::console::{self, Command};
+use crate::world::ServerWorld;
+
+const TEAM_COUNT: usize = 2;
+const MAX_RCON_FAILURES: u32 = 3;
+/// How long an address that failed `MAX_RCON_FAILURES` remote console logins is refused.
+const RCON_LOCKOUT: Duration = Duration::from_secs(300);
+/// Room left in a packet for the message header and names next to the map contents.
+const MAP_DATA_OVERHEAD: usize = 256;
+
+pub struct PlayerInfo {
+    pub id: usize,
+    /// Set once the client has sent its `Greeting`, players without a name are not in game yet.
+    pub name: Option<String>,
+    pub token: Option<String>,
+    pub team: usize,
+    chat_limiter: RateLimiter,
+    rcon_authenticated: bool,
+}
+
+/// Failed remote console logins of each address, kept across reconnects.
+#[derive(Default)]
+struct RconLockout {
+    /// Number of failures and time of the last one.
+    failures: HashMap<IpAddr, (u32, Instant)>,
+}
+
+pub struct Server {
+    config: Config,
+    sender: Sender<Packet>,
+    protocol: SimpleProtocol,
+    queue: Rc<RefCell<Queue<Server>>>,
+    chat_filter: Box<dyn ChatFilter>,
+    world: ServerWorld,
+
+    players: HashMap<SocketAddr, PlayerInfo>,
+    next_player_id: usize,
+    /// Address of the client whose packet is currently being dispatched.
+    current_addr: Option<SocketAddr>,
+    access: AccessList,
+    rcon_lockout: RconLockout,
+    running: bool,
+    /// Packets that could not be decoded.
+    dropped_packets: u64,
+}
+
+impl Server {
+    pub fn new(config: Config, sender: Sender<Packet>, access: AccessList, map: TileMap) -> Self {
+        let mut protocol = SimpleProtocol::new();
+        register_messages(&mut protocol);
+
+        let chat_filter = Box::new(WordFilter::new(&config.chat.filtered_words));
+        let mut server = Self {
+            config,
+            sender,
+            protocol,
+            queue: Rc::new(RefCell::new(Queue::new())),
+            chat_filter,
+            world: ServerWorld::new(map),
+            players: HashMap::new(),
+            next_player_id: 0,
+            current_addr: None,
+            access,
+            rcon_lockout: RconLockout::default(),
+            running: true,
+            dropped_packets: 0,
+        };
+
+        server.subscribe(Self::receive_greeting);
+        server.subscribe(Self::receive_leave);
+        server.subscribe(Self::receive_change_name);
+        server.subscribe(Self::receive_player_status);
+        server.subscribe(Self::receive_chat);
+        server.subscribe(Self::receive_rcon_auth);
+        server.subscribe(Self::receive_rcon_command);
+        server.subscribe(Self::receive_map_request);
+
+        server
+    }
+
+    fn subscribe<M, F>(&mut self, f: F)
+        where
+            F: FnMut(&mut Server, Rc<M>) + 'static,
+            M: 'static + DeserializeOwned,
+    {
+        let mut queue = self.queue.borrow_mut();
+        queue.register::<M>(self.protocol.dispatcher_mut());
+        queue.subscribe(self.protocol.dispatcher_mut(), f);
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    pub fn tick_duration(&self) -> Duration {
+        self.config.tick_duration()
+    }
+
+    /// Number of players in game and of clients that have not sent their greeting yet.
+    pub fn player_counts(&self) -> (usize, usize) {
+        let in_game = self.players.values().filter(|player| player.name.is_some()).count();
+        (in_game, self.players.len() - in_game)
+    }
+
+    pub fn dropped_packets(&self) -> u64 {
+        self.dropped_packets
+    }
+
+    pub fn protocol(&self) -> &SimpleProtocol {
+        &self.protocol
+    }
+
+    pub fn shutdown_grace(&self) -> Duration {
+        self.config.shutdown_grace()
+    }
+
+    /// Stops accepting connections and tells every connected client why the server is going away.
+    pub fn shutdown(&mut self, reason: &str) {
+        if !self.running {
+            return;
+        }
+        self.running = false;
+
+        let addrs = self.players.drain().map(|(addr, _)| addr).collect::<Vec<_>>();
+        for addr in addrs {
+            self.send(addr, from_server::ServerShutdown { reason: reason.into() });
+        }
+    }
+
+    pub fn connect(&mut self, addr: SocketAddr) {
+        if !self.running || self.players.contains_key(&addr) {
+            return;
+        }
+        if let Err(reason) = self.access.check_address(addr.ip(), SystemTime::now()) {
+            info!(%addr, %reason, "Refusing client");
+            self.send(addr, from_server::Kicked { reason });
+            return;
+        }
+        if self.players.len() >= self.config.max_players {
+            info!(%addr, max_players = self.config.max_players, "Refusing client, server is full");
+            return;
+        }
+
+        info!(%addr, "Client connected");
+        let player_id = self.next_player_id;
+        self.next_player_id += 1;
+        let team = self.smallest_team();
+        let chat_limiter = RateLimiter::new(self.config.chat.messages_per_second, self.config.chat.burst, Instant::now());
+        self.players.insert(addr, PlayerInfo {
+            id: player_id,
+            name: None,
+            token: None,
+            team,
+            chat_limiter,
+            rcon_authenticated: false,
+        });
+    }
+
+    pub fn disconnect(&mut self, addr: SocketAddr) {
+        if let Some(player) = self.remove_player(addr) {
+            info!(%addr, player_id = player.id, "Client timeout");
+        }
+    }
+
+    /// Takes the player at `addr` out of the game and tells the others if it was in game.
+    fn remove_player(&mut self, addr: SocketAddr) -> Option<PlayerInfo> {
+        let player = self.players.remove(&addr)?;
+        self.world.remove_player(player.id);
+
+        if player.name.is_some() {
+            self.broadcast(from_server::PlayerLeft { player_id: player.id });
+        }
+
+        Some(player)
+    }
+
+    /// Runs an administrative command and returns its output.
+    pub fn execute(&mut self, command: Command) -> String {
+        match command {
+            Command::Status => {
+                let mut output = format!(
+                    "{} on map {}, {}/{} players, {} ticks per second",
+                    self.config.name, self.config.map, self.players.len(), self.config.max_players, self.config.tick_rate
+                );
+
+                let mut players = self.players.iter().collect::<Vec<_>>();
+                players.sort_by_key(|(_, player)| player.id);
+                for (addr, player) in players {
+                    let name = player.name.as_ref().map(String::as_str).unwrap_or("<connecting>");
+                    write!(output, "\n{:>4} {:<16} team {} {}", player.id, name, player.team, addr).unwrap();
+                }
+
+                output
+            },
+            Command::Kick { player_id, reason } => match self.kick(player_id, &reason) {
+                Some(addr) => format!("Kicked player {} ({})", player_id, addr),
+                None => format!("No player with id {}", player_id),
+            },
+            Command::Ban { player_id, duration, reason } => {
+                let (addr, token) = match self.players.iter().find(|(_, player)| player.id == player_id) {
+                    Some((&addr, player)) => (addr, player.token.clone()),
+                    None => return format!("No player with id {}", player_id),
+                };
+                let until = match duration {
+                    Some(duration) => match SystemTime::now().checked_add(duration) {
+                        Some(until) => Some(until),
+                        None => return format!("A ban of {} minutes is too long", duration.as_secs() / 60),
+                    },
+                    None => None,
+                };
+
+                self.access.ban(Target::Ip(addr.ip()), reason.clone(), until);
+                if let Some(token) = token.clone() {
+                    self.access.ban(Target::Token(token), reason, until);
+                }
+
+                let kick_reason = self.access.check(addr.ip(), token.as_deref(), SystemTime::now())
+                    .err()
+                    .unwrap_or_default();
+                self.kick(player_id, &kick_reason);
+
+                match self.save_access_list() {
+                    Ok(()) => format!("Banned player {} ({})", player_id, addr.ip()),
+                    Err(e) => format!("Banned player {} ({}), but {}", player_id, addr.ip(), e),
+                }
+            },
+            Command::Unban(target) => {
+                let removed = self.access.unban(&target);
+                if removed == 0 {
+                    return format!("{} is not banned", target);
+                }
+
+                match self.save_access_list() {
+                    Ok(()) => format!("Unbanned {}", target),
+                    Err(e) => format!("Unbanned {}, but {}", target, e),
+                }
+            },
+            Command::Say(text) => {
+                self.broadcast(from_server::ChatMessage {
+                    player_id: None,
+                    channel: ChatChannel::All,
+                    text: format!("[server] {}", text),
+                });
+                "Message sent".into()
+            },
+            Command::Map(name) => {
+                let map = match TileMap::load(&self.config.maps_dir, &name) {
+                    Ok(map) => map,
+                    Err(e) => return format!("Cannot load map {}: {}", name, e),
+                };
+
+                let hash = map.hash();
+                info!(map = %name, hash, "Changing map");
+                self.config.map = name.clone();
+                self.world.set_map(map);
+                self.broadcast(from_server::MapChange { name: name.clone(), hash });
+                format!("Changed map to {}", name)
+            },
+            Command::SetTickRate(tick_rate) => {
+                if tick_rate == 0 || tick_rate > 1000 {
+                    return "Tick rate must be between 1 and 1000".into();
+                }
+                self.config.tick_rate = tick_rate;
+                format!("Tick rate set to {}", tick_rate)
+            },
+            Command::Shutdown(reason) => {
+                self.shutdown(&reason);
+                format!("Shutting down: {}", reason)
+            },
+            Command::Help => console::HELP.into(),
+        }
+    }
+
+    fn save_access_list(&mut self) -> Result<(), String> {
+        self.access.prune(SystemTime::now());
+        self.access.save()
+    }
+
+    /// Picks up changes of the access list file and kicks players who are no longer allowed.
+    pub fn reload_access_list(&mut self) {
+        match self.access.reload_if_changed() {
+            Ok(false) => return,
+            Ok(true) => info!("Reloaded access lists"),
+            Err(e) => {
+                warn!(error = %e, "Keeping previous access lists");
+                return;
+            }
+        }
+
+        let now = SystemTime::now();
+        let rejected = self.players.iter()
+            .filter_map(|(addr, player)| {
+                let result = match &player.token {
+                    Some(token) => self.access.check(addr.ip(), Some(token), now),
+                    None => self.access.check_address(addr.ip(), now),
+                };
+                result.err().map(|reason| (player.id, reason))
+            })
+            .collect::<Vec<_>>();
+
+        for (player_id, reason) in rejected {
+            info!(player_id, %reason, "Kicking player");
+            self.kick(player_id, &reason);
+        }
+    }
+
+    /// Takes a player out of the game. Its connection stays until it times out, the client can
+    /// join again by greeting.
+    fn kick(&mut self, player_id: usize, reason: &str) -> Option<SocketAddr> {
+        let addr = self.players.iter()
+            .find(|(_, player)| player.id == player_id)
+            .map(|(&addr, _)| addr)?;
+        self.remove_player(addr)?;
+
+        let reason = if reason.is_empty() { "Kicked by an administrator." } else { reason };
+        self.send(addr, from_server::Kicked { reason: reason.into() });
+
+        Some(addr)
+    }
+
+    /// Advances the world by one tick and sends what changed to the players.
+    ///
+    /// Players get their own status too, so their client can correct its prediction.
+    pub fn update(&mut self) {
+        self.world.update(self.tick_duration().as_secs_f64());
+
+        for status in self.world.changed_statuses() {
+            self.broadcast(status);
+        }
+    }
+
+    /// Dispatches the messages of a packet. Clients without a player, like the ones that left or
+    /// were kicked while their connection stays alive, are only heard when they greet again.
+    pub fn receive(&mut self, packet: Packet) {
+        if let Err(e) = self.protocol.receive(packet.payload()) {
+            warn!(addr = %packet.addr(), error = %e, "Dropping undecodable packet");
+            self.dropped_packets += 1;
+            return;
+        }
+
+        self.current_addr = Some(packet.addr());
+
+        let queue = Rc::clone(&self.queue);
+        queue.borrow_mut().poll(self);
+        self.current_addr = None;
+    }
+
+    fn send<T: 'static + Serialize>(&mut self, addr: SocketAddr, message: T) {
+        self.protocol.send_reliable_unordered(&self.sender, addr, message);
+    }
+
+    /// Sends `message` to every player in game, except `skip`.
+    fn broadcast_except<T: 'static + Serialize + Clone>(&mut self, skip: Option<SocketAddr>, message: T) {
+        let addrs = self.players.iter()
+            .filter(|&(&addr, player)| player.name.is_some() && Some(addr) != skip)
+            .map(|(&addr, _)| addr)
+            .collect::<Vec<_>>();
+
+        for addr in addrs {
+            self.send(addr, message.clone());
+        }
+    }
+
+    fn broadcast<T: 'static + Serialize + Clone>(&mut self, message: T) {
+        self.broadcast_except(None, message);
+    }
+
+    fn send_to_team<T: 'static + Serialize + Clone>(&mut self, team: usize, message: T) {
+        let addrs = self.players.iter()
+            .filter(|&(_, player)| player.name.is_some() && player.team == team)
+            .map(|(&addr, _)| addr)
+            .collect::<Vec<_>>();
+
+        for addr in addrs {
+            self.send(addr, message.clone());
+        }
+    }
+
+    fn smallest_team(&self) -> usize {
+        let mut sizes = [0; TEAM_COUNT];
+        for player in self.players.values() {
+            sizes[player.team] += 1;
+        }
+
+        (0..TEAM_COUNT).min_by_key(|&team| sizes[team]).unwrap_or(0)
+    }
+
+    fn current_player(&mut self) -> Option<(SocketAddr, &mut PlayerInfo)> {
+        let addr = self.current_addr?;
+        self.players.get_mut(&addr).map(|player| (addr, player))
+    }
+
+    fn receive_greeting(&mut self, greeting: Rc<from_client::Greeting>) {
+        let addr = match self.current_addr {
+            Some(addr) => addr,
+            None => return,
+        };
+        // A client greeting again joins again as a new player, its connection may have outlived
+        // its player when it left or was kicked.
+        if self.players.get(&addr).map_or(false, |player| player.name.is_some()) {
+            info!(%addr, "Client joins again");
+            self.remove_player(addr);
+        }
+        if !self.players.contains_key(&addr) {
+            self.connect(addr);
+        }
+        let player_id = match self.players.get(&addr) {
+            Some(player) => player.id,
+            None => return,
+        };
+
+        if let Err(reason) = self.access.check(addr.ip(), Some(&greeting.token), SystemTime::now()) {
+            info!(%addr, %reason, "Refusing client");
+            self.players.remove(&addr);
+            self.send(addr, from_server::Kicked { reason });
+            return;
+        }
+
+        let player = self.players.get_mut(&addr).unwrap();
+        let name = sanitize_player_name(&greeting.name, player.id);
+        info!(player_id = player.id, %name, "Player joined");
+        player.name = Some(name.clone());
+        player.token = Some(greeting.token.clone());
+
+        self.send(addr, from_server::GreetingResponse {
+            player_id,
+            server_name: self.config.name.clone(),
+            motd: self.config.motd.clone(),
+            map: self.config.map.clone(),
+            map_hash: self.world.map().hash(),
+            max_chat_length: self.config.chat.max_length,
+        });
+
+        let players = self.players.values()
+            .filter_map(|player| player.name.clone().map(|name| from_server::RosterEntry {
+                player_id: player.id,
+                name,
+            }))
+            .collect();
+
+        self.send(addr, from_server::Roster { players });
+        for status in self.world.statuses() {
+            self.send(addr, status);
+        }
+        self.broadcast_except(Some(addr), from_server::PlayerJoined { player_id, name });
+    }
+
+    fn receive_leave(&mut self, _: Rc<from_client::Leave>) {
+        let addr = match self.current_addr {
+            Some(addr) => addr,
+            None => return,
+        };
+        if let Some(player) = self.remove_player(addr) {
+            info!(%addr, player_id = player.id, "Client left");
+        }
+    }
+
+    fn receive_change_name(&mut self, message: Rc<from_client::ChangeName>) {
+        let player = match self.current_player() {
+            Some((_, player)) if player.name.is_some() => player,
+            _ => return,
+        };
+
+        let name = sanitize_player_name(&message.name, player.id);
+        if player.name.as_ref() == Some(&name) {
+            return;
+        }
+
+        info!(player_id = player.id, %name, "Player renamed");
+        player.name = Some(name.clone());
+        let player_id = player.id;
+
+        self.broadcast(from_server::PlayerRenamed { player_id, name });
+    }
+
+    fn receive_player_status(&mut self, status: Rc<both::PlayerStatus>) {
+        let player_id = match self.current_player() {
+            Some((_, player)) if player.name.is_some() => player.id,
+            _ => return,
+        };
+
+        let mut status = (*status).clone();
+        status.player_id = player_id;
+
+        self.world.report_status(&status);
+    }
+
+    fn receive_chat(&mut self, message: Rc<from_client::ChatSend>) {
+        let max_length = self.config.chat.max_length;
+        let (addr, player) = match self.current_player() {
+            Some((addr, player)) if player.name.is_some() => (addr, player),
+            _ => return,
+        };
+        let (player_id, team) = (player.id, player.team);
+
+        if !player.chat_limiter.try_acquire(Instant::now()) {
+            debug!(player_id, "Chat message rate limited");
+            self.send(addr, from_server::ChatMessage {
+                player_id: None,
+                channel: message.channel,
+                text: "You are sending messages too fast.".into(),
+            });
+            return;
+        }
+
+        let text = match chat::sanitize_chat_text(&message.text, max_length)
+            .and_then(|text| self.chat_filter.filter(&text))
+        {
+            Some(text) => text,
+            None => return,
+        };
+
+        info!(player_id, channel = ?message.channel, %text, "Chat message");
+        let chat_message = from_server::ChatMessage {
+            player_id: Some(player_id),
+            channel: message.channel,
+            text,
+        };
+        match message.channel {
+            ChatChannel::All => self.broadcast(chat_message),
+            ChatChannel::Team => self.send_to_team(team, chat_message),
+        }
+    }
+
+    fn receive_map_request(&mut self, message: Rc<from_client::MapRequest>) {
+        let max_packet_size = self.config.network.max_packet_size;
+        let (addr, player_id) = match self.current_player() {
+            Some((addr, player)) if player.name.is_some() => (addr, player.id),
+            _ => return,
+        };
+        if message.name != self.config.map {
+            return;
+        }
+
+        let source = self.world.map().source();
+        if source.contents.len() + MAP_DATA_OVERHEAD > max_packet_size {
+            warn!(player_id, map = %message.name, size = source.contents.len(), "Map is too large to send");
+            let reason = format!("Map {} is not installed and too large to download.", message.name);
+            self.kick(player_id, &reason);
+            return;
+        }
+
+        debug!(player_id, map = %message.name, "Sending map");
+        let data = from_server::MapData {
+            name: message.name.clone(),
+            file_name: source.file_name.clone(),
+            contents: source.contents.clone(),
+        };
+        self.send(addr, data);
+    }
+
+    fn receive_rcon_auth(&mut self, message: Rc<from_client::RconAuth>) {
+        let addr = match self.current_addr {
+            Some(addr) => addr,
+            None => return,
+        };
+        let password = self.config.rcon_password.clone();
+        let player = match self.players.get_mut(&addr) {
+            Some(player) => player,
+            None => return,
+        };
+
+        let now = Instant::now();
+        let output = match password {
+            None => "Remote console is disabled on this server.",
+            Some(_) if self.rcon_lockout.is_locked(addr.ip(), now) => "Too many failed attempts, try again later.",
+            Some(password) if constant_time_eq(password.as_bytes(), message.password.as_bytes()) => {
+                info!(player_id = player.id, %addr, "Remote console authenticated");
+                player.rcon_authenticated = true;
+                self.rcon_lockout.succeed(addr.ip());
+                "Authenticated."
+            },
+            Some(_) => {
+                warn!(player_id = player.id, %addr, "Remote console authentication failed");
+                self.rcon_lockout.fail(addr.ip(), now);
+                "Wrong password."
+            },
+        };
+
+        self.send(addr, from_server::RconResponse { output: output.into() });
+    }
+
+    fn receive_rcon_command(&mut self, message: Rc<from_client::RconCommand>) {
+        let addr = match self.current_player() {
+            Some((addr, player)) if player.rcon_authenticated => addr,
+            Some((addr, _)) => {
+                self.send(addr, from_server::RconResponse { output: "Not authenticated.".into() });
+                return;
+            },
+            None => return,
+        };
+
+        info!(%addr, command = %message.command, "Remote console command");
+        let output = match Command::parse(&message.command) {
+            Ok(command) => self.execute(command),
+            Err(e) => e,
+        };
+
+        if self.players.contains_key(&addr) {
+            self.send(addr, from_server::RconResponse { output });
+        }
+    }
+}
+
+impl RconLockout {
+    fn is_locked(&self, ip: IpAddr, now: Instant) -> bool {
+        matches!(
+            self.failures.get(&ip),
+            Some(&(count, last)) if count >= MAX_RCON_FAILURES && now.duration_since(last) < RCON_LOCKOUT
+        )
+    }
+
+    fn fail(&mut self, ip: IpAddr, now: Instant) {
+        // Failures older than the lockout are forgotten, which also keeps the map small.
+        self.failures.retain(|_, &mut (_, last)| now.duration_since(last) < RCON_LOCKOUT);
+
+        let failures = self.failures.entry(ip).or_insert((0, now));
+        failures.0 += 1;
+        failures.1 = now;
+    }
+
+    fn succeed(&mut self, ip: IpAddr) {
+        self.failures.remove(&ip);
+    }
+}
+
+/// Compares a secret with an attempt in time depending only on the attempt's length.
+fn constant_time_eq(secret: &[u8], attempt: &[u8]) -> bool {
+    let mut difference = secret.len() ^ attempt.len();
+    for (i, &byte) in attempt.iter().enumerate() {
+        difference |= (secret.get(i).copied().unwrap_or(0) ^ byte) as usize;
+    }
+
+    difference == 0
+}
+
+/// Strips control characters and surrounding whitespace and limits the length of a requested
+/// player name, falling back to a generic name if nothing is left.
+pub fn sanitize_player_name(name: &str, player_id: usize) -> String {
+    let name = name.chars()
+        .filter(|c| !c.is_control())
+        .collect::<String>();
+    let name = name.trim()
+        .chars()
+        .take(MAX_PLAYER_NAME_LENGTH)
+        .collect::<String>();
+
+    if name.is_empty() {
+        format!("Player {}", player_id)
+    } else {
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{constant_time_eq, sanitize_player_name, RconLockout, Server, MAX_RCON_FAILURES, RCON_LOCKOUT};
+    use crate::access::AccessList;
+    use crate::config::Config;
+    use serde::Serialize;
+    use zed_shared::map::TileMap;
+    use zed_shared::message::from_client;
+    use zed_shared::protocol::{register_messages, Protocol, SimpleProtocol};
+    use std::net::SocketAddr;
+    use std::time::{Duration, Instant};
+
+    /// Hands `message` to the server as if the client at `addr` sent it.
+    fn receive<T: 'static + Serialize>(server: &mut Server, addr: SocketAddr, message: T) {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let mut protocol = SimpleProtocol::new();
+        register_messages(&mut protocol);
+
+        protocol.send_reliable_unordered(&sender, addr, message);
+        server.receive(receiver.try_recv().unwrap());
+    }
+
+    fn greeting() -> from_client::Greeting {
+        from_client::Greeting {
+            name: "Zed".into(),
+            token: "token".into(),
+        }
+    }
+
+    #[test]
+    fn test_join_again() {
+        let (sender, _receiver) = crossbeam_channel::unbounded();
+        let access = AccessList::load("missing-access-list.toml").unwrap();
+        let mut server = Server::new(Config::default(), sender, access, TileMap::empty("test", 20, 20, 8));
+        let addr = "127.0.0.1:5000".parse().unwrap();
+
+        server.connect(addr);
+        assert_eq!(server.player_counts(), (0, 1));
+        receive(&mut server, addr, greeting());
+        assert_eq!(server.player_counts(), (1, 0));
+        receive(&mut server, addr, greeting());
+        assert_eq!(server.player_counts(), (1, 0), "Greeting again joins again");
+
+        let player_id = server.players[&addr].id;
+        assert_eq!(server.kick(player_id, ""), Some(addr));
+        assert_eq!(server.player_counts(), (0, 0));
+        receive(&mut server, addr, greeting());
+        assert_eq!(server.player_counts(), (1, 0), "Kicked clients join again without a new connection");
+
+        receive(&mut server, addr, from_client::Leave {});
+        assert_eq!(server.player_counts(), (0, 0));
+        receive(&mut server, addr, greeting());
+        assert_eq!(server.player_counts(), (1, 0), "Clients join again after leaving");
+    }
+
+    #[test]
+    fn test_sanitize_player_name() {
+        assert_eq!(sanitize_player_name("Zed", 0), "Zed");
+        assert_eq!(sanitize_player_name("  Zed \n", 0), "Zed");
+        assert_eq!(sanitize_player_name("Z\u{7}ed", 0), "Zed");
+        assert_eq!(sanitize_player_name("", 3), "Player 3");
+        assert_eq!(sanitize_player_name(" \t ", 4), "Player 4");
+        assert_eq!(sanitize_player_name("abcdefghijklmnopqrstuvwxyz", 0), "abcdefghijklmnop");
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+        assert!(!constant_time_eq(b"secret", b"sec"));
+        assert!(!constant_time_eq(b"secret", b""));
+    }
+
+    #[test]
+    fn test_rcon_lockout() {
+        let mut lockout = RconLockout::default();
+        let (ip, other) = ("10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap());
+        let now = Instant::now();
+
+        for _ in 0..MAX_RCON_FAILURES {
+            assert!(!lockout.is_locked(ip, now));
+            lockout.fail(ip, now);
+        }
+        assert!(lockout.is_locked(ip, now), "Failures are counted per address, not per connection");
+        assert!(!lockout.is_locked(other, now));
+        assert!(lockout.is_locked(ip, now + RCON_LOCKOUT - Duration::from_secs(1)));
+
+        let later = now + RCON_LOCKOUT;
+        assert!(!lockout.is_locked(ip, later), "Lockout ends");
+        lockout.fail(ip, later);
+        assert!(!lockout.is_locked(ip, later), "Old failures are forgotten");
+    }
+}
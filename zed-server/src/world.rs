@@ -0,0 +1,353 @@
+use legion::entity::Entity;
+use legion::query::{IntoQuery, Read, Write};
+use legion::world::World;
+
+use zed_shared::components::{Direction, Model, MovementInput, Player, Position, Velocity};
+use zed_shared::message::both::PlayerStatus;
+use zed_shared::collision;
+use zed_shared::map::TileMap;
+use zed_shared::simulation::{self, MAX_PREDICTION_ERROR, PLAYER_HITBOX, PLAYER_SPEED};
+
+use std::collections::HashMap;
+
+/// Fastest a player is moved toward its reported position, in units per second on top of its
+/// own movement. Reports always ahead of the simulation gain a player at most this much speed.
+pub const MAX_CORRECTION_SPEED: f64 = PLAYER_SPEED / 10.0;
+/// Free tiles of large maps are thinned out to about this many spawn points.
+const MAX_SPAWN_POINTS: usize = 256;
+
+/// Last status reported by the player's client.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Report {
+    /// Reported position, until the next tick consumes it.
+    position: Option<Position>,
+    /// Sent back with the player's status.
+    sequence: u32,
+}
+
+/// Authoritative game state. Players are simulated from the inputs their clients report,
+/// reported positions are only accepted when they agree with the simulation.
+///
+/// Players spawn where the server decides, away from the other players, and respawn when they
+/// end up outside the map.
+pub struct ServerWorld {
+    world: World,
+    map: TileMap,
+    /// Centers of the tiles a player fits on.
+    spawn_points: Vec<Position>,
+    players: HashMap<usize, Entity>,
+    /// Last state sent for every player, to only send changes.
+    sent: HashMap<usize, PlayerStatus>,
+}
+
+impl ServerWorld {
+    pub fn new(map: TileMap) -> Self {
+        Self {
+            world: World::new(),
+            spawn_points: spawn_points(&map),
+            map,
+            players: HashMap::new(),
+            sent: HashMap::new(),
+        }
+    }
+
+    /// Applies a status reported by a client, the first report spawns the player at a spawn point.
+    pub fn report_status(&mut self, status: &PlayerStatus) {
+        let entity = match self.players.get(&status.player_id) {
+            Some(&entity) => entity,
+            None => {
+                let position = self.spawn_position();
+                let entities = self.world.insert((), [(
+                    position,
+                    Direction::from_angle(status.angle),
+                    Velocity::default(),
+                    PLAYER_HITBOX,
+                    status.input,
+                    Model {
+                        name: "guy".into(),
+                        color: [status.r, status.g, status.b],
+                    },
+                    Player {
+                        id: Some(status.player_id),
+                        holster: status.holster,
+                    },
+                    Report {
+                        position: None,
+                        sequence: status.sequence,
+                    },
+                )].iter().cloned());
+
+                self.players.insert(status.player_id, entities[0]);
+                return;
+            }
+        };
+
+        if let Some(mut report) = self.world.get_component_mut::<Report>(entity) {
+            report.position = Some(Position { x: status.x, y: status.y });
+            report.sequence = status.sequence;
+        }
+        if let Some(mut input) = self.world.get_component_mut::<MovementInput>(entity) {
+            *input = status.input;
+        }
+        if let Some(mut direction) = self.world.get_component_mut::<Direction>(entity) {
+            direction.set_angle(status.angle);
+        }
+        if let Some(mut model) = self.world.get_component_mut::<Model>(entity) {
+            model.color = [status.r, status.g, status.b];
+        }
+        if let Some(mut player) = self.world.get_component_mut::<Player>(entity) {
+            player.holster = status.holster;
+        }
+    }
+
+    pub fn map(&self) -> &TileMap {
+        &self.map
+    }
+
+    /// Switches to another map, players stuck in its walls are pushed out right away
+    /// and players outside of it respawn.
+    pub fn set_map(&mut self, map: TileMap) {
+        self.spawn_points = spawn_points(&map);
+        self.map = map;
+        simulation::collision(&mut self.world, &self.map);
+        self.respawn_lost_players();
+    }
+
+    pub fn remove_player(&mut self, player_id: usize) {
+        if let Some(entity) = self.players.remove(&player_id) {
+            self.world.delete(entity);
+        }
+        self.sent.remove(&player_id);
+    }
+
+    /// Advances the simulation by `dt` seconds: movement, collision and then the game rules.
+    pub fn update(&mut self, dt: f64) {
+        simulation::movement(&mut self.world, dt);
+        accept_reports(&mut self.world, dt);
+        simulation::collision(&mut self.world, &self.map);
+        self.respawn_lost_players();
+    }
+
+    /// The spawn point farthest from every player, so players do not spawn on top of each other.
+    fn spawn_position(&mut self) -> Position {
+        let occupied = <(Read<Position>, Read<Player>)>::query()
+            .iter(&mut self.world)
+            .map(|(position, _)| *position)
+            .collect::<Vec<_>>();
+        let clearance = |spawn: &Position| occupied.iter()
+            .map(|position| (position.x - spawn.x).powi(2) + (position.y - spawn.y).powi(2))
+            .fold(f64::INFINITY, f64::min);
+
+        let mut best = self.spawn_points[0];
+        let mut best_clearance = clearance(&best);
+        for spawn in &self.spawn_points[1..] {
+            let spawn_clearance = clearance(spawn);
+            if spawn_clearance > best_clearance {
+                best = *spawn;
+                best_clearance = spawn_clearance;
+            }
+        }
+
+        best
+    }
+
+    /// Players outside the map, after switching to a smaller one or with a broken position, respawn.
+    fn respawn_lost_players(&mut self) {
+        let (width, height) = (self.map.pixel_width(), self.map.pixel_height());
+        // NaN positions count as outside.
+        let inside = |x: f64, y: f64| (0.0..=width).contains(&x) && (0.0..=height).contains(&y);
+
+        let lost = <(Read<Position>, Read<Player>)>::query()
+            .iter_entities(&mut self.world)
+            .filter(|(_, (position, _))| !inside(position.x, position.y))
+            .map(|(entity, _)| entity)
+            .collect::<Vec<_>>();
+
+        for entity in lost {
+            let spawn = self.spawn_position();
+            if let Some(mut position) = self.world.get_component_mut::<Position>(entity) {
+                *position = spawn;
+            }
+        }
+    }
+
+    /// Current state of every spawned player.
+    pub fn statuses(&mut self) -> Vec<PlayerStatus> {
+        let query = <(Read<Position>, Read<Direction>, Read<Model>, Read<Player>, Read<MovementInput>, Read<Report>)>::query();
+
+        query.iter(&mut self.world)
+            .filter_map(|(position, direction, model, player, input, report)| {
+                let [r, g, b] = model.color;
+                Some(PlayerStatus {
+                    player_id: player.id?,
+                    x: position.x,
+                    y: position.y,
+                    angle: direction.angle(),
+                    r,
+                    g,
+                    b,
+                    holster: player.holster,
+                    input: *input,
+                    sequence: report.sequence,
+                })
+            })
+            .collect()
+    }
+
+    /// States of the players that changed since the previous call, a new sequence number alone
+    /// is not a change.
+    pub fn changed_statuses(&mut self) -> Vec<PlayerStatus> {
+        let mut changed = self.statuses();
+        changed.retain(|status| match self.sent.get(&status.player_id) {
+            Some(sent) => PlayerStatus { sequence: status.sequence, ..sent.clone() } != *status,
+            None => true,
+        });
+
+        for status in &changed {
+            self.sent.insert(status.player_id, status.clone());
+        }
+
+        changed
+    }
+}
+
+/// Centers of the tiles of `map` a player fits on, or the middle of the map if there are none.
+fn spawn_points(map: &TileMap) -> Vec<Position> {
+    let mut free = Vec::new();
+    for y in 0..map.height {
+        for x in 0..map.width {
+            if map.is_solid(x as i64, y as i64) {
+                continue;
+            }
+
+            let center = Position {
+                x: (x as f64 + 0.5) * map.tile_width as f64,
+                y: (y as f64 + 0.5) * map.tile_height as f64,
+            };
+            if collision::resolve_tiles(map, center, PLAYER_HITBOX).1.is_empty() {
+                free.push(center);
+            }
+        }
+    }
+
+    if free.is_empty() {
+        return vec![Position { x: map.pixel_width() / 2.0, y: map.pixel_height() / 2.0 }];
+    }
+
+    let step = (free.len() + MAX_SPAWN_POINTS - 1) / MAX_SPAWN_POINTS;
+    free.into_iter().step_by(step).collect()
+}
+
+/// Moves players toward where their clients say they are when it is close to the simulated
+/// position, so small timing differences do not accumulate. Anything further is ignored.
+fn accept_reports(world: &mut World, dt: f64) {
+    let max_correction = MAX_CORRECTION_SPEED * dt;
+
+    for (mut position, mut report) in <(Write<Position>, Write<Report>)>::query().iter(world) {
+        if let Some(reported) = report.position.take() {
+            let (dx, dy) = (reported.x - position.x, reported.y - position.y);
+            let distance = (dx * dx + dy * dy).sqrt();
+            if distance.is_nan() || distance == 0.0 || distance > MAX_PREDICTION_ERROR {
+                continue;
+            }
+
+            let scale = distance.min(max_correction) / distance;
+            position.x += dx * scale;
+            position.y += dy * scale;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ServerWorld, MAX_CORRECTION_SPEED};
+    use zed_shared::components::MovementInput;
+    use zed_shared::message::both::PlayerStatus;
+    use zed_shared::map::TileMap;
+    use zed_shared::simulation::{MAX_PREDICTION_ERROR, PLAYER_SPEED};
+
+    fn status(x: f64, y: f64, right: bool) -> PlayerStatus {
+        PlayerStatus {
+            player_id: 1,
+            x,
+            y,
+            angle: 0.0,
+            r: 1,
+            g: 2,
+            b: 3,
+            holster: false,
+            input: MovementInput { right, ..MovementInput::default() },
+            sequence: 0,
+        }
+    }
+
+    #[test]
+    fn test_players_are_simulated_from_inputs() {
+        let mut world = ServerWorld::new(TileMap::empty("test", 20, 20, 8));
+
+        world.report_status(&status(100.0, 100.0, false));
+        let spawned = world.changed_statuses();
+        assert_eq!(spawned.len(), 1, "First report spawns the player");
+        let (x, y) = (spawned[0].x, spawned[0].y);
+        assert_ne!((x, y), (100.0, 100.0), "Players spawn where the server decides");
+        assert!(world.changed_statuses().is_empty(), "Unchanged players are not sent again");
+        world.report_status(&PlayerStatus { sequence: 7, ..status(x, y, false) });
+        world.update(0.0);
+        assert!(world.changed_statuses().is_empty(), "Sequence numbers alone are not changes");
+
+        world.report_status(&status(x, y, true));
+        world.update(0.5);
+        let moved = world.changed_statuses();
+        assert_eq!(moved[0].x, x + PLAYER_SPEED * 0.5, "Players move without position reports");
+        assert_eq!(moved[0].sequence, 0, "Statuses carry the sequence of the last report");
+
+        let x = x + PLAYER_SPEED * 0.5;
+        world.report_status(&status(x + MAX_PREDICTION_ERROR / 2.0, y, false));
+        world.update(1.0);
+        assert_eq!(world.changed_statuses()[0].x, x + MAX_PREDICTION_ERROR / 2.0, "Close reports are accepted");
+
+        world.report_status(&status(140.0, y, false));
+        world.update(0.0);
+        assert!(world.changed_statuses().is_empty(), "Teleports are ignored");
+
+        world.remove_player(1);
+        world.update(1.0);
+        assert!(world.changed_statuses().is_empty());
+    }
+
+    #[test]
+    fn test_spawns_and_bounds() {
+        let mut world = ServerWorld::new(TileMap::empty("test", 20, 20, 8));
+
+        world.report_status(&status(0.0, 0.0, false));
+        world.report_status(&PlayerStatus { player_id: 2, ..status(0.0, 0.0, false) });
+        let statuses = world.statuses();
+        let distance = ((statuses[0].x - statuses[1].x).powi(2) + (statuses[0].y - statuses[1].y).powi(2)).sqrt();
+        assert!(distance > 100.0, "Players spawn away from each other, {} apart", distance);
+
+        world.set_map(TileMap::empty("small", 5, 5, 8));
+        for status in world.statuses() {
+            assert!(status.x > 0.0 && status.x < 40.0 && status.y > 0.0 && status.y < 40.0, "Players outside the map respawn");
+        }
+    }
+
+    #[test]
+    fn test_reports_ahead_cannot_add_speed() {
+        let mut world = ServerWorld::new(TileMap::empty("test", 40, 20, 8));
+        let dt = 1.0 / 30.0;
+
+        world.report_status(&status(20.0, 20.0, true));
+        let start = world.statuses()[0].x;
+        for _ in 0..30 {
+            let x = world.statuses()[0].x;
+            world.report_status(&status(x + MAX_PREDICTION_ERROR, 20.0, true));
+            world.update(dt);
+        }
+
+        let travelled = world.statuses()[0].x - start;
+        assert!(
+            travelled <= PLAYER_SPEED + MAX_CORRECTION_SPEED + 1e-6,
+            "Moved {} in a second, reports ahead only add the correction speed", travelled
+        );
+    }
+}
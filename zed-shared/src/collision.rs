@@ -0,0 +1,390 @@
+//! Collision detection between circles and axis aligned boxes, and against the solid tiles of a map.
+//!
+//! Everything here is plain arithmetic on `Position`s, so it runs the same on the client and the server.
+
+use crate::components::{Position, Velocity};
+use crate::map::TileMap;
+
+use std::collections::HashMap;
+
+/// Shape of an entity, centered on its `Position`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HitBox {
+    Circle { radius: f64 },
+    Box { width: f64, height: f64 },
+}
+
+/// Axis aligned bounding box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min_x: f64,
+    pub min_y: f64,
+    pub max_x: f64,
+    pub max_y: f64,
+}
+
+/// How two overlapping shapes are separated: moving the first one `depth` along the
+/// unit normal `(normal_x, normal_y)` makes them touch.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Contact {
+    pub normal_x: f64,
+    pub normal_y: f64,
+    pub depth: f64,
+}
+
+/// Uniform grid bucketing boxes by the cells they cover, to only test shapes that are close.
+pub struct SpatialHash {
+    cell_size: f64,
+    cells: HashMap<(i64, i64), Vec<usize>>,
+}
+
+impl HitBox {
+    /// Half of the width and height of the shape's bounding box.
+    pub fn half_extents(&self) -> (f64, f64) {
+        match *self {
+            HitBox::Circle { radius } => (radius, radius),
+            HitBox::Box { width, height } => (width / 2.0, height / 2.0),
+        }
+    }
+}
+
+impl Aabb {
+    pub fn around(position: Position, hitbox: HitBox) -> Self {
+        let (half_width, half_height) = hitbox.half_extents();
+
+        Self {
+            min_x: position.x - half_width,
+            min_y: position.y - half_height,
+            max_x: position.x + half_width,
+            max_y: position.y + half_height,
+        }
+    }
+
+    /// Whether the boxes overlap, touching boxes do not.
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min_x < other.max_x && other.min_x < self.max_x && self.min_y < other.max_y && other.min_y < self.max_y
+    }
+}
+
+impl Contact {
+    /// The same contact seen from the second shape.
+    pub fn flipped(self) -> Self {
+        Self {
+            normal_x: -self.normal_x,
+            normal_y: -self.normal_y,
+            depth: self.depth,
+        }
+    }
+}
+
+impl SpatialHash {
+    pub fn new(cell_size: f64) -> Self {
+        Self {
+            cell_size,
+            cells: HashMap::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.clear();
+    }
+
+    pub fn insert(&mut self, id: usize, aabb: &Aabb) {
+        let (min, max) = (self.cell(aabb.min_x, aabb.min_y), self.cell(aabb.max_x, aabb.max_y));
+
+        for y in min.1..=max.1 {
+            for x in min.0..=max.0 {
+                self.cells.entry((x, y)).or_default().push(id);
+            }
+        }
+    }
+
+    /// Ids of the boxes sharing a cell with `aabb`, sorted and without duplicates.
+    pub fn query(&self, aabb: &Aabb) -> Vec<usize> {
+        let (min, max) = (self.cell(aabb.min_x, aabb.min_y), self.cell(aabb.max_x, aabb.max_y));
+        let mut ids = Vec::new();
+
+        for y in min.1..=max.1 {
+            for x in min.0..=max.0 {
+                if let Some(cell) = self.cells.get(&(x, y)) {
+                    ids.extend_from_slice(cell);
+                }
+            }
+        }
+
+        ids.sort();
+        ids.dedup();
+        ids
+    }
+
+    /// Pairs of ids sharing at least one cell, each pair once with the smaller id first, sorted.
+    pub fn pairs(&self) -> Vec<(usize, usize)> {
+        let mut pairs = Vec::new();
+
+        for ids in self.cells.values() {
+            for (i, &a) in ids.iter().enumerate() {
+                for &b in &ids[i + 1..] {
+                    if a != b {
+                        pairs.push((a.min(b), a.max(b)));
+                    }
+                }
+            }
+        }
+
+        pairs.sort();
+        pairs.dedup();
+        pairs
+    }
+
+    fn cell(&self, x: f64, y: f64) -> (i64, i64) {
+        ((x / self.cell_size).floor() as i64, (y / self.cell_size).floor() as i64)
+    }
+}
+
+pub fn circle_circle(a: Position, a_radius: f64, b: Position, b_radius: f64) -> Option<Contact> {
+    let (dx, dy) = (a.x - b.x, a.y - b.y);
+    let distance_squared = dx * dx + dy * dy;
+    let radii = a_radius + b_radius;
+
+    if distance_squared >= radii * radii {
+        return None;
+    }
+
+    let distance = distance_squared.sqrt();
+    if distance == 0.0 {
+        // Any direction separates concentric circles, pick a fixed one so both sides agree.
+        return Some(Contact { normal_x: 1.0, normal_y: 0.0, depth: radii });
+    }
+
+    Some(Contact {
+        normal_x: dx / distance,
+        normal_y: dy / distance,
+        depth: radii - distance,
+    })
+}
+
+pub fn circle_aabb(center: Position, radius: f64, aabb: &Aabb) -> Option<Contact> {
+    let dx = center.x - center.x.max(aabb.min_x).min(aabb.max_x);
+    let dy = center.y - center.y.max(aabb.min_y).min(aabb.max_y);
+    let distance_squared = dx * dx + dy * dy;
+
+    if distance_squared >= radius * radius {
+        return None;
+    }
+
+    if distance_squared > 0.0 {
+        let distance = distance_squared.sqrt();
+        return Some(Contact {
+            normal_x: dx / distance,
+            normal_y: dy / distance,
+            depth: radius - distance,
+        });
+    }
+
+    // The center is inside the box, leave through the closest side.
+    let exits = [
+        Contact { normal_x: -1.0, normal_y: 0.0, depth: center.x - aabb.min_x + radius },
+        Contact { normal_x: 1.0, normal_y: 0.0, depth: aabb.max_x - center.x + radius },
+        Contact { normal_x: 0.0, normal_y: -1.0, depth: center.y - aabb.min_y + radius },
+        Contact { normal_x: 0.0, normal_y: 1.0, depth: aabb.max_y - center.y + radius },
+    ];
+
+    Some(exits.iter().cloned().fold(exits[0], |closest, exit| if exit.depth < closest.depth { exit } else { closest }))
+}
+
+pub fn aabb_aabb(a: &Aabb, b: &Aabb) -> Option<Contact> {
+    if !a.intersects(b) {
+        return None;
+    }
+
+    let overlap_x = a.max_x.min(b.max_x) - a.min_x.max(b.min_x);
+    let overlap_y = a.max_y.min(b.max_y) - a.min_y.max(b.min_y);
+    let sign = |a_min: f64, a_max: f64, b_min: f64, b_max: f64| if a_min + a_max < b_min + b_max { -1.0 } else { 1.0 };
+
+    if overlap_x < overlap_y {
+        Some(Contact { normal_x: sign(a.min_x, a.max_x, b.min_x, b.max_x), normal_y: 0.0, depth: overlap_x })
+    } else {
+        Some(Contact { normal_x: 0.0, normal_y: sign(a.min_y, a.max_y, b.min_y, b.max_y), depth: overlap_y })
+    }
+}
+
+/// Separation of any two hit boxes, see `Contact`.
+pub fn contact(a: Position, a_hitbox: HitBox, b: Position, b_hitbox: HitBox) -> Option<Contact> {
+    match (a_hitbox, b_hitbox) {
+        (HitBox::Circle { radius: a_radius }, HitBox::Circle { radius: b_radius }) => circle_circle(a, a_radius, b, b_radius),
+        (HitBox::Circle { radius }, HitBox::Box { .. }) => circle_aabb(a, radius, &Aabb::around(b, b_hitbox)),
+        (HitBox::Box { .. }, HitBox::Circle { radius }) => circle_aabb(b, radius, &Aabb::around(a, a_hitbox)).map(Contact::flipped),
+        (HitBox::Box { .. }, HitBox::Box { .. }) => aabb_aabb(&Aabb::around(a, a_hitbox), &Aabb::around(b, b_hitbox)),
+    }
+}
+
+/// Moves a body out of the solid tiles of `map` it overlaps, everything outside the map is solid.
+/// Returns the new position and the contacts that were resolved, in order.
+pub fn resolve_tiles(map: &TileMap, position: Position, hitbox: HitBox) -> (Position, Vec<Contact>) {
+    let (tile_width, tile_height) = (map.tile_width as f64, map.tile_height as f64);
+    let (half_width, half_height) = hitbox.half_extents();
+    let mut position = position;
+    let mut contacts = Vec::new();
+
+    // Bodies far outside would be pushed from tile to tile, bring them back in one step.
+    let inside = Position {
+        x: position.x.max(half_width).min(map.pixel_width() - half_width),
+        y: position.y.max(half_height).min(map.pixel_height() - half_height),
+    };
+    if inside != position {
+        let (dx, dy) = (inside.x - position.x, inside.y - position.y);
+        let depth = (dx * dx + dy * dy).sqrt();
+        contacts.push(Contact { normal_x: dx / depth, normal_y: dy / depth, depth });
+        position = inside;
+    }
+
+    let aabb = Aabb::around(position, hitbox);
+    let (min_x, max_x) = ((aabb.min_x / tile_width).floor() as i64, (aabb.max_x / tile_width).floor() as i64);
+    let (min_y, max_y) = ((aabb.min_y / tile_height).floor() as i64, (aabb.max_y / tile_height).floor() as i64);
+
+    for tile_y in min_y..=max_y {
+        for tile_x in min_x..=max_x {
+            if !map.is_solid(tile_x, tile_y) {
+                continue;
+            }
+
+            let tile = Aabb {
+                min_x: tile_x as f64 * tile_width,
+                min_y: tile_y as f64 * tile_height,
+                max_x: (tile_x + 1) as f64 * tile_width,
+                max_y: (tile_y + 1) as f64 * tile_height,
+            };
+            let contact = match hitbox {
+                HitBox::Circle { radius } => circle_aabb(position, radius, &tile),
+                HitBox::Box { .. } => aabb_aabb(&Aabb::around(position, hitbox), &tile),
+            };
+
+            if let Some(contact) = contact {
+                position.x += contact.normal_x * contact.depth;
+                position.y += contact.normal_y * contact.depth;
+                contacts.push(contact);
+            }
+        }
+    }
+
+    (position, contacts)
+}
+
+/// Removes the part of `velocity` going into a surface with the given contact normal,
+/// what is left slides along the surface.
+pub fn slide(velocity: Velocity, contact: &Contact) -> Velocity {
+    let into = velocity.x * contact.normal_x + velocity.y * contact.normal_y;
+    if into >= 0.0 {
+        return velocity;
+    }
+
+    Velocity {
+        x: velocity.x - into * contact.normal_x,
+        y: velocity.y - into * contact.normal_y,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{aabb_aabb, circle_aabb, circle_circle, contact, resolve_tiles, slide, Aabb, Contact, HitBox, SpatialHash};
+    use crate::components::{Position, Velocity};
+    use crate::map::{MapSource, TileMap};
+
+    fn position(x: f64, y: f64) -> Position {
+        Position { x, y }
+    }
+
+    fn aabb(min_x: f64, min_y: f64, max_x: f64, max_y: f64) -> Aabb {
+        Aabb { min_x, min_y, max_x, max_y }
+    }
+
+    #[test]
+    fn test_circle_circle() {
+        assert_eq!(circle_circle(position(0.0, 0.0), 1.0, position(2.0, 0.0), 1.0), None, "Touching circles do not collide");
+        assert_eq!(
+            circle_circle(position(0.0, 0.0), 2.0, position(0.0, 3.0), 2.0),
+            Some(Contact { normal_x: 0.0, normal_y: -1.0, depth: 1.0 })
+        );
+        assert_eq!(circle_circle(position(1.0, 1.0), 1.0, position(1.0, 1.0), 1.0).map(|contact| contact.depth), Some(2.0));
+    }
+
+    #[test]
+    fn test_circle_aabb() {
+        let tile = aabb(0.0, 0.0, 8.0, 8.0);
+
+        assert_eq!(circle_aabb(position(10.0, 4.0), 2.0, &tile), None);
+        assert_eq!(circle_aabb(position(9.0, 4.0), 2.0, &tile), Some(Contact { normal_x: 1.0, normal_y: 0.0, depth: 1.0 }));
+        assert_eq!(circle_aabb(position(4.0, 1.0), 2.0, &tile), Some(Contact { normal_x: 0.0, normal_y: -1.0, depth: 3.0 }), "Centers inside leave through the closest side");
+
+        let corner = circle_aabb(position(9.0, 9.0), 2.0, &tile).unwrap();
+        assert!((corner.normal_x - corner.normal_y).abs() < 1e-12 && corner.normal_x > 0.0, "Corners push diagonally, got {:?}", corner);
+        assert!((corner.depth - (2.0 - 2f64.sqrt())).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_aabb_aabb() {
+        let a = aabb(0.0, 0.0, 4.0, 4.0);
+
+        assert_eq!(aabb_aabb(&a, &aabb(4.0, 0.0, 8.0, 4.0)), None, "Touching boxes do not collide");
+        assert_eq!(aabb_aabb(&a, &aabb(3.0, 1.0, 7.0, 5.0)), Some(Contact { normal_x: -1.0, normal_y: 0.0, depth: 1.0 }));
+        assert_eq!(aabb_aabb(&aabb(3.0, 1.0, 7.0, 5.0), &a), Some(Contact { normal_x: 1.0, normal_y: 0.0, depth: 1.0 }));
+        assert_eq!(aabb_aabb(&a, &aabb(0.0, 3.5, 4.0, 8.0)), Some(Contact { normal_x: 0.0, normal_y: -1.0, depth: 0.5 }));
+    }
+
+    #[test]
+    fn test_contact_between_shapes() {
+        let circle = HitBox::Circle { radius: 2.0 };
+        let square = HitBox::Box { width: 4.0, height: 4.0 };
+
+        let circle_first = contact(position(0.0, 0.0), circle, position(3.0, 0.0), square).unwrap();
+        let box_first = contact(position(3.0, 0.0), square, position(0.0, 0.0), circle).unwrap();
+        assert_eq!(circle_first, Contact { normal_x: -1.0, normal_y: 0.0, depth: 1.0 });
+        assert_eq!(box_first, Contact { normal_x: 1.0, normal_y: 0.0, depth: 1.0 }, "Normals point away from the second shape");
+    }
+
+    #[test]
+    fn test_spatial_hash() {
+        let mut hash = SpatialHash::new(10.0);
+        hash.insert(0, &aabb(1.0, 1.0, 3.0, 3.0));
+        hash.insert(1, &aabb(8.0, 8.0, 12.0, 12.0));
+        hash.insert(2, &aabb(31.0, 31.0, 33.0, 33.0));
+        hash.insert(3, &aabb(-5.0, -5.0, -1.0, -1.0));
+
+        assert_eq!(hash.pairs(), vec![(0, 1)], "Only boxes sharing cells are paired, once");
+        assert_eq!(hash.query(&aabb(9.0, 9.0, 25.0, 25.0)), vec![0, 1]);
+        assert_eq!(hash.query(&aabb(-3.0, -3.0, -2.0, -2.0)), vec![3], "Negative coordinates have their own cells");
+
+        hash.clear();
+        assert!(hash.pairs().is_empty());
+    }
+
+    #[test]
+    fn test_resolve_tiles_slides_along_walls() {
+        // A 4x3 map of 8 pixel tiles with a wall at (2, 1).
+        let map = TileMap::parse("test", MapSource {
+            file_name: "test.json".into(),
+            contents: r#"{
+                "width": 4, "height": 3, "tilewidth": 8, "tileheight": 8,
+                "layers": [{ "type": "tilelayer", "name": "collision", "data": [0,0,0,0, 0,0,1,0, 0,0,0,0] }],
+                "tilesets": [{ "firstgid": 1, "image": "tiles.png", "columns": 1, "tilecount": 1, "tilewidth": 8, "tileheight": 8 }]
+            }"#.into(),
+        }).unwrap();
+        let circle = HitBox::Circle { radius: 2.0 };
+
+        let (free, contacts) = resolve_tiles(&map, position(8.0, 12.0), circle);
+        assert_eq!((free, contacts.len()), (position(8.0, 12.0), 0));
+
+        let (pushed, contacts) = resolve_tiles(&map, position(15.0, 12.0), circle);
+        assert_eq!(pushed, position(14.0, 12.0));
+        let velocity = slide(Velocity { x: 3.0, y: 4.0 }, &contacts[0]);
+        assert_eq!(velocity, Velocity { x: 0.0, y: 4.0 }, "Movement along the wall is kept");
+        assert_eq!(slide(Velocity { x: -3.0, y: 4.0 }, &contacts[0]), Velocity { x: -3.0, y: 4.0 }, "Moving away is not affected");
+
+        let (boxed, _) = resolve_tiles(&map, position(17.0, 7.0), HitBox::Box { width: 4.0, height: 4.0 });
+        assert_eq!(boxed, position(17.0, 6.0), "Boxes are pushed along the axis of least overlap");
+
+        let (outside, contacts) = resolve_tiles(&map, position(-5.0, 30.0), circle);
+        assert_eq!(outside, position(2.0, 22.0), "Bodies stay inside the map");
+        assert_eq!(contacts.len(), 1);
+    }
+}
@@ -0,0 +1,62 @@
+//! Gameplay components shared by the client and the server worlds.
+
+use serde::{Serialize, Deserialize};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Position {
+    pub x: f64,
+    pub y: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Direction {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// Units per second.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Velocity {
+    pub x: f64,
+    pub y: f64,
+}
+
+/// Movement keys held by a player, sent along with the player's status.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct MovementInput {
+    pub up: bool,
+    pub down: bool,
+    pub left: bool,
+    pub right: bool,
+}
+
+/// What an entity looks like, renderers map `name` to their own resources.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Model {
+    pub name: String,
+    pub color: [u8; 3],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Player {
+    pub holster: bool,
+    pub id: Option<usize>,
+}
+
+impl Direction {
+    pub fn from_angle(angle: f64) -> Self {
+        Self {
+            x: angle.cos(),
+            y: angle.sin(),
+        }
+    }
+
+    pub fn angle(&self) -> f64 {
+        self.y.atan2(self.x)
+    }
+
+    pub fn set_angle(&mut self, angle: f64) {
+        self.x = angle.cos();
+        self.y = angle.sin();
+    }
+}
@@ -1,6 +1,11 @@
+pub mod collision;
+pub mod components;
+pub mod logging;
+pub mod map;
 pub mod mapping;
 pub mod message;
 pub mod protocol;
+pub mod simulation;
 
 #[cfg(test)]
 mod test {
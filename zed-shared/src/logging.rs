@@ -0,0 +1,96 @@
+use serde::{Serialize, Deserialize};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::{
+    fmt::{self, format::FmtSpan},
+    layer::SubscriberExt,
+    util::SubscriberInitExt,
+    EnvFilter,
+};
+
+use std::fs::OpenOptions;
+use std::path::PathBuf;
+
+/// Environment variable overriding `LogConfig::filter`.
+pub const FILTER_ENV: &str = "ZED_LOG";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    /// Filter directives, a default level optionally followed by per-module levels,
+    /// e.g. `info,zed_server::server=debug`.
+    pub filter: String,
+    /// Also append events as JSON lines to this file.
+    pub json_file: Option<PathBuf>,
+    /// Log how long spans were busy when they close. Frame spans (`tick`, `update`, `draw`)
+    /// are at trace level, enable them with e.g. `app=trace`.
+    pub span_timing: bool,
+}
+
+/// Keeps the JSON file writer alive, buffered events are flushed when it is dropped.
+pub struct LogGuard {
+    _file: Option<WorkerGuard>,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            filter: "info".into(),
+            json_file: None,
+            span_timing: false,
+        }
+    }
+}
+
+/// Installs the global subscriber: human readable output on stdout and, when configured,
+/// JSON lines in a file. Events of the `log` crate (e.g. from laminar) are forwarded too.
+pub fn init(config: &LogConfig) -> Result<LogGuard, String> {
+    let filter = match std::env::var(FILTER_ENV) {
+        Ok(filter) => EnvFilter::try_new(&filter).map_err(|e| format!("invalid {} \"{}\": {}", FILTER_ENV, filter, e))?,
+        Err(_) => EnvFilter::try_new(&config.filter).map_err(|e| format!("invalid log filter \"{}\": {}", config.filter, e))?,
+    };
+    let span_events = if config.span_timing { FmtSpan::CLOSE } else { FmtSpan::NONE };
+
+    let (json, guard) = match &config.json_file {
+        Some(path) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(|e| format!("cannot open log file {}: {}", path.display(), e))?;
+            let (writer, guard) = tracing_appender::non_blocking(file);
+            let layer = fmt::layer()
+                .json()
+                .with_span_events(span_events.clone())
+                .with_writer(writer);
+
+            (Some(layer), Some(guard))
+        },
+        None => (None, None),
+    };
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt::layer().with_span_events(span_events))
+        .with(json)
+        .try_init()
+        .map_err(|e| format!("cannot install logger: {}", e))?;
+
+    Ok(LogGuard { _file: guard })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LogConfig;
+
+    #[test]
+    fn test_parse_config() {
+        let config: LogConfig = toml::from_str(r#"
+            filter = "warn,zed_server=debug"
+            json_file = "server.log"
+        "#).unwrap();
+
+        assert_eq!(config.filter, "warn,zed_server=debug");
+        assert_eq!(config.json_file, Some("server.log".into()));
+        assert!(!config.span_timing, "Missing keys fall back to defaults");
+    }
+}
@@ -0,0 +1,553 @@
+//! Tile maps made with the Tiled editor, in its JSON (`.json`) or XML (`.tmx`) format.
+//!
+//! Only orthogonal maps with embedded tilesets and uncompressed tile data are supported.
+//! Tiles are solid when they are on a layer named `collision` (or with a `collision`
+//! property set to true), or when their tileset marks them with a `solid` property.
+
+use serde::Deserialize;
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Where the client and the server look for maps by default.
+pub const DEFAULT_MAPS_DIR: &str = "static/maps";
+
+/// Tiled stores flip flags in the highest bits of tile ids.
+const TILE_ID_MASK: u32 = 0x1fff_ffff;
+const COLLISION_LAYER: &str = "collision";
+/// Largest map accepted, maps come from servers and must not make clients allocate without bounds.
+pub const MAX_TILES: usize = 1 << 20;
+/// Largest tile width or height in pixels, of the map grid and of tilesets.
+pub const MAX_TILE_SIZE: u32 = 4096;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileMap {
+    pub name: String,
+    /// Size in tiles.
+    pub width: usize,
+    pub height: usize,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    pub layers: Vec<TileLayer>,
+    pub tilesets: Vec<Tileset>,
+    solid: Vec<bool>,
+    source: MapSource,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileLayer {
+    pub name: String,
+    pub visible: bool,
+    /// Every tile of a collision layer is solid.
+    pub collision: bool,
+    /// Row major global tile ids, 0 is an empty cell.
+    pub tiles: Vec<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tileset {
+    pub first_gid: u32,
+    /// Path of the tileset image, relative to the map file.
+    pub image: String,
+    pub columns: u32,
+    pub tile_count: u32,
+    pub tile_width: u32,
+    pub tile_height: u32,
+    solid_tiles: Vec<u32>,
+}
+
+/// The file a map was parsed from, servers send it to clients that do not have the map.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapSource {
+    pub file_name: String,
+    pub contents: String,
+}
+
+#[derive(Debug)]
+pub enum MapError {
+    Io(PathBuf, std::io::Error),
+    NotFound(String),
+    Parse(String),
+    Unsupported(String),
+}
+
+impl TileMap {
+    /// Loads map `name` from `dir`, trying `<name>.json` and then `<name>.tmx`.
+    pub fn load<P: AsRef<Path>>(dir: P, name: &str) -> Result<Self, MapError> {
+        if name.contains(|c| c == '/' || c == '\\') || name.starts_with('.') {
+            return Err(MapError::NotFound(name.into()));
+        }
+
+        for extension in &["json", "tmx"] {
+            let path = dir.as_ref().join(format!("{}.{}", name, extension));
+            if !path.exists() {
+                continue;
+            }
+
+            let contents = std::fs::read_to_string(&path)
+                .map_err(|e| MapError::Io(path.clone(), e))?;
+            return Self::parse(name, MapSource {
+                file_name: format!("{}.{}", name, extension),
+                contents,
+            });
+        }
+
+        Err(MapError::NotFound(name.into()))
+    }
+
+    /// Parses a map file, the format is picked from the file extension.
+    pub fn parse(name: &str, source: MapSource) -> Result<Self, MapError> {
+        let mut map = if source.file_name.ends_with(".json") {
+            parse_json(&source.contents)?
+        } else if source.file_name.ends_with(".tmx") {
+            parse_tmx(&source.contents)?
+        } else {
+            return Err(MapError::Unsupported(format!("map format of {}", source.file_name)));
+        };
+
+        map.name = name.into();
+        map.source = source;
+        map.validate()?;
+        map.solid = map.compute_solid();
+
+        Ok(map)
+    }
+
+    /// A map without tiles, only bounded by its edges.
+    pub fn empty(name: &str, width: usize, height: usize, tile_size: u32) -> Self {
+        Self {
+            name: name.into(),
+            width,
+            height,
+            tile_width: tile_size,
+            tile_height: tile_size,
+            layers: Vec::new(),
+            tilesets: Vec::new(),
+            solid: vec![false; width * height],
+            source: MapSource {
+                file_name: String::new(),
+                contents: String::new(),
+            },
+        }
+    }
+
+    pub fn source(&self) -> &MapSource {
+        &self.source
+    }
+
+    /// Identifies the map contents, clients compare it with their own copy of the map.
+    pub fn hash(&self) -> u64 {
+        fnv1a(self.source.contents.as_bytes())
+    }
+
+    pub fn pixel_width(&self) -> f64 {
+        self.width as f64 * self.tile_width as f64
+    }
+
+    pub fn pixel_height(&self) -> f64 {
+        self.height as f64 * self.tile_height as f64
+    }
+
+    /// Whether the tile at the given tile coordinates blocks movement, everything outside the map does.
+    pub fn is_solid(&self, x: i64, y: i64) -> bool {
+        if x < 0 || y < 0 || x >= self.width as i64 || y >= self.height as i64 {
+            return true;
+        }
+
+        self.solid[y as usize * self.width + x as usize]
+    }
+
+    /// Tileset of a global tile id and the position of the tile in the tileset image.
+    pub fn tile_source(&self, gid: u32) -> Option<(&Tileset, u32, u32)> {
+        let gid = gid & TILE_ID_MASK;
+        let tileset = self.tileset(gid)?;
+        let local = gid - tileset.first_gid;
+        let columns = tileset.columns.max(1);
+
+        Some((tileset, (local % columns) * tileset.tile_width, (local / columns) * tileset.tile_height))
+    }
+
+    fn tileset(&self, gid: u32) -> Option<&Tileset> {
+        if gid == 0 {
+            return None;
+        }
+
+        self.tilesets.iter()
+            .filter(|tileset| tileset.first_gid <= gid && gid - tileset.first_gid < tileset.tile_count)
+            .last()
+    }
+
+    fn validate(&self) -> Result<(), MapError> {
+        if self.width == 0 || self.height == 0 || self.tile_width == 0 || self.tile_height == 0 {
+            return Err(MapError::Parse("map and tile sizes must not be zero".into()));
+        }
+        // Renderers place tiles with i32 pixel coordinates.
+        if self.tile_width > MAX_TILE_SIZE || self.tile_height > MAX_TILE_SIZE
+            || self.pixel_width() > i32::MAX as f64 || self.pixel_height() > i32::MAX as f64 {
+            return Err(MapError::Parse("map is too large in pixels".into()));
+        }
+
+        let tiles = tile_count(self.width, self.height)?;
+        for layer in &self.layers {
+            if layer.tiles.len() != tiles {
+                return Err(MapError::Parse(format!(
+                    "layer \"{}\" has {} tiles, expected {}",
+                    layer.name, layer.tiles.len(), tiles
+                )));
+            }
+        }
+
+        for tileset in &self.tilesets {
+            // Tile positions in the tileset image are computed in u32 pixels.
+            let rows = tileset.tile_count / tileset.columns.max(1) + 1;
+            let fits = tileset.tile_width <= MAX_TILE_SIZE && tileset.tile_height <= MAX_TILE_SIZE
+                && tileset.first_gid.checked_add(tileset.tile_count).is_some()
+                && tileset.columns.checked_mul(tileset.tile_width).is_some()
+                && rows.checked_mul(tileset.tile_height).is_some();
+            if !fits {
+                return Err(MapError::Parse(format!("tileset \"{}\" is too large", tileset.image)));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn compute_solid(&self) -> Vec<bool> {
+        let mut solid = vec![false; self.width * self.height];
+
+        for layer in &self.layers {
+            for (cell, &gid) in solid.iter_mut().zip(&layer.tiles) {
+                let gid = gid & TILE_ID_MASK;
+                if gid == 0 {
+                    continue;
+                }
+
+                let solid_tile = self.tileset(gid)
+                    .map_or(false, |tileset| tileset.solid_tiles.contains(&(gid - tileset.first_gid)));
+                *cell |= layer.collision || solid_tile;
+            }
+        }
+
+        solid
+    }
+}
+
+/// Number of tiles of a `width` x `height` map, refusing maps larger than `MAX_TILES`.
+fn tile_count(width: usize, height: usize) -> Result<usize, MapError> {
+    width.checked_mul(height)
+        .filter(|&tiles| tiles <= MAX_TILES)
+        .ok_or_else(|| MapError::Parse(format!("map of {}x{} tiles is larger than {} tiles", width, height, MAX_TILES)))
+}
+
+/// 64 bit FNV-1a, stable across platforms and compiler versions unlike `DefaultHasher`.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[derive(Deserialize)]
+struct JsonMap {
+    width: usize,
+    height: usize,
+    #[serde(rename = "tilewidth")]
+    tile_width: u32,
+    #[serde(rename = "tileheight")]
+    tile_height: u32,
+    #[serde(default)]
+    orientation: Option<String>,
+    #[serde(default)]
+    layers: Vec<JsonLayer>,
+    #[serde(default)]
+    tilesets: Vec<JsonTileset>,
+}
+
+#[derive(Deserialize)]
+struct JsonLayer {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default = "default_visible")]
+    visible: bool,
+    #[serde(default)]
+    encoding: Option<String>,
+    #[serde(default)]
+    data: Option<serde_json::Value>,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+}
+
+#[derive(Deserialize)]
+struct JsonTileset {
+    #[serde(rename = "firstgid")]
+    first_gid: u32,
+    #[serde(default)]
+    source: Option<String>,
+    #[serde(default)]
+    image: String,
+    #[serde(default)]
+    columns: u32,
+    #[serde(default, rename = "tilecount")]
+    tile_count: u32,
+    #[serde(default, rename = "tilewidth")]
+    tile_width: u32,
+    #[serde(default, rename = "tileheight")]
+    tile_height: u32,
+    #[serde(default)]
+    tiles: Vec<JsonTile>,
+}
+
+#[derive(Deserialize)]
+struct JsonTile {
+    id: u32,
+    #[serde(default)]
+    properties: Vec<JsonProperty>,
+}
+
+#[derive(Deserialize)]
+struct JsonProperty {
+    name: String,
+    value: serde_json::Value,
+}
+
+fn default_visible() -> bool {
+    true
+}
+
+fn json_flag(properties: &[JsonProperty], name: &str) -> bool {
+    properties.iter().any(|property| property.name == name && property.value == serde_json::Value::Bool(true))
+}
+
+fn parse_json(contents: &str) -> Result<TileMap, MapError> {
+    let json: JsonMap = serde_json::from_str(contents).map_err(|e| MapError::Parse(e.to_string()))?;
+    if json.orientation.as_ref().map_or(false, |orientation| orientation != "orthogonal") {
+        return Err(MapError::Unsupported("maps that are not orthogonal".into()));
+    }
+
+    tile_count(json.width, json.height)?;
+    let mut map = TileMap::empty("", json.width, json.height, json.tile_width);
+    map.tile_height = json.tile_height;
+
+    for tileset in json.tilesets {
+        if let Some(source) = tileset.source {
+            return Err(MapError::Unsupported(format!("external tileset {}", source)));
+        }
+
+        map.tilesets.push(Tileset {
+            first_gid: tileset.first_gid,
+            image: tileset.image,
+            columns: tileset.columns,
+            tile_count: tileset.tile_count,
+            tile_width: tileset.tile_width,
+            tile_height: tileset.tile_height,
+            solid_tiles: tileset.tiles.iter()
+                .filter(|tile| json_flag(&tile.properties, "solid"))
+                .map(|tile| tile.id)
+                .collect(),
+        });
+    }
+
+    for layer in json.layers.into_iter().filter(|layer| layer.kind == "tilelayer") {
+        if layer.encoding.as_ref().map_or(false, |encoding| encoding != "csv") {
+            return Err(MapError::Unsupported(format!("encoded tile data in layer \"{}\"", layer.name)));
+        }
+
+        let tiles = match layer.data {
+            Some(data) => serde_json::from_value(data).map_err(|e| MapError::Parse(e.to_string()))?,
+            None => Vec::new(),
+        };
+        map.layers.push(TileLayer {
+            collision: layer.name.eq_ignore_ascii_case(COLLISION_LAYER) || json_flag(&layer.properties, COLLISION_LAYER),
+            name: layer.name,
+            visible: layer.visible,
+            tiles,
+        });
+    }
+
+    Ok(map)
+}
+
+fn parse_tmx(contents: &str) -> Result<TileMap, MapError> {
+    let document = roxmltree::Document::parse(contents).map_err(|e| MapError::Parse(e.to_string()))?;
+    let root = document.root_element();
+    if root.attribute("orientation").map_or(false, |orientation| orientation != "orthogonal") {
+        return Err(MapError::Unsupported("maps that are not orthogonal".into()));
+    }
+
+    let (width, height) = (attribute(root, "width")?, attribute(root, "height")?);
+    tile_count(width, height)?;
+    let mut map = TileMap::empty("", width, height, attribute(root, "tilewidth")?);
+    map.tile_height = attribute(root, "tileheight")?;
+
+    for node in root.children().filter(|node| node.is_element()) {
+        match node.tag_name().name() {
+            "tileset" => {
+                if let Some(source) = node.attribute("source") {
+                    return Err(MapError::Unsupported(format!("external tileset {}", source)));
+                }
+
+                let image = node.children()
+                    .find(|child| child.has_tag_name("image"))
+                    .and_then(|image| image.attribute("source"))
+                    .unwrap_or("");
+
+                map.tilesets.push(Tileset {
+                    first_gid: attribute(node, "firstgid")?,
+                    image: image.into(),
+                    columns: attribute(node, "columns")?,
+                    tile_count: attribute(node, "tilecount")?,
+                    tile_width: attribute(node, "tilewidth")?,
+                    tile_height: attribute(node, "tileheight")?,
+                    solid_tiles: node.children()
+                        .filter(|child| child.has_tag_name("tile") && tmx_flag(*child, "solid"))
+                        .map(|tile| attribute(tile, "id"))
+                        .collect::<Result<_, _>>()?,
+                });
+            },
+            "layer" => {
+                let name = node.attribute("name").unwrap_or("");
+                let data = node.children()
+                    .find(|child| child.has_tag_name("data"))
+                    .ok_or_else(|| MapError::Parse(format!("layer \"{}\" has no data", name)))?;
+                if data.attribute("encoding") != Some("csv") {
+                    return Err(MapError::Unsupported(format!("tile data of layer \"{}\" that is not CSV encoded", name)));
+                }
+
+                let tiles = data.text().unwrap_or("")
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|gid| !gid.is_empty())
+                    .map(|gid| gid.parse().map_err(|_| MapError::Parse(format!("invalid tile \"{}\"", gid))))
+                    .collect::<Result<_, _>>()?;
+                map.layers.push(TileLayer {
+                    name: name.into(),
+                    collision: name.eq_ignore_ascii_case(COLLISION_LAYER) || tmx_flag(node, COLLISION_LAYER),
+                    visible: node.attribute("visible") != Some("0"),
+                    tiles,
+                });
+            },
+            _ => (),
+        }
+    }
+
+    Ok(map)
+}
+
+fn attribute<T: std::str::FromStr>(node: roxmltree::Node, name: &str) -> Result<T, MapError> {
+    let value = node.attribute(name)
+        .ok_or_else(|| MapError::Parse(format!("<{}> has no {} attribute", node.tag_name().name(), name)))?;
+
+    value.parse().map_err(|_| MapError::Parse(format!("invalid {} \"{}\"", name, value)))
+}
+
+/// Whether the node has a `<properties>` child with the boolean property `name` set to true.
+fn tmx_flag(node: roxmltree::Node, name: &str) -> bool {
+    node.children()
+        .filter(|child| child.has_tag_name("properties"))
+        .flat_map(|properties| properties.children())
+        .any(|property| property.attribute("name") == Some(name) && property.attribute("value") == Some("true"))
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapError::Io(path, e) => write!(f, "cannot read {}: {}", path.display(), e),
+            MapError::NotFound(name) => write!(f, "map \"{}\" not found", name),
+            MapError::Parse(reason) => write!(f, "invalid map: {}", reason),
+            MapError::Unsupported(what) => write!(f, "unsupported map: {} are not supported", what),
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
+
+#[cfg(test)]
+mod tests {
+    use super::{MapSource, TileMap};
+
+    const JSON: &str = r#"{
+        "width": 3, "height": 2, "tilewidth": 8, "tileheight": 8, "orientation": "orthogonal",
+        "layers": [
+            { "type": "tilelayer", "name": "ground", "data": [1, 1, 1, 1, 3, 1] },
+            { "type": "tilelayer", "name": "walls", "data": [0, 2, 0, 0, 0, 0],
+              "properties": [{ "name": "collision", "type": "bool", "value": true }] },
+            { "type": "objectgroup", "name": "spawns", "objects": [] }
+        ],
+        "tilesets": [{
+            "firstgid": 1, "name": "tiles", "image": "../tiles.png", "columns": 2, "tilecount": 4,
+            "tilewidth": 8, "tileheight": 8,
+            "tiles": [{ "id": 2, "properties": [{ "name": "solid", "type": "bool", "value": true }] }]
+        }]
+    }"#;
+
+    const TMX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <map version="1.2" orientation="orthogonal" width="3" height="2" tilewidth="8" tileheight="8">
+         <tileset firstgid="1" name="tiles" tilewidth="8" tileheight="8" tilecount="4" columns="2">
+          <image source="../tiles.png" width="16" height="16"/>
+          <tile id="2"><properties><property name="solid" type="bool" value="true"/></properties></tile>
+         </tileset>
+         <layer name="ground" width="3" height="2"><data encoding="csv">
+        1,1,1,
+        1,3,1
+        </data></layer>
+         <layer name="walls" width="3" height="2">
+          <properties><property name="collision" type="bool" value="true"/></properties>
+          <data encoding="csv">0,2,0,0,0,0</data>
+         </layer>
+        </map>"#;
+
+    fn parse(file_name: &str, contents: &str) -> TileMap {
+        TileMap::parse("test", MapSource { file_name: file_name.into(), contents: contents.into() }).unwrap()
+    }
+
+    #[test]
+    fn test_json_and_tmx_are_equivalent() {
+        let json = parse("test.json", JSON);
+        let tmx = parse("test.tmx", TMX);
+
+        assert_eq!(json.layers, tmx.layers);
+        assert_eq!(json.tilesets, tmx.tilesets);
+        assert_eq!((json.width, json.height, json.pixel_width(), json.pixel_height()), (3, 2, 24.0, 16.0));
+        assert_eq!(json.layers.len(), 2, "Object layers are skipped");
+    }
+
+    #[test]
+    fn test_solid_tiles() {
+        let map = parse("test.json", JSON);
+
+        assert!(map.is_solid(1, 0), "Tiles on the collision layer are solid");
+        assert!(map.is_solid(1, 1), "Tiles marked solid in the tileset are solid");
+        assert!(!map.is_solid(0, 0));
+        assert!(map.is_solid(-1, 0) && map.is_solid(3, 1) && map.is_solid(0, 2), "Outside the map is solid");
+    }
+
+    #[test]
+    fn test_tile_source() {
+        let map = parse("test.json", JSON);
+
+        assert_eq!(map.tile_source(0), None);
+        assert_eq!(map.tile_source(1).map(|(_, x, y)| (x, y)), Some((0, 0)));
+        assert_eq!(map.tile_source(4).map(|(_, x, y)| (x, y)), Some((8, 8)));
+        assert_eq!(map.tile_source(0x8000_0002).map(|(_, x, y)| (x, y)), Some((8, 0)), "Flip flags are ignored");
+        assert_eq!(map.tile_source(5), None);
+    }
+
+    #[test]
+    fn test_invalid_maps() {
+        let parse = |file_name: &str, contents: &str| {
+            TileMap::parse("test", MapSource { file_name: file_name.into(), contents: contents.into() })
+        };
+
+        assert!(parse("test.json", "{}").is_err());
+        assert!(parse("test.json", &JSON.replace("[1, 1, 1, 1, 3, 1]", "[1, 1]")).is_err(), "Layer size must match");
+        assert!(parse("test.tmx", &TMX.replace("encoding=\"csv\"", "encoding=\"base64\"")).is_err());
+        assert!(parse("test.txt", JSON).is_err());
+        assert!(parse("test.json", &JSON.replace("\"width\": 3", "\"width\": 4294967296")).is_err(), "Huge maps are refused");
+        assert!(parse("test.json", &JSON.replace("\"firstgid\": 1", "\"firstgid\": 4294967295")).is_err(), "Tile ids overflow");
+        assert!(parse("test.json", &JSON.replace("\"columns\": 2", "\"columns\": 4294967295")).is_err(), "Tileset image overflows");
+        assert!(parse("test.json", &JSON.replace("\"tilewidth\": 8,", "\"tilewidth\": 4294967295,")).is_err(), "Pixel size overflows");
+        assert!(parse("test.tmx", &TMX.replace("width=\"3\"", "width=\"18446744073709551615\"")).is_err(), "Tile count overflows");
+        assert_ne!(parse("test.json", JSON).unwrap().hash(), parse("test.json", &JSON.replace("8", "16")).unwrap().hash());
+    }
+}
@@ -1,9 +1,54 @@
+use serde::{Serialize, Deserialize};
+
+/// Longest player name accepted by the server, in characters.
+pub const MAX_PLAYER_NAME_LENGTH: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ChatChannel {
+    All,
+    Team,
+}
+
 pub mod from_client {
     use serde::{Serialize, Deserialize};
 
     #[derive(Serialize, Deserialize)]
     pub struct Greeting {
         pub name: String,
+        /// Random token identifying the player across sessions, used by server ban lists.
+        pub token: String,
+    }
+
+    /// Sent when the player leaves the server, so it does not wait for the connection to time out.
+    #[derive(Serialize, Deserialize)]
+    pub struct Leave {}
+
+    #[derive(Serialize, Deserialize)]
+    pub struct ChangeName {
+        pub name: String,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub struct ChatSend {
+        pub channel: super::ChatChannel,
+        pub text: String,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    pub struct RconAuth {
+        pub password: String,
+    }
+
+    /// Server console command, only accepted after a successful `RconAuth`.
+    #[derive(Serialize, Deserialize)]
+    pub struct RconCommand {
+        pub command: String,
+    }
+
+    /// Asks for the current map when the client does not have it or its copy differs.
+    #[derive(Serialize, Deserialize)]
+    pub struct MapRequest {
+        pub name: String,
     }
 }
 
@@ -12,14 +57,89 @@ pub mod from_server {
 
     #[derive(Serialize, Deserialize)]
     pub struct GreetingResponse {
-        pub player_id: usize
+        pub player_id: usize,
+        pub server_name: String,
+        pub motd: String,
+        pub map: String,
+        /// `TileMap::hash` of the map, clients with a different copy request it with `MapRequest`.
+        pub map_hash: u64,
+        /// Longest chat message the server relays, in characters.
+        pub max_chat_length: usize,
+    }
+
+    #[derive(Clone, Serialize, Deserialize)]
+    pub struct RosterEntry {
+        pub player_id: usize,
+        pub name: String,
+    }
+
+    /// Full list of players in game, sent to a client right after its greeting.
+    #[derive(Clone, Serialize, Deserialize)]
+    pub struct Roster {
+        pub players: Vec<RosterEntry>,
+    }
+
+    #[derive(Clone, Serialize, Deserialize)]
+    pub struct PlayerJoined {
+        pub player_id: usize,
+        pub name: String,
+    }
+
+    #[derive(Clone, Serialize, Deserialize)]
+    pub struct PlayerLeft {
+        pub player_id: usize,
+    }
+
+    #[derive(Clone, Serialize, Deserialize)]
+    pub struct PlayerRenamed {
+        pub player_id: usize,
+        pub name: String,
+    }
+
+    #[derive(Clone, Serialize, Deserialize)]
+    pub struct RconResponse {
+        pub output: String,
+    }
+
+    #[derive(Clone, Serialize, Deserialize)]
+    pub struct Kicked {
+        pub reason: String,
+    }
+
+    /// Sent to every client right before the server stops.
+    #[derive(Clone, Serialize, Deserialize)]
+    pub struct ServerShutdown {
+        pub reason: String,
+    }
+
+    #[derive(Clone, Serialize, Deserialize)]
+    pub struct MapChange {
+        pub name: String,
+        pub hash: u64,
+    }
+
+    /// Map file sent in response to a `MapRequest`.
+    #[derive(Clone, Serialize, Deserialize)]
+    pub struct MapData {
+        pub name: String,
+        pub file_name: String,
+        pub contents: String,
+    }
+
+    /// Chat line relayed by the server, `player_id` is `None` for messages from the server itself.
+    #[derive(Clone, Serialize, Deserialize)]
+    pub struct ChatMessage {
+        pub player_id: Option<usize>,
+        pub channel: super::ChatChannel,
+        pub text: String,
     }
 }
 
 pub mod both {
     use serde::{Serialize, Deserialize};
+    use crate::components::MovementInput;
 
-    #[derive(Serialize, Deserialize)]
+    #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
     pub struct PlayerStatus {
         pub player_id: usize,
         pub x: f64,
@@ -29,5 +149,10 @@ pub mod both {
         pub g: u8,
         pub b: u8,
         pub holster: bool,
+        /// Movement keys held when the status was sent.
+        pub input: MovementInput,
+        /// Counts the statuses a client sends. The server sends back the last one it applied,
+        /// so the client compares its position with what it predicted for that status.
+        pub sequence: u32,
     }
-}
\ No newline at end of file
+}
@@ -5,6 +5,7 @@ use serde::{
 
 use std::any::TypeId;
 use std::collections::HashMap;
+use std::fmt;
 use std::io::{Cursor, Read};
 use std::net::SocketAddr;
 use std::rc::Rc;
@@ -18,7 +19,22 @@ use mockall::automock;
 pub trait Protocol {
     fn register<T: 'static + DeserializeOwned>(&mut self);
     fn send_reliable_unordered<S: Sender<Packet>, T: 'static + Serialize>(&mut self, sender: &S, addr: SocketAddr, value: T);
-    fn receive(&mut self, raw: &[u8]);
+    fn receive(&mut self, raw: &[u8]) -> Result<(), DecodeError>;
+}
+
+#[derive(Debug)]
+pub enum DecodeError {
+    UnknownMessage(usize),
+    Malformed(bincode::Error),
+}
+
+/// Traffic of a single message type since the protocol was created.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MessageStats {
+    pub sent_packets: u64,
+    pub sent_bytes: u64,
+    pub received_packets: u64,
+    pub received_bytes: u64,
 }
 
 #[cfg_attr(test, automock)]
@@ -26,12 +42,15 @@ pub trait Sender<T: Send + 'static> {
     fn send(&self, msg: T) -> Result<(), Box<dyn std::error::Error>>;
 }
 
-type Decoder = Box<dyn Fn(&mut Dispatcher, &mut dyn Read)>;
+type Decoder = Box<dyn Fn(&mut Dispatcher, &mut dyn Read) -> bincode::Result<()>>;
 
 pub struct SimpleProtocol {
     dispatcher: Dispatcher,
     decoders: Vec<Decoder>,
     message_ids: HashMap<TypeId, usize>,
+    message_names: Vec<&'static str>,
+    stats: Vec<MessageStats>,
+    decode_errors: u64,
 }
 
 impl SimpleProtocol {
@@ -40,12 +59,15 @@ impl SimpleProtocol {
             dispatcher: Dispatcher::new(),
             decoders: Vec::new(),
             message_ids: HashMap::new(),
+            message_names: Vec::new(),
+            stats: Vec::new(),
+            decode_errors: 0,
         }
     }
 
-    fn prepare_send_buffer<T: 'static + Serialize>(&self, message: T) -> Vec<u8> {
+    fn prepare_send_buffer<T: 'static + Serialize>(&self, id: usize, message: T) -> Vec<u8> {
         let mut buffer = Vec::new();
-        bincode::serialize_into(&mut buffer, &self.message_ids.get(&TypeId::of::<T>()).unwrap()).unwrap();
+        bincode::serialize_into(&mut buffer, &id).unwrap();
         bincode::serialize_into(&mut buffer, &message).unwrap();
 
         buffer
@@ -54,6 +76,16 @@ impl SimpleProtocol {
     pub fn dispatcher_mut(&mut self) -> &mut Dispatcher {
         &mut self.dispatcher
     }
+
+    /// Traffic per registered message type, keyed by the type name without its module path.
+    pub fn message_stats(&self) -> impl Iterator<Item = (&'static str, &MessageStats)> {
+        self.message_names.iter().cloned().zip(self.stats.iter())
+    }
+
+    /// Number of received packets that could not be decoded.
+    pub fn decode_errors(&self) -> u64 {
+        self.decode_errors
+    }
 }
 
 impl Protocol for SimpleProtocol {
@@ -61,26 +93,56 @@ impl Protocol for SimpleProtocol {
         self.dispatcher.register::<T>();
 
         let decoder = |dispatcher: &mut Dispatcher, read: &mut dyn Read| {
-            let message: Rc<T> = Rc::new(bincode::deserialize_from(read).unwrap());
+            let message: Rc<T> = Rc::new(bincode::deserialize_from(read)?);
             dispatcher.dispatch(message);
+            Ok(())
         };
         let id = self.decoders.len();
+        let name = std::any::type_name::<T>();
 
         self.message_ids.insert(TypeId::of::<T>(), id);
+        self.message_names.push(name.rsplit("::").next().unwrap_or(name));
+        self.stats.push(MessageStats::default());
         self.decoders.push(Box::new(decoder));
     }
 
-    fn receive(&mut self, bytes: &[u8]) {
-        let mut bytes = Cursor::new(bytes);
+    fn receive(&mut self, raw: &[u8]) -> Result<(), DecodeError> {
+        let mut bytes = Cursor::new(raw);
+
+        let result = bincode::deserialize_from(&mut bytes)
+            .map_err(DecodeError::Malformed)
+            .and_then(|discriminant: usize| match self.decoders.get(discriminant) {
+                Some(decoder) => decoder(&mut self.dispatcher, &mut bytes)
+                    .map(|_| discriminant)
+                    .map_err(DecodeError::Malformed),
+                None => Err(DecodeError::UnknownMessage(discriminant)),
+            });
 
-        let discriminant: usize = bincode::deserialize_from(&mut bytes).unwrap();
-        (self.decoders[discriminant])(&mut self.dispatcher, &mut bytes);
+        match result {
+            Ok(id) => {
+                let stats = &mut self.stats[id];
+                stats.received_packets += 1;
+                stats.received_bytes += raw.len() as u64;
+                Ok(())
+            },
+            Err(e) => {
+                self.decode_errors += 1;
+                Err(e)
+            },
+        }
     }
 
     fn send_reliable_unordered<S: Sender<Packet>, T: 'static + Serialize>(&mut self, sender: &S, addr: SocketAddr, message: T)
     {
+        let id = *self.message_ids.get(&TypeId::of::<T>()).unwrap();
+        let buffer = self.prepare_send_buffer(id, message);
+
+        let stats = &mut self.stats[id];
+        stats.sent_packets += 1;
+        stats.sent_bytes += buffer.len() as u64;
+
         sender.send(
-            Packet::reliable_unordered(addr, self.prepare_send_buffer(message))
+            Packet::reliable_unordered(addr, buffer)
         ).unwrap();
     }
 }
@@ -95,8 +157,35 @@ pub fn register_messages(protocol: &mut impl Protocol) {
     protocol.register::<from_client::Greeting>();
     protocol.register::<from_server::GreetingResponse>();
     protocol.register::<both::PlayerStatus>();
+    protocol.register::<from_client::ChangeName>();
+    protocol.register::<from_server::Roster>();
+    protocol.register::<from_server::PlayerJoined>();
+    protocol.register::<from_server::PlayerLeft>();
+    protocol.register::<from_server::PlayerRenamed>();
+    protocol.register::<from_client::ChatSend>();
+    protocol.register::<from_server::ChatMessage>();
+    protocol.register::<from_client::RconAuth>();
+    protocol.register::<from_client::RconCommand>();
+    protocol.register::<from_server::RconResponse>();
+    protocol.register::<from_server::Kicked>();
+    protocol.register::<from_server::MapChange>();
+    protocol.register::<from_server::ServerShutdown>();
+    protocol.register::<from_client::MapRequest>();
+    protocol.register::<from_server::MapData>();
+    protocol.register::<from_client::Leave>();
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::UnknownMessage(id) => write!(f, "unknown message id {}", id),
+            DecodeError::Malformed(e) => write!(f, "malformed message: {}", e),
+        }
+    }
 }
 
+impl std::error::Error for DecodeError {}
+
 impl<T: Send+'static> Sender<T> for crossbeam_channel::Sender<T> {
     fn send(&self, data: T) -> Result<(), Box<dyn std::error::Error>> {
         self.send(data)?;
@@ -107,7 +196,7 @@ impl<T: Send+'static> Sender<T> for crossbeam_channel::Sender<T> {
 
 #[cfg(test)]
 mod tests {
-    use super::{MockSender, SimpleProtocol, Protocol, Packet};
+    use super::{MockSender, SimpleProtocol, Protocol, Packet, DecodeError, MessageStats};
     use std::net::{SocketAddrV4, Ipv4Addr};
     use serde::{Serialize, Deserialize};
 
@@ -131,6 +220,31 @@ mod tests {
 
         protocol.send_reliable_unordered(&mut sender, SocketAddrV4::new(Ipv4Addr::LOCALHOST, 1600).into(), Msg { a: 42 });
 
+        let (name, stats) = protocol.message_stats().next().unwrap();
+        assert_eq!(name, "Msg");
+        assert_eq!(stats, &MessageStats { sent_packets: 1, sent_bytes: 12, ..MessageStats::default() });
+    }
+
+    #[test]
+    fn test_receive_invalid_packets() {
+        let mut protocol = SimpleProtocol::new();
+        protocol.register::<Msg>();
+
+        match protocol.receive(&[]) {
+            Err(DecodeError::Malformed(_)) => (),
+            other => panic!("Expected a malformed message, got {:?}", other),
+        }
+        match protocol.receive(&7usize.to_le_bytes()) {
+            Err(DecodeError::UnknownMessage(7)) => (),
+            other => panic!("Expected an unknown message, got {:?}", other),
+        }
+        match protocol.receive(&0usize.to_le_bytes()) {
+            Err(DecodeError::Malformed(_)) => (),
+            other => panic!("Expected a truncated message, got {:?}", other),
+        }
+
+        assert_eq!(protocol.decode_errors(), 3);
+        assert_eq!(protocol.message_stats().next().unwrap().1, &MessageStats::default());
     }
 
     #[test]
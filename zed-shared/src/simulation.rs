@@ -0,0 +1,327 @@
+//! Simulation steps run by both the client and the server on their legion worlds.
+
+use legion::query::{IntoQuery, Read, Write};
+use legion::world::World;
+
+use crate::collision::{self, Aabb, HitBox, SpatialHash};
+use crate::components::{MovementInput, Position, Velocity};
+use crate::map::TileMap;
+
+use std::collections::VecDeque;
+
+/// Player movement speed, in units per second.
+pub const PLAYER_SPEED: f64 = 50.0;
+/// Radius of the player's body.
+pub const PLAYER_RADIUS: f64 = 6.0;
+pub const PLAYER_HITBOX: HitBox = HitBox::Circle { radius: PLAYER_RADIUS };
+/// Cell size of the collision broadphase, a little more than a player.
+const BROADPHASE_CELL_SIZE: f64 = 16.0;
+/// Largest distance between a client's prediction and the server's simulation. The server moves
+/// players toward reports closer than this, clients move to the server's position when further.
+pub const MAX_PREDICTION_ERROR: f64 = 4.0;
+/// Predictions kept for statuses the server has not answered yet, a few seconds of statuses.
+const MAX_PREDICTION_HISTORY: usize = 256;
+
+/// Kinematic state of a moving player.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MovementState {
+    pub position: Position,
+    pub velocity: Velocity,
+}
+
+/// Advances a player by `dt` seconds while `input` is held.
+///
+/// Only uses basic arithmetic and `sqrt`, which IEEE 754 requires to be correctly rounded,
+/// so the client prediction and the server reach bit-identical results for the same inputs.
+pub fn simulate_movement(state: MovementState, input: MovementInput, dt: f64) -> MovementState {
+    let mut dx: f64 = 0.0;
+    let mut dy: f64 = 0.0;
+
+    if input.left {
+        dx -= 1.0;
+    }
+    if input.right {
+        dx += 1.0;
+    }
+    if input.up {
+        dy -= 1.0;
+    }
+    if input.down {
+        dy += 1.0;
+    }
+
+    // Diagonal movement is as fast as movement along an axis.
+    let len = (dx * dx + dy * dy).sqrt();
+    if len > 0.0 {
+        dx /= len;
+        dy /= len;
+    }
+
+    let velocity = Velocity {
+        x: dx * PLAYER_SPEED,
+        y: dy * PLAYER_SPEED,
+    };
+
+    MovementState {
+        position: Position {
+            x: state.position.x + velocity.x * dt,
+            y: state.position.y + velocity.y * dt,
+        },
+        velocity,
+    }
+}
+
+/// Positions the client predicted for the statuses it sent, by sequence number.
+///
+/// The server answers a status after a round trip, so its position is compared with the
+/// prediction made for the same status instead of the current one.
+#[derive(Clone, Debug, Default)]
+pub struct PredictionHistory {
+    sequence: u32,
+    positions: VecDeque<(u32, Position)>,
+}
+
+impl PredictionHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Remembers `position` as the prediction sent with the next status and returns its sequence number.
+    pub fn record(&mut self, position: Position) -> u32 {
+        self.sequence = self.sequence.wrapping_add(1);
+        if self.positions.len() == MAX_PREDICTION_HISTORY {
+            self.positions.pop_front();
+        }
+        self.positions.push_back((self.sequence, position));
+
+        self.sequence
+    }
+
+    /// Compares the server's `position` after the status `sequence` with what was predicted for it.
+    ///
+    /// Returns how far the prediction has to move when it is off by more than `MAX_PREDICTION_ERROR`,
+    /// the predictions made since move along. Answers to unknown or older statuses are ignored.
+    pub fn reconcile(&mut self, sequence: u32, position: Position) -> Option<(f64, f64)> {
+        let index = self.positions.iter().position(|&(recorded, _)| recorded == sequence)?;
+        let (_, predicted) = self.positions[index];
+        self.positions.drain(..=index);
+
+        let (dx, dy) = (position.x - predicted.x, position.y - predicted.y);
+        if (dx * dx + dy * dy).sqrt() <= MAX_PREDICTION_ERROR {
+            return None;
+        }
+
+        for (_, predicted) in &mut self.positions {
+            predicted.x += dx;
+            predicted.y += dy;
+        }
+
+        Some((dx, dy))
+    }
+
+    /// Forgets the predictions, e.g. when leaving a server.
+    pub fn clear(&mut self) {
+        self.positions.clear();
+    }
+}
+
+/// Moves every entity driven by a `MovementInput` by `dt` seconds.
+pub fn movement(world: &mut World, dt: f64) {
+    let query = <(Write<Position>, Write<Velocity>, Read<MovementInput>)>::query();
+    for (mut position, mut velocity, input) in query.iter(world) {
+        let state = simulate_movement(MovementState { position: *position, velocity: *velocity }, *input, dt);
+        *position = state.position;
+        *velocity = state.velocity;
+    }
+}
+
+/// Pushes overlapping bodies apart, then out of the walls of `map`.
+///
+/// Only entities with a `Velocity` are moved, the others are obstacles. Players have one on the
+/// client and on the server alike, so both resolve contacts between players the same way. Two moving bodies share the separation. Velocities lose the part going into what
+/// they hit, so bodies slide along walls and each other.
+pub fn collision(world: &mut World, map: &TileMap) {
+    let mut bodies = <(Read<Position>, Read<HitBox>)>::query()
+        .iter_entities(world)
+        .map(|(entity, (position, hitbox))| (entity, *position, *hitbox))
+        .collect::<Vec<_>>();
+    let mut velocities = bodies.iter()
+        .map(|(entity, _, _)| world.get_component::<Velocity>(*entity).map(|velocity| *velocity))
+        .collect::<Vec<_>>();
+
+    let mut broadphase = SpatialHash::new(BROADPHASE_CELL_SIZE);
+    for (i, (_, position, hitbox)) in bodies.iter().enumerate() {
+        broadphase.insert(i, &Aabb::around(*position, *hitbox));
+    }
+
+    for (a, b) in broadphase.pairs() {
+        let contact = match collision::contact(bodies[a].1, bodies[a].2, bodies[b].1, bodies[b].2) {
+            Some(contact) => contact,
+            None => continue,
+        };
+        let (a_share, b_share) = match (velocities[a].is_some(), velocities[b].is_some()) {
+            (true, true) => (0.5, 0.5),
+            (true, false) => (1.0, 0.0),
+            (false, true) => (0.0, 1.0),
+            (false, false) => continue,
+        };
+
+        bodies[a].1.x += contact.normal_x * contact.depth * a_share;
+        bodies[a].1.y += contact.normal_y * contact.depth * a_share;
+        bodies[b].1.x -= contact.normal_x * contact.depth * b_share;
+        bodies[b].1.y -= contact.normal_y * contact.depth * b_share;
+        if let Some(velocity) = &mut velocities[a] {
+            *velocity = collision::slide(*velocity, &contact);
+        }
+        if let Some(velocity) = &mut velocities[b] {
+            *velocity = collision::slide(*velocity, &contact.flipped());
+        }
+    }
+
+    for ((entity, position, hitbox), velocity) in bodies.into_iter().zip(velocities) {
+        let mut velocity = match velocity {
+            Some(velocity) => velocity,
+            None => continue,
+        };
+
+        let (position, contacts) = collision::resolve_tiles(map, position, hitbox);
+        for contact in &contacts {
+            velocity = collision::slide(velocity, contact);
+        }
+
+        if let Some(mut current) = world.get_component_mut::<Position>(entity) {
+            *current = position;
+        }
+        if let Some(mut current) = world.get_component_mut::<Velocity>(entity) {
+            *current = velocity;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        collision, movement, simulate_movement, MovementState, PredictionHistory,
+        MAX_PREDICTION_ERROR, PLAYER_HITBOX, PLAYER_RADIUS, PLAYER_SPEED,
+    };
+    use crate::components::{MovementInput, Position, Velocity};
+    use crate::map::TileMap;
+    use legion::world::World;
+
+    fn input(up: bool, down: bool, left: bool, right: bool) -> MovementInput {
+        MovementInput { up, down, left, right }
+    }
+
+    /// Runs a fixed pseudo random input sequence and returns the bits of the final state.
+    fn run_inputs() -> [u64; 4] {
+        let mut state = MovementState {
+            position: Position { x: 16.0, y: 16.0 },
+            velocity: Velocity::default(),
+        };
+        let mut seed: u32 = 12345;
+
+        for _ in 0..10_000 {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            let keys = seed >> 16;
+            let input = input(keys & 1 != 0, keys & 2 != 0, keys & 4 != 0, keys & 8 != 0);
+            state = simulate_movement(state, input, 1.0 / 60.0);
+        }
+
+        [state.position.x.to_bits(), state.position.y.to_bits(), state.velocity.x.to_bits(), state.velocity.y.to_bits()]
+    }
+
+    #[test]
+    fn test_simulate_movement_is_deterministic() {
+        let first = run_inputs();
+        for _ in 0..3 {
+            assert_eq!(run_inputs(), first);
+        }
+    }
+
+    #[test]
+    fn test_simulate_movement_speed() {
+        let state = MovementState {
+            position: Position { x: 0.0, y: 0.0 },
+            velocity: Velocity::default(),
+        };
+
+        let right = simulate_movement(state, input(false, false, false, true), 0.5);
+        assert_eq!(right.position, Position { x: PLAYER_SPEED * 0.5, y: 0.0 });
+        assert_eq!(right.velocity, Velocity { x: PLAYER_SPEED, y: 0.0 });
+
+        let diagonal = simulate_movement(state, input(true, false, false, true), 0.5);
+        let speed = (diagonal.velocity.x.powi(2) + diagonal.velocity.y.powi(2)).sqrt();
+        assert!((speed - PLAYER_SPEED).abs() < 1e-9, "Diagonal speed is normalized, got {}", speed);
+        assert_eq!(diagonal.velocity.x, -diagonal.velocity.y);
+
+        let opposite = simulate_movement(state, input(true, true, true, true), 0.5);
+        assert_eq!(opposite.position, state.position, "Opposite keys cancel out");
+        assert_eq!(opposite.velocity, Velocity::default());
+    }
+
+    #[test]
+    fn test_movement_and_collision() {
+        let mut world = World::new();
+        let entities = world.insert((), vec![
+            (Position { x: 50.0, y: 50.0 }, Velocity::default(), input(true, false, false, false), PLAYER_HITBOX),
+            (Position { x: 150.0, y: 50.0 }, Velocity::default(), input(false, false, false, true), PLAYER_HITBOX),
+        ]).to_vec();
+        let map = TileMap::empty("test", 20, 20, 8);
+
+        movement(&mut world, 0.5);
+        collision(&mut world, &map);
+
+        assert_eq!(*world.get_component::<Position>(entities[0]).unwrap(), Position { x: 50.0, y: 50.0 - PLAYER_SPEED * 0.5 });
+        assert_eq!(*world.get_component::<Velocity>(entities[0]).unwrap(), Velocity { x: 0.0, y: -PLAYER_SPEED });
+
+        assert_eq!(
+            *world.get_component::<Position>(entities[1]).unwrap(),
+            Position { x: map.pixel_width() - PLAYER_RADIUS, y: 50.0 },
+            "Entities stop at the map bounds"
+        );
+        assert_eq!(*world.get_component::<Velocity>(entities[1]).unwrap(), Velocity { x: 0.0, y: 0.0 });
+    }
+
+    #[test]
+    fn test_players_push_each_other() {
+        let mut world = World::new();
+        let map = TileMap::empty("test", 20, 20, 8);
+        let players = world.insert((), vec![
+            (Position { x: 50.0, y: 50.0 }, Velocity { x: PLAYER_SPEED, y: 0.0 }, PLAYER_HITBOX),
+            (Position { x: 58.0, y: 50.0 }, Velocity::default(), PLAYER_HITBOX),
+        ]).to_vec();
+        let obstacle = world.insert((), vec![(Position { x: 100.0, y: 50.0 }, PLAYER_HITBOX)]).to_vec()[0];
+        let blocked = world.insert((), vec![
+            (Position { x: 92.0, y: 50.0 }, Velocity::default(), PLAYER_HITBOX),
+        ]).to_vec()[0];
+
+        collision(&mut world, &map);
+
+        let x = |entity| world.get_component::<Position>(entity).unwrap().x;
+        assert_eq!((x(players[0]), x(players[1])), (48.0, 60.0), "Moving bodies share the separation");
+        assert_eq!(*world.get_component::<Velocity>(players[0]).unwrap(), Velocity::default(), "Velocity into the other body is removed");
+        assert_eq!((x(blocked), x(obstacle)), (88.0, 100.0), "Bodies without velocity do not move");
+    }
+
+    #[test]
+    fn test_prediction_history() {
+        let mut history = PredictionHistory::new();
+        let first = history.record(Position { x: 10.0, y: 10.0 });
+        let second = history.record(Position { x: 20.0, y: 10.0 });
+        let third = history.record(Position { x: 30.0, y: 10.0 });
+
+        let close = Position { x: 10.0 + MAX_PREDICTION_ERROR, y: 10.0 };
+        assert_eq!(history.reconcile(first, close), None, "Errors the server absorbs are left to it");
+
+        assert_eq!(history.reconcile(second, Position { x: 10.0, y: 10.0 }), Some((-10.0, 0.0)));
+        assert_eq!(history.reconcile(first, Position { x: 0.0, y: 0.0 }), None, "Older answers are ignored");
+        assert_eq!(
+            history.reconcile(third, Position { x: 20.0, y: 10.0 }), None,
+            "Later predictions move along with the correction"
+        );
+
+        history.record(Position { x: 0.0, y: 0.0 });
+        history.clear();
+        assert_eq!(history.reconcile(third + 1, Position { x: 100.0, y: 100.0 }), None);
+    }
+}